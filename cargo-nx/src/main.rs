@@ -0,0 +1,266 @@
+//! `cargo nx build [--release] [--manifest-path <path>] [cargo build options]`
+//!
+//! Builds a package for the Switch with Xargo, against a sysroot built from
+//! nx-std, then packages each of its binaries as an NRO or NSP next to the
+//! linked ELF. Everything about the package is configured in its Cargo.toml,
+//! see the `metadata` module.
+//!
+//! `cargo nx run [--address <ip>] [--retries <n>] [--server] [build options] [-- <args>...]`
+//!
+//! Builds and packages an NRO the same way, then sends it to the homebrew
+//! menu's netloader with nxlink, which launches it with `args`. The device is
+//! found by broadcasting unless `--address` is given. With `--server`, the
+//! app's stdout and stderr are shown once it redirects them, see
+//! `nx::nxlink`.
+//!
+//! Needs devkitPro with libnx (`$DEVKITPRO`) and Xargo. The target spec is
+//! generated from the devkitPro install, so there is nothing to set up by
+//! hand.
+
+extern crate elf2nx;
+extern crate nx;
+extern crate nxlink;
+extern crate serde_json;
+
+mod metadata;
+mod package;
+mod target;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+use serde_json::Value;
+
+use metadata::{Format, Metadata};
+use nxlink::StdioServer;
+use target::DevkitPro;
+
+const USAGE: &str = "usage: cargo nx build [--release] [--manifest-path <path>] [cargo build options]\n       \
+                     cargo nx run [--address <ip>] [--retries <n>] [--server] [build options] [-- <args>...]";
+
+fn other(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+fn cargo() -> Command {
+    Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+}
+
+/// Runs a Cargo command that prints JSON.
+fn json_output(command: &mut Command) -> io::Result<Value> {
+    let output = command.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(other(format!("{:?} failed", command)));
+    }
+    serde_json::from_slice(&output.stdout).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// The binaries Xargo reports building for the package with ID `id`, by
+/// name, echoing the compiler's messages as it goes.
+fn xargo_build(command: &mut Command, id: &str) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut child = command.stdout(Stdio::piped()).spawn()
+        .map_err(|err| other(format!("couldn't run xargo, is it installed? ({})", err)))?;
+    let mut binaries = Vec::new();
+    for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+        let message: Value = match serde_json::from_str(&line?) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+        match message["reason"].as_str() {
+            Some("compiler-message") => {
+                if let Some(rendered) = message["message"]["rendered"].as_str() {
+                    eprint!("{}", rendered);
+                }
+            }
+            Some("compiler-artifact") if message["package_id"] == id => {
+                let is_bin = message["target"]["kind"].as_array()
+                    .map_or(false, |kinds| kinds.iter().any(|kind| kind == "bin"));
+                if let (true, Some(name), Some(path)) = (is_bin, message["target"]["name"].as_str(),
+                                                         message["filenames"][0].as_str()) {
+                    binaries.push((name.to_owned(), PathBuf::from(path)));
+                }
+            }
+            _ => {}
+        }
+    }
+    if !child.wait()?.success() {
+        return Err(other("build failed".to_owned()));
+    }
+    Ok(binaries)
+}
+
+/// Builds and packages the package, returning its format and the packaged
+/// files.
+fn build(args: &[String]) -> io::Result<(Format, Vec<PathBuf>)> {
+    let mut manifest = None;
+    let mut cargo_args = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--manifest-path" {
+            manifest = Some(PathBuf::from(iter.next().ok_or_else(|| other(USAGE.to_owned()))?));
+        } else if arg.starts_with("--manifest-path=") {
+            manifest = Some(PathBuf::from(&arg["--manifest-path=".len()..]));
+        } else if arg == "--target" || arg.starts_with("--target=") || arg.starts_with("--message-format") {
+            return Err(other(format!("cargo nx sets {} itself", arg)));
+        } else {
+            cargo_args.push(arg);
+        }
+    }
+    let manifest = match manifest {
+        Some(manifest) => manifest,
+        None => {
+            let location = json_output(cargo().arg("locate-project"))?;
+            PathBuf::from(location["root"].as_str().ok_or_else(|| other("cargo locate-project printed no root".to_owned()))?)
+        }
+    };
+
+    let cargo_metadata = json_output(cargo()
+        .args(&["metadata", "--no-deps", "--format-version", "1", "--manifest-path"])
+        .arg(&manifest))?;
+    let package = cargo_metadata["packages"].as_array()
+        .and_then(|packages| packages.iter().find(|package| {
+            package["manifest_path"].as_str().map_or(false, |path| same_file(Path::new(path), &manifest))
+        }))
+        .ok_or_else(|| other(format!("{} is not a package; run cargo nx in a package, not a virtual workspace",
+                                     manifest.display())))?;
+    let metadata = Metadata::from_package(package)?;
+    let target_dir = PathBuf::from(cargo_metadata["target_directory"].as_str()
+        .ok_or_else(|| other("cargo metadata printed no target directory".to_owned()))?);
+    let manifest_dir = manifest.parent().unwrap_or_else(|| Path::new("."));
+
+    let dkp = DevkitPro::find()?;
+    let spec_dir = target_dir.join("nx");
+    target::write_spec(&dkp, &spec_dir)?;
+    // A package can bring its own Xargo.toml to customize the sysroot.
+    // Otherwise Xargo is run from a stub project holding the default one, so
+    // nothing is written into the package.
+    let xargo_dir = if manifest_dir.join("Xargo.toml").exists() {
+        manifest_dir.to_owned()
+    } else {
+        let dir = spec_dir.join("xargo");
+        target::write_xargo_project(&dir)?;
+        dir
+    };
+
+    let binaries = xargo_build(Command::new("xargo")
+        .current_dir(&xargo_dir)
+        .args(&["build", "--target", target::TRIPLE, "--message-format=json", "--manifest-path"])
+        .arg(&manifest)
+        .args(&cargo_args)
+        .env("RUST_TARGET_PATH", &spec_dir)
+        .env("XARGO_RUST_SRC", target::nx_std().join("src")),
+        package["id"].as_str().unwrap_or(""))?;
+    if binaries.is_empty() {
+        return Err(other("the package has no binaries to package".to_owned()));
+    }
+
+    let extension = match metadata.format {
+        Format::Nro => "nro",
+        Format::Nsp => "nsp",
+    };
+    let mut packaged = Vec::new();
+    for (name, elf) in binaries {
+        let contents = package::package(&fs::read(&elf)?, &metadata, &dkp)
+            .map_err(|err| io::Error::new(err.kind(), format!("couldn't package {}: {}", name, err)))?;
+        let path = elf.with_extension(extension);
+        fs::write(&path, contents)?;
+        eprintln!("{:>12} {}", "Packaged", path.display());
+        packaged.push(path);
+    }
+    Ok((metadata.format, packaged))
+}
+
+/// Builds, packages and sends the NRO to the homebrew menu with nxlink.
+fn run(args: &[String]) -> io::Result<()> {
+    let mut address = None;
+    let mut retries = 10;
+    let mut server = false;
+    let mut build_args = Vec::new();
+    let mut app_args = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            app_args.extend(iter.cloned());
+            break;
+        }
+        let (key, inline_value) = match arg.find('=') {
+            Some(eq) if arg.starts_with("--") => (&arg[..eq], Some(&arg[eq + 1..])),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || inline_value.or_else(|| iter.next().map(|value| value.as_str()))
+            .ok_or_else(|| other(USAGE.to_owned()));
+        match key {
+            "--address" => address = Some(value()?.parse::<IpAddr>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid address"))?),
+            "--retries" => retries = value()?.parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid retry count"))?,
+            "--server" => server = true,
+            _ => build_args.push(arg.clone()),
+        }
+    }
+
+    let (format, packaged) = build(&build_args)?;
+    if format != Format::Nro {
+        return Err(other("only NROs can be sent with nxlink; set `format = \"nro\"` to run the package".to_owned()));
+    }
+    let nro_path = match packaged.len() {
+        1 => &packaged[0],
+        _ => return Err(other("the package has several binaries; pick the one to run with --bin".to_owned())),
+    };
+    let nro = fs::read(nro_path)?;
+    let name = nro_path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| other(format!("{} has no usable file name", nro_path.display())))?
+        .to_owned();
+
+    let address = match address {
+        Some(address) => address,
+        None => {
+            let address = nxlink::discover(retries)?;
+            eprintln!("{:>12} a device at {}", "Found", address);
+            address
+        }
+    };
+    // Listen before sending, so the app can't connect before we do.
+    let stdio = if server { Some(StdioServer::new()?) } else { None };
+    if server {
+        app_args.push(nxlink::STDIO_ARG.to_owned());
+    }
+    eprintln!("{:>12} {} to {}", "Sending", name, address);
+    nxlink::send_to(SocketAddr::new(address, nxlink::SERVER_PORT), &name, &nro, &app_args)?;
+    if let Some(stdio) = stdio {
+        stdio.serve(&mut io::stdout())?;
+    }
+    Ok(())
+}
+
+fn dispatch(args: &[String]) -> io::Result<()> {
+    match args.split_first() {
+        Some((command, rest)) if command == "build" => build(rest).map(|_| ()),
+        Some((command, rest)) if command == "run" => run(rest),
+        _ => Err(other(USAGE.to_owned())),
+    }
+}
+
+fn main() {
+    // Cargo runs subcommands as `cargo-nx nx <args>`.
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map_or(false, |arg| arg == "nx") {
+        args.remove(0);
+    }
+    if let Err(err) = dispatch(&args) {
+        eprintln!("cargo nx: {}", err);
+        process::exit(1);
+    }
+}
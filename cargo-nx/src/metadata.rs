@@ -0,0 +1,177 @@
+//! The `[package.metadata.nx]` table of a package's Cargo.toml, read from
+//! `cargo metadata`'s output:
+//!
+//! ```toml
+//! [package.metadata.nx]
+//! format = "nro"        # or "nsp"
+//! title = "My App"      # defaults to the package name
+//! author = "Me"         # defaults to the first of the package's authors
+//! version = "1.0.0"     # defaults to the package version
+//! icon = "icon.jpg"     # defaults to libnx's default icon
+//! romfs = "romfs"       # a directory to embed as the NRO's RomFS
+//! title-id = "0100000000000123"  # required for NSPs
+//! npdm = "main.npdm"    # used instead of the NSP's generated NPDM
+//! ```
+//!
+//! Paths are relative to the directory of the package's Cargo.toml.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A homebrew application, launched from the homebrew menu.
+    Nro,
+    /// An ExeFS holding `main` and `main.npdm`, like sysmodules are
+    /// installed as.
+    Nsp,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Metadata {
+    pub format: Format,
+    pub title: String,
+    pub author: String,
+    pub version: String,
+    pub icon: Option<PathBuf>,
+    pub romfs: Option<PathBuf>,
+    pub title_id: Option<u64>,
+    pub npdm: Option<PathBuf>,
+}
+
+const KEYS: &[&str] = &["format", "title", "author", "version", "icon", "romfs", "title-id", "npdm"];
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn string<'a>(table: &'a Value, key: &str) -> io::Result<Option<&'a str>> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value.as_str()
+            .map(Some)
+            .ok_or_else(|| invalid(format!("package.metadata.nx.{} must be a string", key))),
+    }
+}
+
+impl Metadata {
+    /// Reads the metadata of `package`, an element of the `packages` array
+    /// `cargo metadata` prints.
+    pub fn from_package(package: &Value) -> io::Result<Self> {
+        let field = |key: &str| package[key].as_str()
+            .ok_or_else(|| invalid(format!("cargo metadata is missing the package {}", key)));
+        let name = field("name")?;
+        let package_version = field("version")?;
+        let manifest_dir = Path::new(field("manifest_path")?).parent().unwrap_or_else(|| Path::new(""));
+
+        let table = &package["metadata"]["nx"];
+        if let Some(table) = table.as_object() {
+            if let Some(key) = table.keys().find(|key| !KEYS.contains(&key.as_str())) {
+                return Err(invalid(format!("unknown key package.metadata.nx.{}", key)));
+            }
+        } else if !table.is_null() {
+            return Err(invalid("package.metadata.nx must be a table".to_owned()));
+        }
+
+        let format = match string(table, "format")? {
+            None | Some("nro") => Format::Nro,
+            Some("nsp") => Format::Nsp,
+            Some(other) => return Err(invalid(format!("unknown package format `{}`, expected nro or nsp", other))),
+        };
+        // Cargo's authors are usually "Name <email>"; only the name is shown.
+        let author = match string(table, "author")? {
+            Some(author) => author.to_owned(),
+            None => package["authors"][0].as_str()
+                .map(|author| author.split(" <").next().unwrap_or(author).trim().to_owned())
+                .unwrap_or_else(|| "Unspecified Author".to_owned()),
+        };
+        let title_id = match string(table, "title-id")? {
+            Some(id) => Some(u64::from_str_radix(id.trim_start_matches("0x"), 16)
+                .map_err(|_| invalid(format!("title ID `{}` is not a hexadecimal number", id)))?),
+            None => None,
+        };
+        if format == Format::Nsp && title_id.is_none() {
+            return Err(invalid("NSPs need a package.metadata.nx.title-id".to_owned()));
+        }
+        let path = |key: &str| -> io::Result<Option<PathBuf>> {
+            Ok(string(table, key)?.map(|path| manifest_dir.join(path)))
+        };
+
+        Ok(Metadata {
+            format,
+            title: string(table, "title")?.unwrap_or(name).to_owned(),
+            author,
+            version: string(table, "version")?.unwrap_or(package_version).to_owned(),
+            icon: path("icon")?,
+            romfs: path("romfs")?,
+            title_id,
+            npdm: path("npdm")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(nx: &str) -> Value {
+        serde_json::from_str(&format!(r#"{{
+            "name": "hello",
+            "version": "0.2.0",
+            "authors": ["Jane Doe <jane@example.com>"],
+            "manifest_path": "/src/hello/Cargo.toml",
+            "metadata": {}
+        }}"#, nx)).unwrap()
+    }
+
+    #[test]
+    fn defaults_to_package_fields() {
+        for nx in &["null", r#"{"nx": {}}"#] {
+            let metadata = Metadata::from_package(&package(nx)).unwrap();
+            assert_eq!(metadata, Metadata {
+                format: Format::Nro,
+                title: "hello".to_owned(),
+                author: "Jane Doe".to_owned(),
+                version: "0.2.0".to_owned(),
+                icon: None,
+                romfs: None,
+                title_id: None,
+                npdm: None,
+            });
+        }
+    }
+
+    #[test]
+    fn reads_nx_table() {
+        let metadata = Metadata::from_package(&package(r#"{"nx": {
+            "format": "nsp",
+            "title": "Hello, world",
+            "author": "Someone",
+            "version": "1.0",
+            "icon": "assets/icon.jpg",
+            "romfs": "assets/romfs",
+            "title-id": "0x0100000000000123"
+        }}"#)).unwrap();
+        assert_eq!(metadata.format, Format::Nsp);
+        assert_eq!(metadata.title, "Hello, world");
+        assert_eq!(metadata.author, "Someone");
+        assert_eq!(metadata.version, "1.0");
+        assert_eq!(metadata.icon, Some(PathBuf::from("/src/hello/assets/icon.jpg")));
+        assert_eq!(metadata.romfs, Some(PathBuf::from("/src/hello/assets/romfs")));
+        assert_eq!(metadata.title_id, Some(0x0100000000000123));
+    }
+
+    #[test]
+    fn rejects_bad_tables() {
+        for nx in &[r#"{"nx": {"format": "nca"}}"#,
+                    r#"{"nx": {"format": "nsp"}}"#,
+                    r#"{"nx": {"title-id": "xyz"}}"#,
+                    r#"{"nx": {"title": 1}}"#,
+                    r#"{"nx": {"romfs-dir": "romfs"}}"#,
+                    r#"{"nx": "nro"}"#] {
+            assert!(Metadata::from_package(&package(nx)).is_err(), "{}", nx);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Packages a linked ELF as configured by its package's metadata.
+
+use std::fs;
+use std::io;
+
+use elf2nx;
+use nx::formats::nacp::Nacp;
+use nx::formats::npdm::{KernelCapability, Npdm, ServiceAccess};
+use nx::formats::nro::Assets;
+use nx::formats::pfs0;
+use nx::formats::romfs;
+
+use metadata::{Format, Metadata};
+use target::DevkitPro;
+
+/// An NRO with the title, icon and RomFS from `metadata`.
+pub fn nro(elf: &[u8], metadata: &Metadata, dkp: &DevkitPro) -> io::Result<Vec<u8>> {
+    let icon = match metadata.icon.clone().or_else(|| dkp.default_icon()) {
+        Some(icon) => fs::read(icon)?,
+        None => Vec::new(),
+    };
+    let romfs = match metadata.romfs {
+        Some(ref dir) => romfs::Builder::from_dir(dir)?.to_bytes()?,
+        None => Vec::new(),
+    };
+    let nacp = Nacp::new(&metadata.title, &metadata.author, &metadata.version).to_bytes()?;
+    elf2nx::elf_to_nro(elf, Some(Assets { icon, nacp, romfs }))?.to_bytes()
+}
+
+/// The NPDM used when the package doesn't bring its own: every service and
+/// filesystem permission, and the capabilities homebrew sysmodules usually
+/// ask for.
+fn default_npdm(metadata: &Metadata, title_id: u64) -> io::Result<Npdm> {
+    let mut caps = vec![
+        KernelCapability::ThreadInfo { max_priority: 63, min_priority: 24, min_core: 0, max_core: 3 },
+        KernelCapability::HandleTableSize(512),
+        KernelCapability::DebugFlags { allow_debug: true, force_debug: false },
+    ];
+    caps.extend(KernelCapability::syscalls(&(1..0x80).collect::<Vec<_>>())?);
+    // The name field holds 15 bytes and a NUL.
+    let mut name = metadata.title.as_str();
+    while name.len() > 0xf {
+        let mut end = 0xf;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name = &name[..end];
+    }
+    Ok(Npdm::new(name, title_id, !0,
+                 vec![ServiceAccess::client("*"), ServiceAccess::server("*")],
+                 caps))
+}
+
+/// An ExeFS NSP holding the ELF as `main` and its `main.npdm`.
+pub fn nsp(elf: &[u8], metadata: &Metadata) -> io::Result<Vec<u8>> {
+    let title_id = metadata.title_id
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "NSPs need a title ID"))?;
+    let npdm = match metadata.npdm {
+        Some(ref path) => fs::read(path)?,
+        None => default_npdm(metadata, title_id)?.to_bytes()?,
+    };
+    let mut builder = pfs0::Builder::new();
+    builder.add_bytes("main", elf2nx::elf_to_nso(elf)?.to_bytes()?)
+        .add_bytes("main.npdm", npdm);
+    builder.to_bytes()
+}
+
+pub fn package(elf: &[u8], metadata: &Metadata, dkp: &DevkitPro) -> io::Result<Vec<u8>> {
+    match metadata.format {
+        Format::Nro => nro(elf, metadata, dkp),
+        Format::Nsp => nsp(elf, metadata),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_npdm() {
+        let metadata = Metadata {
+            format: Format::Nsp,
+            title: "A sysmodule with a long name".to_owned(),
+            author: String::new(),
+            version: String::new(),
+            icon: None,
+            romfs: None,
+            title_id: Some(0x0100000000000123),
+            npdm: None,
+        };
+        let npdm = Npdm::parse(&default_npdm(&metadata, 0x0100000000000123).unwrap().to_bytes().unwrap()).unwrap();
+        assert_eq!(npdm.name, "A sysmodule wit");
+        assert_eq!(npdm.aci0.program_id, 0x0100000000000123);
+        assert_eq!(KernelCapability::syscall_ids(&npdm.aci0.kernel_capabilities), (1..0x80).collect::<Vec<_>>());
+        assert!(nsp(b"\x7fELF", &metadata).is_err());
+    }
+}
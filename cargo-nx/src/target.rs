@@ -0,0 +1,158 @@
+//! The custom target nx-std is built for, and the devkitPro install it links
+//! with.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The target triple, which is also the name of the generated spec file.
+pub const TRIPLE: &str = "aarch64-none-elf";
+
+/// The parts of devkitPro needed to link against libnx.
+pub struct DevkitPro {
+    pub gcc: PathBuf,
+    pub libnx: PathBuf,
+    pub portlibs: PathBuf,
+}
+
+impl DevkitPro {
+    /// Finds devkitPro from $DEVKITPRO. $DEVKITA64 and $LIBNX override where
+    /// devkitA64 and libnx are, like they do for nx-sys.
+    pub fn find() -> io::Result<Self> {
+        let root = env::var_os("DEVKITPRO")
+            .map(PathBuf::from)
+            .filter(|root| root.is_dir())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound,
+                                          "DEVKITPRO must point to a devkitPro install"))?;
+        let devkita64 = env::var_os("DEVKITA64").map_or_else(|| root.join("devkitA64"), PathBuf::from);
+        let libnx = env::var_os("LIBNX").map_or_else(|| root.join("libnx"), PathBuf::from);
+        let gcc = devkita64.join("bin").join(format!("{}-gcc{}", TRIPLE, env::consts::EXE_SUFFIX));
+        if !gcc.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound,
+                                      format!("{} does not exist; install devkitA64", gcc.display())));
+        }
+        if !libnx.join("switch.specs").is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound,
+                                      format!("no switch.specs in {}; install libnx", libnx.display())));
+        }
+        Ok(DevkitPro { gcc, libnx, portlibs: root.join("portlibs").join("switch") })
+    }
+
+    /// libnx's icon, which devkitPro's Makefiles use for NROs without one.
+    pub fn default_icon(&self) -> Option<PathBuf> {
+        Some(self.libnx.join("default_icon.jpg")).filter(|icon| icon.is_file())
+    }
+}
+
+fn json_strings(strings: &[String]) -> String {
+    let quoted: Vec<String> = strings.iter().map(|s| serde_json::to_string(s).unwrap()).collect();
+    format!("[{}]", quoted.join(", "))
+}
+
+/// The rustc target spec for Horizon. Code is position independent and
+/// linked by devkitA64's GCC with libnx's specs, which lay the ELF out the
+/// way elf2nx expects.
+pub fn spec(dkp: &DevkitPro) -> String {
+    let pre_link_args = vec![
+        format!("-specs={}", dkp.libnx.join("switch.specs").display()),
+        "-march=armv8-a+crc+crypto".to_owned(),
+        "-mtune=cortex-a57".to_owned(),
+        "-mtp=soft".to_owned(),
+        "-fPIE".to_owned(),
+        format!("-L{}", dkp.libnx.join("lib").display()),
+        format!("-L{}", dkp.portlibs.join("lib").display()),
+    ];
+    let post_link_args = vec!["-lnx".to_owned()];
+    format!(r#"{{
+    "llvm-target": "aarch64-unknown-none",
+    "data-layout": "e-m:e-i8:8:32-i16:16:32-i64:64-i128:128-n32:64-S128",
+    "arch": "aarch64",
+    "target-endian": "little",
+    "target-pointer-width": "64",
+    "target-c-int-width": "32",
+    "os": "horizon-nx",
+    "env": "newlib",
+    "vendor": "unknown",
+    "target-family": "unix",
+    "max-atomic-width": 128,
+    "features": "+a57,+strict-align,+crc,+crypto",
+    "executables": true,
+    "dynamic-linking": true,
+    "position-independent-executables": true,
+    "relocation-model": "pic",
+    "relro-level": "off",
+    "has-elf-tls": false,
+    "panic-strategy": "abort",
+    "trap-unreachable": true,
+    "linker-flavor": "gcc",
+    "linker": {},
+    "pre-link-args": {{ "gcc": {} }},
+    "post-link-args": {{ "gcc": {} }}
+}}
+"#, serde_json::to_string(&dkp.gcc.display().to_string()).unwrap(),
+        json_strings(&pre_link_args), json_strings(&post_link_args))
+}
+
+/// Tells Xargo to build the sysroot's std from nx-std.
+pub const XARGO_TOML: &str = "[target.aarch64-none-elf.dependencies.std]\n";
+
+/// Writes `contents` to `path` unless it already holds them, so Cargo doesn't
+/// see a change and rebuild everything.
+fn write_if_changed(path: &Path, contents: &str) -> io::Result<()> {
+    if fs::read_to_string(path).ok().as_ref().map(|existing| existing.as_str()) != Some(contents) {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+/// Writes the target spec to `dir`, which rustc has to be pointed at with
+/// $RUST_TARGET_PATH.
+pub fn write_spec(dkp: &DevkitPro, dir: &Path) -> io::Result<()> {
+    write_if_changed(&dir.join(format!("{}.json", TRIPLE)), &spec(dkp))
+}
+
+/// Writes `XARGO_TOML` into a stub project in `dir`, for packages without an
+/// Xargo.toml of their own.
+///
+/// Xargo only reads Xargo.toml from the project it's run in, so running it
+/// from `dir` with --manifest-path pointing at the real package picks this
+/// one up without writing anything into the package.
+pub fn write_xargo_project(dir: &Path) -> io::Result<()> {
+    write_if_changed(&dir.join("Cargo.toml"),
+                     "# Generated by cargo nx for Xargo to find Xargo.toml by.\n\
+                      [package]\nname = \"cargo-nx-sysroot\"\nversion = \"0.0.0\"\n\n\
+                      [lib]\npath = \"lib.rs\"\n\n[workspace]\n")?;
+    write_if_changed(&dir.join("lib.rs"), "")?;
+    write_if_changed(&dir.join("Xargo.toml"), XARGO_TOML)
+}
+
+/// The nx-std checkout the sysroot is built from: $NX_STD, else the one next
+/// to this crate.
+pub fn nx_std() -> PathBuf {
+    env::var_os("NX_STD")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("nx-std"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spec_is_valid_json() {
+        let dkp = DevkitPro {
+            gcc: PathBuf::from("/opt/devkitpro/devkitA64/bin/aarch64-none-elf-gcc"),
+            libnx: PathBuf::from("/opt/devkitpro/libnx"),
+            portlibs: PathBuf::from("/opt/devkitpro/portlibs/switch"),
+        };
+        let spec: serde_json::Value = serde_json::from_str(&spec(&dkp)).unwrap();
+        assert_eq!(spec["os"], "horizon-nx");
+        assert_eq!(spec["linker"], "/opt/devkitpro/devkitA64/bin/aarch64-none-elf-gcc");
+        assert_eq!(spec["pre-link-args"]["gcc"][0], "-specs=/opt/devkitpro/libnx/switch.specs");
+        assert_eq!(spec["post-link-args"]["gcc"][0], "-lnx");
+    }
+}
@@ -0,0 +1,57 @@
+//! elf2nro <elf_file> <nro_file> [--icon=<icon.jpg>] [--nacp=<control.nacp>]
+//!         [--romfs=<image>] [--romfsdir=<directory>]
+//!
+//! Takes the same arguments as devkitPro's tool of the same name.
+
+extern crate elf2nx;
+extern crate nx;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+use nx::formats::nro::Assets;
+use nx::formats::romfs;
+
+const USAGE: &str = "usage: elf2nro <elf_file> <nro_file> [--icon=<icon.jpg>] [--nacp=<control.nacp>] \
+                     [--romfs=<image>] [--romfsdir=<directory>]";
+
+fn run(args: &[String]) -> io::Result<()> {
+    let mut paths = Vec::new();
+    let mut assets = Assets::default();
+    let mut has_assets = false;
+    for arg in args {
+        let (key, value) = match arg.find('=') {
+            Some(eq) if arg.starts_with("--") => (&arg[..eq], &arg[eq + 1..]),
+            _ if arg.starts_with("--") => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+            _ => {
+                paths.push(arg);
+                continue;
+            }
+        };
+        match key {
+            "--icon" => assets.icon = fs::read(value)?,
+            "--nacp" => assets.nacp = fs::read(value)?,
+            "--romfs" => assets.romfs = fs::read(value)?,
+            "--romfsdir" => assets.romfs = romfs::Builder::from_dir(value)?.to_bytes()?,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+        has_assets = true;
+    }
+    if paths.len() != 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE));
+    }
+
+    let elf = fs::read(paths[0])?;
+    let nro = elf2nx::elf_to_nro(&elf, if has_assets { Some(assets) } else { None })?;
+    fs::write(paths[1], nro.to_bytes()?)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("elf2nro: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,27 @@
+//! elf2nso <elf_file> <nso_file>
+//!
+//! Takes the same arguments as devkitPro's tool of the same name.
+
+extern crate elf2nx;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+fn run(args: &[String]) -> io::Result<()> {
+    if args.len() != 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "usage: elf2nso <elf_file> <nso_file>"));
+    }
+    let elf = fs::read(&args[0])?;
+    let nso = elf2nx::elf_to_nso(&elf)?;
+    fs::write(&args[1], nso.to_bytes()?)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("elf2nso: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,144 @@
+//! Just enough of ELF64 to find an executable's loadable segments, sections
+//! and build ID.
+
+use std::io;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_NOTE: u32 = 4;
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+const EM_AARCH64: u16 = 183;
+const NT_GNU_BUILD_ID: u32 = 3;
+
+pub(crate) fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn bytes(buf: &[u8], offset: u64, len: u64) -> io::Result<&[u8]> {
+    offset.checked_add(len)
+        .filter(|&end| end <= buf.len() as u64)
+        .map(|end| &buf[offset as usize..end as usize])
+        .ok_or_else(|| invalid_data("ELF is truncated"))
+}
+
+fn u16_at(buf: &[u8], offset: u64) -> io::Result<u16> {
+    let b = bytes(buf, offset, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(buf: &[u8], offset: u64) -> io::Result<u32> {
+    let b = bytes(buf, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u64_at(buf: &[u8], offset: u64) -> io::Result<u64> {
+    let b = bytes(buf, offset, 8)?;
+    Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+    pub mem_size: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// A little-endian AArch64 ELF64 file.
+pub struct Elf<'a> {
+    data: &'a [u8],
+    pub segments: Vec<ProgramHeader>,
+    pub sections: Vec<Section>,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(data: &'a [u8]) -> io::Result<Self> {
+        let ident = bytes(data, 0, 6)?;
+        if &ident[..4] != b"\x7fELF" {
+            return Err(invalid_data("not an ELF file"));
+        }
+        if ident[4] != 2 || ident[5] != 1 || u16_at(data, 0x12)? != EM_AARCH64 {
+            return Err(invalid_data("not a little-endian AArch64 ELF64 file"));
+        }
+        let phoff = u64_at(data, 0x20)?;
+        let shoff = u64_at(data, 0x28)?;
+        let phentsize = u16_at(data, 0x36)? as u64;
+        let phnum = u16_at(data, 0x38)? as u64;
+        let shentsize = u16_at(data, 0x3a)? as u64;
+        let shnum = u16_at(data, 0x3c)? as u64;
+        let shstrndx = u16_at(data, 0x3e)? as u64;
+
+        let mut segments = Vec::with_capacity(phnum as usize);
+        for i in 0..phnum {
+            let ph = phoff + i * phentsize;
+            segments.push(ProgramHeader {
+                kind: u32_at(data, ph)?,
+                flags: u32_at(data, ph + 4)?,
+                offset: u64_at(data, ph + 8)?,
+                vaddr: u64_at(data, ph + 0x10)?,
+                file_size: u64_at(data, ph + 0x20)?,
+                mem_size: u64_at(data, ph + 0x28)?,
+            });
+        }
+
+        let mut sections = Vec::with_capacity(shnum as usize);
+        if shnum != 0 {
+            let strtab = shoff + shstrndx * shentsize;
+            let names = bytes(data, u64_at(data, strtab + 0x18)?, u64_at(data, strtab + 0x20)?)?;
+            for i in 0..shnum {
+                let sh = shoff + i * shentsize;
+                let name = names.get(u32_at(data, sh)? as usize..)
+                    .and_then(|name| name.iter().position(|&b| b == 0).map(|end| &name[..end]))
+                    .ok_or_else(|| invalid_data("ELF section name is out of bounds"))?;
+                sections.push(Section {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    addr: u64_at(data, sh + 0x10)?,
+                    offset: u64_at(data, sh + 0x18)?,
+                    size: u64_at(data, sh + 0x20)?,
+                });
+            }
+        }
+        Ok(Elf { data, segments, sections })
+    }
+
+    /// The bytes of a segment that are stored in the file.
+    pub fn segment_data(&self, segment: &ProgramHeader) -> io::Result<&'a [u8]> {
+        bytes(self.data, segment.offset, segment.file_size)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    /// The GNU build ID, which becomes the module ID.
+    pub fn build_id(&self) -> io::Result<Option<&'a [u8]>> {
+        for segment in self.segments.iter().filter(|segment| segment.kind == PT_NOTE) {
+            let notes = self.segment_data(segment)?;
+            let mut pos = 0;
+            while pos + 12 <= notes.len() as u64 {
+                let name_size = u32_at(notes, pos)? as u64;
+                let desc_size = u32_at(notes, pos + 4)? as u64;
+                let kind = u32_at(notes, pos + 8)?;
+                let name = bytes(notes, pos + 12, name_size)?;
+                let desc_offset = pos + 12 + ((name_size + 3) & !3);
+                let desc = bytes(notes, desc_offset, desc_size)?;
+                if kind == NT_GNU_BUILD_ID && name == b"GNU\0" {
+                    return Ok(Some(desc));
+                }
+                pos = desc_offset + ((desc_size + 3) & !3);
+            }
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,221 @@
+//! Converts the AArch64 ELFs nx-rs apps and sysmodules link to into NRO or
+//! NSO, replacing devkitPro's `elf2nro` and `elf2nso`.
+//!
+//! The ELF has to be laid out the way devkitPro's `switch.ld` does it: text
+//! linked at address 0, followed by the read-only and read-write segments,
+//! each starting on a page boundary.
+
+extern crate nx;
+
+pub mod elf;
+
+use std::io;
+
+use nx::formats::nro::{Assets, Extent, Nro};
+use nx::formats::nso::Nso;
+
+use elf::{invalid_data, Elf, ProgramHeader, PF_R, PF_W, PF_X, PT_LOAD};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+fn page_align(value: u64) -> u64 {
+    (value + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// The text, ro and data segments, checked to be laid out as expected.
+fn load_segments(elf: &Elf) -> io::Result<[ProgramHeader; 3]> {
+    let mut loads: Vec<_> = elf.segments.iter()
+        .filter(|segment| segment.kind == PT_LOAD && segment.mem_size != 0)
+        .cloned()
+        .collect();
+    loads.sort_by_key(|segment| segment.vaddr);
+    if loads.len() != 3 {
+        return Err(invalid_data(&format!(
+            "expected text, ro and data PT_LOAD segments, found {} segments", loads.len())));
+    }
+    for (segment, &(flags, name)) in loads.iter().zip(&[(PF_R | PF_X, "text"), (PF_R, "ro"), (PF_R | PF_W, "data")]) {
+        if segment.flags & (PF_R | PF_W | PF_X) != flags {
+            return Err(invalid_data(&format!("the {} segment has unexpected permissions", name)));
+        }
+        if segment.vaddr % PAGE_SIZE != 0 {
+            return Err(invalid_data(&format!("the {} segment is not page-aligned", name)));
+        }
+    }
+    if loads[0].vaddr != 0 {
+        return Err(invalid_data("the text segment must be linked at address 0"));
+    }
+    if loads[0].vaddr + loads[0].mem_size > loads[1].vaddr || loads[1].vaddr + loads[1].mem_size > loads[2].vaddr {
+        return Err(invalid_data("segments overlap"));
+    }
+    Ok([loads[0], loads[1], loads[2]])
+}
+
+/// A segment's contents, zero-filled up to `size` bytes.
+fn padded(elf: &Elf, segment: &ProgramHeader, size: u64) -> io::Result<Vec<u8>> {
+    let mut data = elf.segment_data(segment)?.to_vec();
+    data.resize(size as usize, 0);
+    Ok(data)
+}
+
+/// Where section `name` is relative to the ro segment, if it's in there.
+fn ro_extent(elf: &Elf, ro: &ProgramHeader, name: &str) -> Extent {
+    elf.section(name)
+        .filter(|section| section.addr >= ro.vaddr && section.addr + section.size <= ro.vaddr + ro.mem_size)
+        .map_or(Extent::default(), |section| Extent {
+            offset: (section.addr - ro.vaddr) as u32,
+            size: section.size as u32,
+        })
+}
+
+fn module_id(elf: &Elf) -> io::Result<[u8; 0x20]> {
+    let mut id = [0; 0x20];
+    if let Some(build_id) = elf.build_id()? {
+        let len = build_id.len().min(id.len());
+        id[..len].copy_from_slice(&build_id[..len]);
+    }
+    Ok(id)
+}
+
+/// Converts an ELF to an NRO, optionally with an asset section.
+pub fn elf_to_nro(elf: &[u8], assets: Option<Assets>) -> io::Result<Nro> {
+    let elf = Elf::parse(elf)?;
+    let [text, ro, data] = load_segments(&elf)?;
+    let data_end = data.vaddr + page_align(data.file_size);
+
+    // NRO segments are stored as mapped, so each one is padded up to the
+    // next.
+    let mut nro = Nro::new(padded(&elf, &text, ro.vaddr)?,
+                           padded(&elf, &ro, data.vaddr - ro.vaddr)?,
+                           padded(&elf, &data, data.file_size)?);
+    nro.bss_size = page_align(data.vaddr + data.mem_size).saturating_sub(data_end) as u32;
+    nro.module_id = module_id(&elf)?;
+    nro.api_info = ro_extent(&elf, &ro, ".api_info");
+    nro.dynstr = ro_extent(&elf, &ro, ".dynstr");
+    nro.dynsym = ro_extent(&elf, &ro, ".dynsym");
+    nro.assets = assets;
+    Ok(nro)
+}
+
+/// Converts an ELF to an NSO with compressed, hash-checked segments.
+pub fn elf_to_nso(elf: &[u8]) -> io::Result<Nso> {
+    let elf = Elf::parse(elf)?;
+    let [text, ro, data] = load_segments(&elf)?;
+
+    let mut nso = Nso::new(elf.segment_data(&text)?.to_vec(),
+                           elf.segment_data(&ro)?.to_vec(),
+                           elf.segment_data(&data)?.to_vec());
+    nso.text.memory_offset = text.vaddr as u32;
+    nso.ro.memory_offset = ro.vaddr as u32;
+    nso.data.memory_offset = data.vaddr as u32;
+    nso.bss_size = (data.mem_size - data.file_size) as u32;
+    nso.module_id = module_id(&elf)?;
+    nso.api_info = ro_extent(&elf, &ro, ".api_info");
+    nso.dynstr = ro_extent(&elf, &ro, ".dynstr");
+    nso.dynsym = ro_extent(&elf, &ro, ".dynsym");
+    Ok(nso)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put(buf: &mut Vec<u8>, pos: usize, bytes: &[u8]) {
+        if buf.len() < pos + bytes.len() {
+            buf.resize(pos + bytes.len(), 0);
+        }
+        buf[pos..pos + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// An ELF with text at 0, ro at 0x1000 holding `.dynstr`, data at
+    /// 0x2000 with 0x1800 bytes of BSS, and a build ID note.
+    fn sample_elf() -> Vec<u8> {
+        let mut elf = Vec::new();
+        put(&mut elf, 0, b"\x7fELF\x02\x01\x01");
+        put(&mut elf, 0x12, &183u16.to_le_bytes());
+        put(&mut elf, 0x20, &0x40u64.to_le_bytes());
+        put(&mut elf, 0x36, &0x38u16.to_le_bytes());
+        put(&mut elf, 0x38, &4u16.to_le_bytes());
+
+        let note = [&4u32.to_le_bytes()[..], &4u32.to_le_bytes(), &3u32.to_le_bytes(), b"GNU\0", &[0xb1, 0xd0, 0, 0x1d]].concat();
+        let phdrs: [(u32, u32, u64, u64, u64, u64); 4] = [
+            (PT_LOAD, PF_R | PF_X, 0x1000, 0, 0x200, 0x200),
+            (PT_LOAD, PF_R, 0x2000, 0x1000, 0x100, 0x100),
+            (PT_LOAD, PF_R | PF_W, 0x3000, 0x2000, 0x80, 0x1880),
+            (elf::PT_NOTE, PF_R, 0x3080, 0x1100, note.len() as u64, note.len() as u64),
+        ];
+        for (i, &(kind, flags, offset, vaddr, file_size, mem_size)) in phdrs.iter().enumerate() {
+            let ph = 0x40 + i * 0x38;
+            put(&mut elf, ph, &kind.to_le_bytes());
+            put(&mut elf, ph + 4, &flags.to_le_bytes());
+            put(&mut elf, ph + 8, &offset.to_le_bytes());
+            put(&mut elf, ph + 0x10, &vaddr.to_le_bytes());
+            put(&mut elf, ph + 0x20, &file_size.to_le_bytes());
+            put(&mut elf, ph + 0x28, &mem_size.to_le_bytes());
+        }
+        put(&mut elf, 0x1000, &[0x11; 0x200]);
+        put(&mut elf, 0x2000, &[0x22; 0x100]);
+        put(&mut elf, 0x3000, &[0x33; 0x80]);
+        put(&mut elf, 0x3080, &note);
+
+        // Sections: null, .dynstr and .shstrtab.
+        let names = b"\0.dynstr\0.shstrtab\0";
+        put(&mut elf, 0x3100, names);
+        let shoff = 0x3200;
+        put(&mut elf, 0x28, &(shoff as u64).to_le_bytes());
+        put(&mut elf, 0x3a, &0x40u16.to_le_bytes());
+        put(&mut elf, 0x3c, &3u16.to_le_bytes());
+        put(&mut elf, 0x3e, &2u16.to_le_bytes());
+        for &(i, name, addr, offset, size) in &[(1, 1u32, 0x1010u64, 0x2010u64, 0x20u64),
+                                               (2, 9, 0, 0x3100, names.len() as u64)] {
+            let sh = shoff + i * 0x40;
+            put(&mut elf, sh, &name.to_le_bytes());
+            put(&mut elf, sh + 0x10, &addr.to_le_bytes());
+            put(&mut elf, sh + 0x18, &offset.to_le_bytes());
+            put(&mut elf, sh + 0x20, &size.to_le_bytes());
+        }
+        elf
+    }
+
+    #[test]
+    fn converts_to_nro() {
+        let assets = Assets { icon: vec![1], nacp: vec![2; 0x4000], romfs: vec![] };
+        let nro = elf_to_nro(&sample_elf(), Some(assets.clone())).unwrap();
+        assert_eq!(nro.text.len(), 0x1000);
+        assert_eq!(&nro.text[..4], &[0x11; 4]);
+        assert_eq!(nro.ro.len(), 0x1000);
+        assert_eq!(nro.data.len(), 0x80);
+        assert_eq!(nro.bss_size, 0x1000);
+        assert_eq!(&nro.module_id[..5], &[0xb1, 0xd0, 0, 0x1d, 0]);
+        assert_eq!(nro.dynstr, Extent { offset: 0x10, size: 0x20 });
+        assert_eq!(nro.dynsym, Extent::default());
+
+        let bytes = nro.to_bytes().unwrap();
+        assert_eq!(&bytes[0x1000..0x1004], &[0x22; 4]);
+        assert_eq!(&bytes[0x2000..0x2004], &[0x33; 4]);
+        assert_eq!(Nro::parse(&bytes).unwrap().assets, Some(assets));
+    }
+
+    #[test]
+    fn converts_to_nso() {
+        let nso = elf_to_nso(&sample_elf()).unwrap();
+        assert_eq!(nso.text.data, vec![0x11; 0x200]);
+        assert_eq!(nso.ro.memory_offset, 0x1000);
+        assert_eq!(nso.data.memory_offset, 0x2000);
+        assert_eq!(nso.bss_size, 0x1800);
+        assert_eq!(nso.module_id_string(), "B1D0001D");
+        assert_eq!(Nso::parse(&nso.to_bytes().unwrap()).unwrap(), nso);
+    }
+
+    #[test]
+    fn rejects_unexpected_layouts() {
+        let mut elf = sample_elf();
+        // Make ro writable.
+        elf[0x40 + 0x38 + 4] |= PF_W as u8;
+        assert_eq!(elf_to_nro(&elf, None).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut elf = sample_elf();
+        elf[0x12] = 62;
+        assert!(elf_to_nso(&elf).is_err());
+        assert!(elf_to_nso(b"\x7fELF").is_err());
+    }
+}
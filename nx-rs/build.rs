@@ -0,0 +1,26 @@
+use std::env;
+
+// Covers every libnx release so far with room to spare; nx-sys declares the same range.
+const MAX_LIBNX_MAJOR: u32 = 9;
+const MAX_LIBNX_MINOR: u32 = 9;
+
+fn main() {
+    // nx-sys passes on the libnx version its bindings come from (see its build.rs), so wrappers
+    // can be gated on the same libnx_vX/libnx_vX_Y flags. Under host-mock there is no nx-sys, and
+    // the fakes implement the oldest API generation.
+    let cfgs = env::var("DEP_NX_CFGS").unwrap_or_default();
+    let cfgs: Vec<&str> = cfgs.split(',').filter(|cfg| !cfg.is_empty()).collect();
+    for cfg in &cfgs {
+        println!("cargo:rustc-cfg={}", cfg);
+    }
+
+    // Every flag nx-sys could set is declared, set or not, so gating on a newer libnx than the
+    // one built against isn't reported as an unexpected cfg.
+    let mut known: Vec<String> = (1..MAX_LIBNX_MAJOR + 1).map(|major| format!("libnx_v{}", major)).collect();
+    for major in 1..MAX_LIBNX_MAJOR + 1 {
+        known.extend((0..MAX_LIBNX_MINOR + 1).map(|minor| format!("libnx_v{}_{}", major, minor)));
+    }
+    known.extend(cfgs.iter().map(|cfg| cfg.to_string()));
+    println!("cargo:rustc-check-cfg=cfg({})", known.join(", "));
+    println!("cargo:rerun-if-env-changed=DEP_NX_CFGS");
+}
@@ -17,7 +17,7 @@ impl Keyboard {
         unsafe {
             let mut kbd: ::libnx::SwkbdConfig = std::mem::zeroed();
             let rc = ::libnx::swkbdCreate(&mut kbd, 0);
-            result_final!(rc, Self { kbd: kbd })
+            result_final!(rc, Self { kbd })
         }
     }
 
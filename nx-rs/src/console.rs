@@ -22,4 +22,16 @@ pub fn flush() {
     unsafe {
         ::libnx::consoleUpdate(std::ptr::null_mut());
     }
-}
\ No newline at end of file
+}
+
+/// Prints `text` to the console.
+#[cfg(not(feature = "host-mock"))]
+pub fn print(text: &str) {
+    print!("{}", text);
+}
+
+/// Captures `text` instead of printing it, see `mock::console`.
+#[cfg(feature = "host-mock")]
+pub fn print(text: &str) {
+    ::mock::console::write(text);
+}
@@ -0,0 +1,181 @@
+//! LZ4 block compression, as used for NSO segments.
+//!
+//! Only the raw block format is handled; there is no frame header, and the
+//! decompressed size has to be known up front.
+
+use std::io;
+
+use super::invalid_data;
+
+const MIN_MATCH: usize = 4;
+/// The last bytes of a block are always literals.
+const LAST_LITERALS: usize = 5;
+/// No match may start this close to the end of a block.
+const MF_LIMIT: usize = 12;
+const MAX_OFFSET: usize = 0xffff;
+const HASH_LOG: u32 = 12;
+
+/// Decompresses a block that is `size` bytes long once decompressed.
+pub fn decompress(src: &[u8], size: usize) -> io::Result<Vec<u8>> {
+    // `size` comes from the file, so don't trust it further than the block
+    // could expand: each byte of it yields at most 255 bytes of output.
+    let mut out = Vec::with_capacity(size.min(src.len().saturating_mul(255)));
+    let mut pos = 0;
+    let next = |pos: &mut usize| -> io::Result<u8> {
+        let b = *src.get(*pos).ok_or_else(|| invalid_data("LZ4 block is truncated"))?;
+        *pos += 1;
+        Ok(b)
+    };
+    let length = |pos: &mut usize, mut len: usize| -> io::Result<usize> {
+        if len == 15 {
+            loop {
+                let b = next(pos)?;
+                len += b as usize;
+                if b != 255 {
+                    break;
+                }
+            }
+        }
+        Ok(len)
+    };
+
+    loop {
+        let token = next(&mut pos)?;
+        let literals = length(&mut pos, (token >> 4) as usize)?;
+        if literals > src.len() - pos || out.len() + literals > size {
+            return Err(invalid_data("LZ4 literals overrun the block"));
+        }
+        out.extend_from_slice(&src[pos..pos + literals]);
+        pos += literals;
+        if pos == src.len() {
+            break;
+        }
+
+        let offset = next(&mut pos)? as usize | (next(&mut pos)? as usize) << 8;
+        if offset == 0 || offset > out.len() {
+            return Err(invalid_data("LZ4 match offset is out of range"));
+        }
+        let len = length(&mut pos, (token & 0xf) as usize)? + MIN_MATCH;
+        if out.len() + len > size {
+            return Err(invalid_data("LZ4 match overruns the block"));
+        }
+        // The match may overlap the bytes it produces, so copy one at a time.
+        let start = out.len() - offset;
+        for i in 0..len {
+            let b = out[start + i];
+            out.push(b);
+        }
+    }
+
+    if out.len() != size {
+        return Err(invalid_data("LZ4 block decompressed to the wrong size"));
+    }
+    Ok(out)
+}
+
+/// Compresses `src` into a single block. Like the reference fast mode, this
+/// takes the first match a small hash table turns up.
+pub fn compress(src: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(src.len() + src.len() / 255 + 16);
+    let mut table = vec![0usize; 1 << HASH_LOG];
+    let match_limit = src.len().saturating_sub(LAST_LITERALS);
+    let search_limit = src.len().saturating_sub(MF_LIMIT);
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    while pos < search_limit {
+        let seq = read_u32(src, pos);
+        let hash = (seq.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize;
+        // Entries are stored off by one so zero can mean empty.
+        let candidate = table[hash];
+        table[hash] = pos + 1;
+        if candidate != 0 {
+            let candidate = candidate - 1;
+            if pos - candidate <= MAX_OFFSET && read_u32(src, candidate) == seq {
+                let mut len = MIN_MATCH;
+                while pos + len < match_limit && src[candidate + len] == src[pos + len] {
+                    len += 1;
+                }
+                push_sequence(&mut out, &src[anchor..pos], Some((pos - candidate, len)));
+                pos += len;
+                anchor = pos;
+                continue;
+            }
+        }
+        pos += 1;
+    }
+    push_sequence(&mut out, &src[anchor..], None);
+    out
+}
+
+fn read_u32(src: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([src[pos], src[pos + 1], src[pos + 2], src[pos + 3]])
+}
+
+fn push_length(out: &mut Vec<u8>, len: usize) {
+    let mut rest = len - 15;
+    while rest >= 255 {
+        out.push(255);
+        rest -= 255;
+    }
+    out.push(rest as u8);
+}
+
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    let token = (literals.len().min(15) << 4) | match_len.min(15);
+    out.push(token as u8);
+    if literals.len() >= 15 {
+        push_length(out, literals.len());
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.push(offset as u8);
+        out.push((offset >> 8) as u8);
+        if match_len >= 15 {
+            push_length(out, match_len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(src: &[u8]) -> Vec<u8> {
+        let compressed = compress(src);
+        assert_eq!(decompress(&compressed, src.len()).unwrap(), src);
+        compressed
+    }
+
+    #[test]
+    fn decompresses_overlapping_match() {
+        let block = [0x14, b'a', 1, 0, 0x50, b'b', b'b', b'b', b'b', b'b'];
+        assert_eq!(decompress(&block, 14).unwrap(), b"aaaaaaaaabbbbb");
+    }
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(round_trip(b""), [0]);
+        round_trip(b"short");
+        round_trip(b"abcabcabcabcabcabcabcabcabcabcabc");
+
+        let repetitive: Vec<u8> = (0..0x10000).map(|i| (i / 7 % 13) as u8).collect();
+        assert!(round_trip(&repetitive).len() < repetitive.len() / 10);
+
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..0x1000).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        }).collect();
+        round_trip(&noise);
+    }
+
+    #[test]
+    fn rejects_bad_blocks() {
+        assert!(decompress(&[0x40, b'a'], 4).is_err());
+        assert!(decompress(&[0x10, b'a'], usize::max_value()).is_err());
+        assert!(decompress(&[0x14, b'a', 2, 0, 0x00], 9).is_err());
+        assert!(decompress(&[0x10, b'a'], 2).is_err());
+    }
+}
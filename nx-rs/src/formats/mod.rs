@@ -0,0 +1,187 @@
+//! Readers and writers for the Switch's file formats.
+//!
+//! Nothing here calls into libnx, so this module also builds for the host
+//! with `default-features = false`, where build tools can use it.
+
+use std::cmp;
+use std::io::{self, Read, Seek, SeekFrom};
+
+pub mod lz4;
+pub mod mod0;
+pub mod nacp;
+pub mod npdm;
+pub mod nro;
+pub mod nso;
+pub mod pfs0;
+pub mod romfs;
+
+pub(crate) fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// Bounds-checked little-endian reads from a byte slice.
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pub pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Reader { buf, pos: 0 }
+    }
+
+    pub fn at(buf: &'a [u8], pos: usize) -> Self {
+        Reader { buf, pos }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or_else(|| invalid_data("offset out of range"))?;
+        if end > self.buf.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of data"));
+        }
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn array<T: Default + AsMut<[u8]>>(&mut self) -> io::Result<T> {
+        let mut array = T::default();
+        let len = array.as_mut().len();
+        array.as_mut().copy_from_slice(self.bytes(len)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        let b = self.bytes(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    /// Reads a fixed-size field holding a NUL-padded string. Invalid UTF-8 is
+    /// replaced rather than rejected, since these are only ever displayed.
+    pub fn str(&mut self, len: usize) -> io::Result<String> {
+        let field = self.bytes(len)?;
+        let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+        Ok(String::from_utf8_lossy(&field[..end]).into_owned())
+    }
+
+    pub fn magic(&mut self, magic: &[u8], what: &str) -> io::Result<()> {
+        if self.bytes(magic.len())? != magic {
+            return Err(invalid_data(what));
+        }
+        Ok(())
+    }
+}
+
+/// Little-endian writes into a growable buffer.
+pub(crate) trait WriteLe {
+    fn put_u8(&mut self, value: u8);
+    fn put_u16(&mut self, value: u16);
+    fn put_u32(&mut self, value: u32);
+    fn put_u64(&mut self, value: u64);
+    fn pad_to(&mut self, len: usize);
+    /// Writes `value` into a fixed-size field, leaving room for the NUL.
+    fn put_str(&mut self, value: &str, len: usize, what: &str) -> io::Result<()>;
+}
+
+impl WriteLe for Vec<u8> {
+    fn put_u8(&mut self, value: u8) {
+        self.push(value);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, value: u64) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Zero-fills up to `len` bytes.
+    fn pad_to(&mut self, len: usize) {
+        if self.len() < len {
+            self.resize(len, 0);
+        }
+    }
+
+    fn put_str(&mut self, value: &str, len: usize, what: &str) -> io::Result<()> {
+        if value.len() >= len || value.as_bytes().contains(&0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, what));
+        }
+        let end = self.len() + len;
+        self.extend_from_slice(value.as_bytes());
+        self.pad_to(end);
+        Ok(())
+    }
+}
+
+/// A `Read + Seek` view of one file in an archive, reading from the
+/// archive's stream on demand.
+pub struct EntryReader<'a, R: 'a> {
+    inner: &'a mut R,
+    start: u64,
+    size: u64,
+    pos: u64,
+}
+
+impl<'a, R: Read + Seek> EntryReader<'a, R> {
+    pub(crate) fn new(inner: &'a mut R, start: u64, size: u64) -> Self {
+        EntryReader { inner, start, size, pos: 0 }
+    }
+}
+
+impl<'a, R: Read + Seek> Read for EntryReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size {
+            return Ok(0);
+        }
+        let len = cmp::min(buf.len() as u64, self.size - self.pos) as usize;
+        self.inner.seek(SeekFrom::Start(self.start + self.pos))?;
+        let read = self.inner.read(&mut buf[..len])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<'a, R: Read + Seek> Seek for EntryReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => offset(self.size, delta),
+            SeekFrom::Current(delta) => offset(self.pos, delta),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+fn offset(base: u64, delta: i64) -> Option<u64> {
+    if delta < 0 {
+        base.checked_sub(delta.wrapping_neg() as u64)
+    } else {
+        base.checked_add(delta as u64)
+    }
+}
@@ -0,0 +1,57 @@
+//! The MOD0 header the runtime uses to find a module's dynamic section and
+//! BSS, shared by NRO and NSO executables.
+
+use std::io;
+
+use super::{invalid_data, Reader, WriteLe};
+
+pub const MAGIC: &[u8; 4] = b"MOD0";
+pub const SIZE: usize = 0x1c;
+
+/// A MOD0 header. All offsets are relative to the header itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Mod0 {
+    pub dynamic_offset: i32,
+    pub bss_start_offset: i32,
+    pub bss_end_offset: i32,
+    pub eh_frame_hdr_start_offset: i32,
+    pub eh_frame_hdr_end_offset: i32,
+    pub module_object_offset: i32,
+}
+
+impl Mod0 {
+    /// Parses the header at `offset` in a loaded module image, i.e. its
+    /// segments laid out at their memory offsets.
+    pub fn parse(image: &[u8], offset: usize) -> io::Result<Self> {
+        let mut r = Reader::at(image, offset);
+        r.magic(MAGIC, "missing MOD0 magic")?;
+        Ok(Mod0 {
+            dynamic_offset: r.u32()? as i32,
+            bss_start_offset: r.u32()? as i32,
+            bss_end_offset: r.u32()? as i32,
+            eh_frame_hdr_start_offset: r.u32()? as i32,
+            eh_frame_hdr_end_offset: r.u32()? as i32,
+            module_object_offset: r.u32()? as i32,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(SIZE);
+        buf.extend_from_slice(MAGIC);
+        for &offset in &[self.dynamic_offset, self.bss_start_offset, self.bss_end_offset,
+                         self.eh_frame_hdr_start_offset, self.eh_frame_hdr_end_offset,
+                         self.module_object_offset] {
+            buf.put_u32(offset as u32);
+        }
+        buf
+    }
+
+    /// Resolves one of the header's offsets, given the header's own position.
+    pub fn resolve(header_offset: usize, offset: i32) -> io::Result<usize> {
+        let absolute = header_offset as i64 + offset as i64;
+        if absolute < 0 {
+            return Err(invalid_data("MOD0 offset points before the module"));
+        }
+        Ok(absolute as usize)
+    }
+}
@@ -0,0 +1,485 @@
+//! NACP, the application control properties: titles, version, save data
+//! sizes and the other settings the system reads before launching a title.
+//!
+//! The same file is embedded in an NRO's asset section and returned by ns
+//! for installed applications, so it can be generated at build time and
+//! read back on the console with this module.
+
+use std::io::{self, Write};
+
+use super::{invalid_data, Reader, WriteLe};
+
+pub const SIZE: usize = 0x4000;
+pub const LANGUAGE_COUNT: usize = 16;
+const NAME_LEN: usize = 0x200;
+const PUBLISHER_LEN: usize = 0x100;
+/// Where the fields this module knows about end. The rest of the struct is
+/// kept as raw bytes in `Nacp::extra`.
+const KNOWN_END: usize = 0x3214;
+
+/// The languages a NACP has title entries for, in entry order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Language {
+    AmericanEnglish,
+    BritishEnglish,
+    Japanese,
+    French,
+    German,
+    LatinAmericanSpanish,
+    Spanish,
+    Italian,
+    Dutch,
+    CanadianFrench,
+    Portuguese,
+    Russian,
+    Korean,
+    TraditionalChinese,
+    SimplifiedChinese,
+    BrazilianPortuguese,
+}
+
+impl Language {
+    pub const ALL: [Language; LANGUAGE_COUNT] = [
+        Language::AmericanEnglish,
+        Language::BritishEnglish,
+        Language::Japanese,
+        Language::French,
+        Language::German,
+        Language::LatinAmericanSpanish,
+        Language::Spanish,
+        Language::Italian,
+        Language::Dutch,
+        Language::CanadianFrench,
+        Language::Portuguese,
+        Language::Russian,
+        Language::Korean,
+        Language::TraditionalChinese,
+        Language::SimplifiedChinese,
+        Language::BrazilianPortuguese,
+    ];
+
+    /// The language's entry in `Nacp::titles` and bit in
+    /// `Nacp::supported_language_flag`.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The language code settings uses for this language.
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::AmericanEnglish => "en-US",
+            Language::BritishEnglish => "en-GB",
+            Language::Japanese => "ja",
+            Language::French => "fr",
+            Language::German => "de",
+            Language::LatinAmericanSpanish => "es-419",
+            Language::Spanish => "es",
+            Language::Italian => "it",
+            Language::Dutch => "nl",
+            Language::CanadianFrench => "fr-CA",
+            Language::Portuguese => "pt",
+            Language::Russian => "ru",
+            Language::Korean => "ko",
+            Language::TraditionalChinese => "zh-TW",
+            Language::SimplifiedChinese => "zh-CN",
+            Language::BrazilianPortuguese => "pt-BR",
+        }
+    }
+
+    /// Maps a settings language code, e.g. "en-US", to its NACP entry.
+    /// The script-based Chinese codes share entries with the region-based
+    /// ones.
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "zh-Hant" => return Some(Language::TraditionalChinese),
+            "zh-Hans" => return Some(Language::SimplifiedChinese),
+            _ => {}
+        }
+        Language::ALL.iter().cloned().find(|lang| lang.code() == code)
+    }
+
+    /// Maps a language code as returned by `setGetSystemLanguage`, which
+    /// packs the code's characters into an integer.
+    pub fn from_language_code(code: u64) -> Option<Self> {
+        let bytes = code.to_le_bytes();
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        ::std::str::from_utf8(&bytes[..end]).ok().and_then(Language::from_code)
+    }
+}
+
+/// A title entry: the application's name and publisher in one language.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Title {
+    pub name: String,
+    pub publisher: String,
+}
+
+impl Title {
+    pub fn new(name: &str, publisher: &str) -> Self {
+        Title { name: name.to_owned(), publisher: publisher.to_owned() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty()
+    }
+}
+
+/// The NACP struct. Single-byte settings are kept as their raw values; see
+/// switchbrew's NACP page for what each one means. Reserved bytes are not
+/// kept and are written as zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nacp {
+    /// Indexed by `Language::index`. Use `title` to pick an entry.
+    pub titles: [Title; LANGUAGE_COUNT],
+    pub isbn: String,
+    pub startup_user_account: u8,
+    pub user_account_switch_lock: u8,
+    pub add_on_content_registration_type: u8,
+    pub attribute_flag: u32,
+    pub supported_language_flag: u32,
+    pub parental_control_flag: u32,
+    pub screenshot: u8,
+    pub video_capture: u8,
+    pub data_loss_confirmation: u8,
+    pub play_log_policy: u8,
+    pub presence_group_id: u64,
+    /// Minimum age per rating organization, or -1 where unrated.
+    pub rating_age: [i8; 0x20],
+    pub display_version: String,
+    pub add_on_content_base_id: u64,
+    pub save_data_owner_id: u64,
+    pub user_account_save_data_size: i64,
+    pub user_account_save_data_journal_size: i64,
+    pub device_save_data_size: i64,
+    pub device_save_data_journal_size: i64,
+    pub bcat_delivery_cache_storage_size: i64,
+    pub application_error_code_category: String,
+    pub local_communication_id: [u64; 8],
+    pub logo_type: u8,
+    pub logo_handling: u8,
+    pub runtime_add_on_content_install: u8,
+    pub runtime_parameter_delivery: u8,
+    pub crash_report: u8,
+    pub hdcp: u8,
+    pub pseudo_device_id_seed: u64,
+    pub bcat_passphrase: String,
+    pub startup_user_account_option: u8,
+    pub user_account_save_data_size_max: i64,
+    pub user_account_save_data_journal_size_max: i64,
+    pub device_save_data_size_max: i64,
+    pub device_save_data_journal_size_max: i64,
+    pub temporary_storage_size: i64,
+    pub cache_storage_size: i64,
+    pub cache_storage_journal_size: i64,
+    pub cache_storage_data_and_journal_size_max: i64,
+    pub cache_storage_index_max: u16,
+    pub play_log_queryable_application_id: [u64; 16],
+    pub play_log_query_capability: u8,
+    pub repair_flag: u8,
+    pub program_index: u8,
+    pub required_network_service_license_on_launch: u8,
+    /// The rest of the struct, which newer firmware keeps adding fields to.
+    /// Written back as is, and zero-filled if shorter than that.
+    pub extra: Vec<u8>,
+}
+
+impl Default for Nacp {
+    /// An empty NACP with no ratings, as a starting point for generating one.
+    fn default() -> Self {
+        Nacp {
+            titles: Default::default(),
+            isbn: String::new(),
+            startup_user_account: 0,
+            user_account_switch_lock: 0,
+            add_on_content_registration_type: 0,
+            attribute_flag: 0,
+            supported_language_flag: 0,
+            parental_control_flag: 0,
+            screenshot: 0,
+            video_capture: 0,
+            data_loss_confirmation: 0,
+            play_log_policy: 0,
+            presence_group_id: 0,
+            rating_age: [-1; 0x20],
+            display_version: String::new(),
+            add_on_content_base_id: 0,
+            save_data_owner_id: 0,
+            user_account_save_data_size: 0,
+            user_account_save_data_journal_size: 0,
+            device_save_data_size: 0,
+            device_save_data_journal_size: 0,
+            bcat_delivery_cache_storage_size: 0,
+            application_error_code_category: String::new(),
+            local_communication_id: [0; 8],
+            logo_type: 0,
+            logo_handling: 0,
+            runtime_add_on_content_install: 0,
+            runtime_parameter_delivery: 0,
+            crash_report: 0,
+            hdcp: 0,
+            pseudo_device_id_seed: 0,
+            bcat_passphrase: String::new(),
+            startup_user_account_option: 0,
+            user_account_save_data_size_max: 0,
+            user_account_save_data_journal_size_max: 0,
+            device_save_data_size_max: 0,
+            device_save_data_journal_size_max: 0,
+            temporary_storage_size: 0,
+            cache_storage_size: 0,
+            cache_storage_journal_size: 0,
+            cache_storage_data_and_journal_size_max: 0,
+            cache_storage_index_max: 0,
+            play_log_queryable_application_id: [0; 16],
+            play_log_query_capability: 0,
+            repair_flag: 0,
+            program_index: 0,
+            required_network_service_license_on_launch: 0,
+            extra: vec![0; SIZE - KNOWN_END],
+        }
+    }
+}
+
+impl Nacp {
+    /// A NACP with the same title in every language, like nacptool makes.
+    pub fn new(name: &str, publisher: &str, display_version: &str) -> Self {
+        let mut nacp = Nacp::default();
+        nacp.set_all_titles(name, publisher);
+        nacp.display_version = display_version.to_owned();
+        nacp
+    }
+
+    /// The title to show for `lang`. Like libnx's `nacpGetLanguageEntry`,
+    /// this falls back to the first non-empty entry if there is none for
+    /// `lang`, and is `None` only if every entry is empty.
+    pub fn title(&self, lang: Language) -> Option<&Title> {
+        let entry = &self.titles[lang.index()];
+        if !entry.is_empty() {
+            return Some(entry);
+        }
+        self.titles.iter().find(|title| !title.is_empty())
+    }
+
+    /// Sets the title for `lang` and marks the language as supported.
+    pub fn set_title(&mut self, lang: Language, name: &str, publisher: &str) {
+        self.titles[lang.index()] = Title::new(name, publisher);
+        self.supported_language_flag |= 1 << lang.index();
+    }
+
+    pub fn set_all_titles(&mut self, name: &str, publisher: &str) {
+        for &lang in Language::ALL.iter() {
+            self.set_title(lang, name, publisher);
+        }
+    }
+
+    /// The languages marked as supported.
+    pub fn supported_languages(&self) -> Vec<Language> {
+        Language::ALL.iter()
+            .cloned()
+            .filter(|lang| self.supported_language_flag & (1 << lang.index()) != 0)
+            .collect()
+    }
+
+    /// Parses a NACP from the start of `buf`, which may be followed by other
+    /// data such as the icon in ns's control data.
+    pub fn parse(buf: &[u8]) -> io::Result<Self> {
+        if buf.len() < SIZE {
+            return Err(invalid_data("NACP is too small"));
+        }
+        let mut r = Reader::new(&buf[..SIZE]);
+        let mut nacp = Nacp::default();
+        for title in nacp.titles.iter_mut() {
+            title.name = r.str(NAME_LEN)?;
+            title.publisher = r.str(PUBLISHER_LEN)?;
+        }
+        nacp.isbn = r.str(0x25)?;
+        nacp.startup_user_account = r.u8()?;
+        nacp.user_account_switch_lock = r.u8()?;
+        nacp.add_on_content_registration_type = r.u8()?;
+        nacp.attribute_flag = r.u32()?;
+        nacp.supported_language_flag = r.u32()?;
+        nacp.parental_control_flag = r.u32()?;
+        nacp.screenshot = r.u8()?;
+        nacp.video_capture = r.u8()?;
+        nacp.data_loss_confirmation = r.u8()?;
+        nacp.play_log_policy = r.u8()?;
+        nacp.presence_group_id = r.u64()?;
+        for age in nacp.rating_age.iter_mut() {
+            *age = r.u8()? as i8;
+        }
+        nacp.display_version = r.str(0x10)?;
+        nacp.add_on_content_base_id = r.u64()?;
+        nacp.save_data_owner_id = r.u64()?;
+        nacp.user_account_save_data_size = r.u64()? as i64;
+        nacp.user_account_save_data_journal_size = r.u64()? as i64;
+        nacp.device_save_data_size = r.u64()? as i64;
+        nacp.device_save_data_journal_size = r.u64()? as i64;
+        nacp.bcat_delivery_cache_storage_size = r.u64()? as i64;
+        nacp.application_error_code_category = r.str(8)?;
+        for id in nacp.local_communication_id.iter_mut() {
+            *id = r.u64()?;
+        }
+        nacp.logo_type = r.u8()?;
+        nacp.logo_handling = r.u8()?;
+        nacp.runtime_add_on_content_install = r.u8()?;
+        nacp.runtime_parameter_delivery = r.u8()?;
+        r.pos += 2;
+        nacp.crash_report = r.u8()?;
+        nacp.hdcp = r.u8()?;
+        nacp.pseudo_device_id_seed = r.u64()?;
+        nacp.bcat_passphrase = r.str(0x41)?;
+        nacp.startup_user_account_option = r.u8()?;
+        r.pos += 6;
+        nacp.user_account_save_data_size_max = r.u64()? as i64;
+        nacp.user_account_save_data_journal_size_max = r.u64()? as i64;
+        nacp.device_save_data_size_max = r.u64()? as i64;
+        nacp.device_save_data_journal_size_max = r.u64()? as i64;
+        nacp.temporary_storage_size = r.u64()? as i64;
+        nacp.cache_storage_size = r.u64()? as i64;
+        nacp.cache_storage_journal_size = r.u64()? as i64;
+        nacp.cache_storage_data_and_journal_size_max = r.u64()? as i64;
+        nacp.cache_storage_index_max = r.u16()?;
+        r.pos += 6;
+        for id in nacp.play_log_queryable_application_id.iter_mut() {
+            *id = r.u64()?;
+        }
+        nacp.play_log_query_capability = r.u8()?;
+        nacp.repair_flag = r.u8()?;
+        nacp.program_index = r.u8()?;
+        nacp.required_network_service_license_on_launch = r.u8()?;
+        debug_assert_eq!(r.pos, KNOWN_END);
+        nacp.extra = r.bytes(SIZE - KNOWN_END)?.to_vec();
+        Ok(nacp)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(SIZE);
+        for title in self.titles.iter() {
+            buf.put_str(&title.name, NAME_LEN, "NACP title name is too long")?;
+            buf.put_str(&title.publisher, PUBLISHER_LEN, "NACP publisher is too long")?;
+        }
+        buf.put_str(&self.isbn, 0x25, "NACP ISBN is too long")?;
+        buf.put_u8(self.startup_user_account);
+        buf.put_u8(self.user_account_switch_lock);
+        buf.put_u8(self.add_on_content_registration_type);
+        buf.put_u32(self.attribute_flag);
+        buf.put_u32(self.supported_language_flag);
+        buf.put_u32(self.parental_control_flag);
+        buf.put_u8(self.screenshot);
+        buf.put_u8(self.video_capture);
+        buf.put_u8(self.data_loss_confirmation);
+        buf.put_u8(self.play_log_policy);
+        buf.put_u64(self.presence_group_id);
+        for &age in self.rating_age.iter() {
+            buf.put_u8(age as u8);
+        }
+        buf.put_str(&self.display_version, 0x10, "NACP display version is too long")?;
+        buf.put_u64(self.add_on_content_base_id);
+        buf.put_u64(self.save_data_owner_id);
+        buf.put_u64(self.user_account_save_data_size as u64);
+        buf.put_u64(self.user_account_save_data_journal_size as u64);
+        buf.put_u64(self.device_save_data_size as u64);
+        buf.put_u64(self.device_save_data_journal_size as u64);
+        buf.put_u64(self.bcat_delivery_cache_storage_size as u64);
+        buf.put_str(&self.application_error_code_category, 8,
+                    "NACP application error code category is too long")?;
+        for &id in self.local_communication_id.iter() {
+            buf.put_u64(id);
+        }
+        buf.put_u8(self.logo_type);
+        buf.put_u8(self.logo_handling);
+        buf.put_u8(self.runtime_add_on_content_install);
+        buf.put_u8(self.runtime_parameter_delivery);
+        buf.put_u16(0);
+        buf.put_u8(self.crash_report);
+        buf.put_u8(self.hdcp);
+        buf.put_u64(self.pseudo_device_id_seed);
+        buf.put_str(&self.bcat_passphrase, 0x41, "NACP BCAT passphrase is too long")?;
+        buf.put_u8(self.startup_user_account_option);
+        let len = buf.len();
+        buf.pad_to(len + 6);
+        buf.put_u64(self.user_account_save_data_size_max as u64);
+        buf.put_u64(self.user_account_save_data_journal_size_max as u64);
+        buf.put_u64(self.device_save_data_size_max as u64);
+        buf.put_u64(self.device_save_data_journal_size_max as u64);
+        buf.put_u64(self.temporary_storage_size as u64);
+        buf.put_u64(self.cache_storage_size as u64);
+        buf.put_u64(self.cache_storage_journal_size as u64);
+        buf.put_u64(self.cache_storage_data_and_journal_size_max as u64);
+        buf.put_u16(self.cache_storage_index_max);
+        let len = buf.len();
+        buf.pad_to(len + 6);
+        for &id in self.play_log_queryable_application_id.iter() {
+            buf.put_u64(id);
+        }
+        buf.put_u8(self.play_log_query_capability);
+        buf.put_u8(self.repair_flag);
+        buf.put_u8(self.program_index);
+        buf.put_u8(self.required_network_service_license_on_launch);
+        debug_assert_eq!(buf.len(), KNOWN_END);
+        if self.extra.len() > SIZE - KNOWN_END {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "NACP extra data is too long"));
+        }
+        buf.extend_from_slice(&self.extra);
+        buf.pad_to(SIZE);
+        Ok(buf)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_bytes()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut nacp = Nacp::new("Hello", "Someone", "1.2.3");
+        nacp.set_title(Language::Japanese, "こんにちは", "誰か");
+        nacp.user_account_save_data_size = 0x40_0000;
+        nacp.rating_age[0] = 12;
+        nacp.play_log_queryable_application_id[15] = 0x0100_0000_0000_1000;
+        nacp.required_network_service_license_on_launch = 1;
+        nacp.extra[0] = 0x5a;
+
+        let bytes = nacp.to_bytes().unwrap();
+        assert_eq!(bytes.len(), SIZE);
+        assert_eq!(&bytes[..5], b"Hello");
+        assert_eq!(&bytes[0x3060..0x3065], b"1.2.3");
+        assert_eq!(bytes[0x3040], 12);
+        assert_eq!(bytes[0x3041], 0xff);
+        assert_eq!(bytes[0x3213], 1);
+        assert_eq!(bytes[KNOWN_END], 0x5a);
+        assert_eq!(Nacp::parse(&bytes).unwrap(), nacp);
+    }
+
+    #[test]
+    fn picks_title() {
+        let mut nacp = Nacp::default();
+        assert_eq!(nacp.title(Language::French), None);
+
+        nacp.set_title(Language::German, "Hallo", "Jemand");
+        assert_eq!(nacp.title(Language::French).unwrap().name, "Hallo");
+
+        nacp.set_title(Language::French, "Bonjour", "Quelqu'un");
+        assert_eq!(nacp.title(Language::French).unwrap().name, "Bonjour");
+        assert_eq!(nacp.supported_languages(), vec![Language::French, Language::German]);
+    }
+
+    #[test]
+    fn maps_language_codes() {
+        assert_eq!(Language::from_code("fr-CA"), Some(Language::CanadianFrench));
+        assert_eq!(Language::from_code("zh-Hans"), Some(Language::SimplifiedChinese));
+        assert_eq!(Language::from_code("xx"), None);
+        let packed = u64::from_le_bytes(*b"en-GB\0\0\0");
+        assert_eq!(Language::from_language_code(packed), Some(Language::BritishEnglish));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(Nacp::parse(&[0; 0x100]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let nacp = Nacp::new("Hello", "Someone", "1.0.0-a-long-version");
+        assert_eq!(nacp.to_bytes().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,674 @@
+//! NPDM, the program metadata (`main.npdm`) the loader reads to create a
+//! process: its main thread's parameters, and the ACI0 and ACID sections
+//! granting it filesystem access, services and kernel capabilities.
+//!
+//! ACI0 holds what the program asks for and ACID what it is allowed, signed
+//! by Nintendo for official titles. Homebrew sysmodules usually make both
+//! the same and leave the signature empty.
+
+use std::io::{self, Read, Write};
+
+use super::{align_up, invalid_data, Reader, WriteLe};
+
+pub const MAGIC: &[u8; 4] = b"META";
+pub const ACI0_MAGIC: &[u8; 4] = b"ACI0";
+pub const ACID_MAGIC: &[u8; 4] = b"ACID";
+const META_SIZE: usize = 0x80;
+const ACI0_HEADER_SIZE: usize = 0x40;
+const ACID_HEADER_SIZE: usize = 0x240;
+/// Sections and the tables inside them start at this alignment.
+const SECTION_ALIGN: usize = 0x10;
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Filesystem permissions: ACID's access control or ACI0's access header.
+///
+/// Only the version and permission bits are interpreted; the owner ID data
+/// after them is kept as stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FsAccess {
+    pub raw: Vec<u8>,
+}
+
+impl FsAccess {
+    /// ACID's access control with `permissions` and no owner ID ranges.
+    pub fn acid(permissions: u64) -> Self {
+        let mut raw = vec![1, 0, 0, 0];
+        raw.put_u64(permissions);
+        raw.pad_to(0x2c);
+        FsAccess { raw }
+    }
+
+    /// ACI0's access header with `permissions` and no owner info.
+    pub fn aci0(permissions: u64) -> Self {
+        let mut raw = vec![1, 0, 0, 0];
+        raw.put_u64(permissions);
+        for _ in 0..2 {
+            raw.put_u32(0x1c);
+            raw.put_u32(0);
+        }
+        FsAccess { raw }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.raw.first().cloned().unwrap_or(0)
+    }
+
+    pub fn permissions(&self) -> u64 {
+        Reader::at(&self.raw, 4).u64().unwrap_or(0)
+    }
+
+    pub fn set_permissions(&mut self, permissions: u64) {
+        self.raw.pad_to(0xc);
+        self.raw[4..0xc].copy_from_slice(&permissions.to_le_bytes());
+    }
+}
+
+/// An entry in a service access list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServiceAccess {
+    /// Up to eight characters. A trailing `*` matches any suffix.
+    pub name: String,
+    /// Whether the program may register the service rather than connect
+    /// to it.
+    pub is_server: bool,
+}
+
+impl ServiceAccess {
+    pub fn client(name: &str) -> Self {
+        ServiceAccess { name: name.to_owned(), is_server: false }
+    }
+
+    pub fn server(name: &str) -> Self {
+        ServiceAccess { name: name.to_owned(), is_server: true }
+    }
+
+    pub fn decode(buf: &[u8]) -> io::Result<Vec<Self>> {
+        let mut r = Reader::new(buf);
+        let mut services = Vec::new();
+        while r.pos < buf.len() {
+            let control = r.u8()?;
+            let name = r.bytes((control & 7) as usize + 1)?;
+            services.push(ServiceAccess {
+                name: String::from_utf8_lossy(name).into_owned(),
+                is_server: control & 0x80 != 0,
+            });
+        }
+        Ok(services)
+    }
+
+    pub fn encode(services: &[Self]) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        for service in services {
+            let len = service.name.len();
+            if len == 0 || len > 8 || !service.name.is_ascii() {
+                return Err(invalid_input("service names must be 1 to 8 ASCII characters"));
+            }
+            buf.put_u8((len - 1) as u8 | if service.is_server { 0x80 } else { 0 });
+            buf.extend_from_slice(service.name.as_bytes());
+        }
+        Ok(buf)
+    }
+}
+
+/// A kernel capability descriptor. Each kind is identified by the number of
+/// consecutive set bits at the bottom of the descriptor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KernelCapability {
+    /// The allowed thread priorities and cores. Priorities are numeric, so
+    /// `max_priority` is the lowest priority a thread can have.
+    ThreadInfo { max_priority: u8, min_priority: u8, min_core: u8, max_core: u8 },
+    /// Allows the set bits of `mask`, which cover syscalls `index * 24` to
+    /// `index * 24 + 23`. See `KernelCapability::syscalls`.
+    SyscallMask { index: u8, mask: u32 },
+    /// Maps a range of physical memory. Takes two descriptors.
+    MapRange { address: u64, size: u64, read_only: bool, io: bool },
+    /// Maps a single page of IO memory.
+    MapPage { address: u64 },
+    /// Allows binding up to two interrupts.
+    Interrupts(Option<u16>, Option<u16>),
+    ProgramType(u8),
+    KernelVersion { major: u16, minor: u8 },
+    HandleTableSize(u16),
+    DebugFlags { allow_debug: bool, force_debug: bool },
+    /// A descriptor of a kind not modeled here, including padding, kept as
+    /// is.
+    Raw(u32),
+}
+
+const PAGE_SHIFT: u32 = 12;
+const NO_INTERRUPT: u32 = 0x3ff;
+
+/// The marker of a descriptor kind with `ones` low set bits.
+fn kind(ones: u32) -> u32 {
+    (1 << ones) - 1
+}
+
+/// `value` shifted into a field of `bits` bits at `shift`, or an error if it
+/// doesn't fit.
+fn field(value: u64, bits: u32, shift: u32, what: &str) -> io::Result<u32> {
+    if value >> bits != 0 {
+        return Err(invalid_input(what));
+    }
+    Ok((value as u32) << shift)
+}
+
+fn get(desc: u32, bits: u32, shift: u32) -> u32 {
+    (desc >> shift) & ((1 << bits) - 1)
+}
+
+impl KernelCapability {
+    /// Syscall masks allowing exactly the syscalls in `ids`.
+    pub fn syscalls(ids: &[u32]) -> io::Result<Vec<Self>> {
+        let mut masks = [0u32; 8];
+        for &id in ids {
+            let index = id as usize / 24;
+            if index >= masks.len() {
+                return Err(invalid_input("syscall ID is out of range"));
+            }
+            masks[index] |= 1 << (id % 24);
+        }
+        Ok(masks.iter()
+            .enumerate()
+            .filter(|&(_, &mask)| mask != 0)
+            .map(|(index, &mask)| KernelCapability::SyscallMask { index: index as u8, mask })
+            .collect())
+    }
+
+    /// The syscalls allowed by the masks in `caps`, in ascending order.
+    pub fn syscall_ids(caps: &[Self]) -> Vec<u32> {
+        let mut ids = Vec::new();
+        for cap in caps {
+            if let KernelCapability::SyscallMask { index, mask } = *cap {
+                ids.extend((0..24).filter(|bit| mask & (1 << bit) != 0).map(|bit| index as u32 * 24 + bit));
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    pub fn decode(descriptors: &[u32]) -> io::Result<Vec<Self>> {
+        let mut caps = Vec::with_capacity(descriptors.len());
+        let mut iter = descriptors.iter().cloned();
+        while let Some(desc) = iter.next() {
+            let cap = match (!desc).trailing_zeros() {
+                3 => KernelCapability::ThreadInfo {
+                    max_priority: get(desc, 6, 4) as u8,
+                    min_priority: get(desc, 6, 10) as u8,
+                    min_core: get(desc, 8, 16) as u8,
+                    max_core: get(desc, 8, 24) as u8,
+                },
+                4 => KernelCapability::SyscallMask { index: get(desc, 3, 29) as u8, mask: get(desc, 24, 5) },
+                6 => {
+                    let size = iter.next()
+                        .filter(|size| (!size).trailing_zeros() == 6)
+                        .ok_or_else(|| invalid_data("memory map descriptor is missing its size"))?;
+                    KernelCapability::MapRange {
+                        address: (get(desc, 24, 7) as u64) << PAGE_SHIFT,
+                        size: (get(size, 20, 7) as u64) << PAGE_SHIFT,
+                        read_only: desc >> 31 != 0,
+                        io: size >> 31 == 0,
+                    }
+                }
+                7 => KernelCapability::MapPage { address: (get(desc, 24, 8) as u64) << PAGE_SHIFT },
+                11 => {
+                    let irq = |shift| Some(get(desc, 10, shift)).filter(|&irq| irq != NO_INTERRUPT).map(|irq| irq as u16);
+                    KernelCapability::Interrupts(irq(12), irq(22))
+                }
+                13 => KernelCapability::ProgramType(get(desc, 3, 14) as u8),
+                14 => KernelCapability::KernelVersion { major: get(desc, 13, 19) as u16, minor: get(desc, 4, 15) as u8 },
+                15 => KernelCapability::HandleTableSize(get(desc, 10, 16) as u16),
+                16 => KernelCapability::DebugFlags {
+                    allow_debug: get(desc, 1, 17) != 0,
+                    force_debug: get(desc, 1, 18) != 0,
+                },
+                _ => KernelCapability::Raw(desc),
+            };
+            caps.push(cap);
+        }
+        Ok(caps)
+    }
+
+    pub fn encode(caps: &[Self]) -> io::Result<Vec<u32>> {
+        let mut descriptors = Vec::with_capacity(caps.len());
+        for cap in caps {
+            match *cap {
+                KernelCapability::ThreadInfo { max_priority, min_priority, min_core, max_core } => {
+                    descriptors.push(kind(3)
+                        | field(max_priority as u64, 6, 4, "thread priority is out of range")?
+                        | field(min_priority as u64, 6, 10, "thread priority is out of range")?
+                        | (min_core as u32) << 16
+                        | (max_core as u32) << 24);
+                }
+                KernelCapability::SyscallMask { index, mask } => {
+                    descriptors.push(kind(4)
+                        | field(mask as u64, 24, 5, "syscall mask is out of range")?
+                        | field(index as u64, 3, 29, "syscall mask index is out of range")?);
+                }
+                KernelCapability::MapRange { address, size, read_only, io } => {
+                    if (address | size) & ((1 << PAGE_SHIFT) - 1) != 0 {
+                        return Err(invalid_input("memory map is not page-aligned"));
+                    }
+                    descriptors.push(kind(6)
+                        | field(address >> PAGE_SHIFT, 24, 7, "memory map address is out of range")?
+                        | (read_only as u32) << 31);
+                    descriptors.push(kind(6)
+                        | field(size >> PAGE_SHIFT, 20, 7, "memory map size is out of range")?
+                        | (!io as u32) << 31);
+                }
+                KernelCapability::MapPage { address } => {
+                    if address & ((1 << PAGE_SHIFT) - 1) != 0 {
+                        return Err(invalid_input("IO page is not page-aligned"));
+                    }
+                    descriptors.push(kind(7) | field(address >> PAGE_SHIFT, 24, 8, "IO page is out of range")?);
+                }
+                KernelCapability::Interrupts(first, second) => {
+                    let irq = |irq: Option<u16>, shift: u32| -> io::Result<u32> {
+                        match irq {
+                            Some(irq) if irq as u32 >= NO_INTERRUPT => Err(invalid_input("interrupt is out of range")),
+                            Some(irq) => Ok((irq as u32) << shift),
+                            None => Ok(NO_INTERRUPT << shift),
+                        }
+                    };
+                    descriptors.push(kind(11) | irq(first, 12)? | irq(second, 22)?);
+                }
+                KernelCapability::ProgramType(ty) => {
+                    descriptors.push(kind(13) | field(ty as u64, 3, 14, "program type is out of range")?);
+                }
+                KernelCapability::KernelVersion { major, minor } => {
+                    descriptors.push(kind(14)
+                        | field(minor as u64, 4, 15, "kernel minor version is out of range")?
+                        | field(major as u64, 13, 19, "kernel major version is out of range")?);
+                }
+                KernelCapability::HandleTableSize(size) => {
+                    descriptors.push(kind(15) | field(size as u64, 10, 16, "handle table size is out of range")?);
+                }
+                KernelCapability::DebugFlags { allow_debug, force_debug } => {
+                    descriptors.push(kind(16) | (allow_debug as u32) << 17 | (force_debug as u32) << 18);
+                }
+                KernelCapability::Raw(desc) => descriptors.push(desc),
+            }
+        }
+        Ok(descriptors)
+    }
+
+    fn decode_bytes(buf: &[u8]) -> io::Result<Vec<Self>> {
+        let mut r = Reader::new(buf);
+        let descriptors = (0..buf.len() / 4).map(|_| r.u32()).collect::<io::Result<Vec<_>>>()?;
+        KernelCapability::decode(&descriptors)
+    }
+
+    fn encode_bytes(caps: &[Self]) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        for desc in KernelCapability::encode(caps)? {
+            buf.put_u32(desc);
+        }
+        Ok(buf)
+    }
+}
+
+/// The ACI0 section: what the program requests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Aci0 {
+    pub program_id: u64,
+    pub fs_access: FsAccess,
+    pub services: Vec<ServiceAccess>,
+    pub kernel_capabilities: Vec<KernelCapability>,
+}
+
+/// The ACID section: what the program is allowed, and the signature over it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Acid {
+    /// An RSA-2048 signature over the rest of the section, from the public
+    /// key onwards. Zeroes when unsigned.
+    pub signature: Vec<u8>,
+    /// The RSA-2048 modulus NCA header signatures are checked against.
+    pub public_key: Vec<u8>,
+    pub version: u8,
+    /// Bit 0 is production, bit 1 unqualified approval and bits 2-5 the
+    /// memory region.
+    pub flags: u32,
+    pub program_id_min: u64,
+    pub program_id_max: u64,
+    pub fs_access: FsAccess,
+    pub services: Vec<ServiceAccess>,
+    pub kernel_capabilities: Vec<KernelCapability>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Npdm {
+    pub signature_key_generation: u8,
+    /// Bit 0 is a 64-bit instruction set and bits 1-3 the address space
+    /// type.
+    pub flags: u8,
+    pub main_thread_priority: u8,
+    pub main_thread_core: u8,
+    pub system_resource_size: u32,
+    pub version: u32,
+    pub main_thread_stack_size: u32,
+    pub name: String,
+    pub product_code: String,
+    pub aci0: Aci0,
+    pub acid: Acid,
+}
+
+/// Reads an offset and size pair at `r` and returns the part of `buf` it
+/// points at.
+fn table<'a>(r: &mut Reader, buf: &'a [u8]) -> io::Result<&'a [u8]> {
+    let offset = r.u32()? as usize;
+    let size = r.u32()? as usize;
+    Reader::at(buf, offset).bytes(size)
+}
+
+fn set_u32(buf: &mut [u8], pos: usize, value: u32) {
+    buf[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Appends `tables` to `buf`, which holds a section header, and fills in the
+/// header's offset and size pairs starting at `pos`.
+fn put_tables(buf: &mut Vec<u8>, mut pos: usize, tables: &[&[u8]]) {
+    for table in tables {
+        let offset = align_up(buf.len(), SECTION_ALIGN);
+        buf.pad_to(offset);
+        buf.extend_from_slice(table);
+        set_u32(buf, pos, offset as u32);
+        set_u32(buf, pos + 4, table.len() as u32);
+        pos += 8;
+    }
+}
+
+impl Aci0 {
+    fn parse(section: &[u8]) -> io::Result<Self> {
+        let mut r = Reader::new(section);
+        r.magic(ACI0_MAGIC, "missing ACI0 magic")?;
+        r.pos = 0x10;
+        let program_id = r.u64()?;
+        r.pos = 0x20;
+        Ok(Aci0 {
+            program_id,
+            fs_access: FsAccess { raw: table(&mut r, section)?.to_vec() },
+            services: ServiceAccess::decode(table(&mut r, section)?)?,
+            kernel_capabilities: KernelCapability::decode_bytes(table(&mut r, section)?)?,
+        })
+    }
+
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(ACI0_HEADER_SIZE);
+        buf.extend_from_slice(ACI0_MAGIC);
+        buf.pad_to(0x10);
+        buf.put_u64(self.program_id);
+        buf.pad_to(ACI0_HEADER_SIZE);
+        let services = ServiceAccess::encode(&self.services)?;
+        let caps = KernelCapability::encode_bytes(&self.kernel_capabilities)?;
+        put_tables(&mut buf, 0x20, &[&self.fs_access.raw, &services, &caps]);
+        Ok(buf)
+    }
+}
+
+impl Acid {
+    fn parse(section: &[u8]) -> io::Result<Self> {
+        let mut r = Reader::new(section);
+        let signature = r.bytes(0x100)?.to_vec();
+        let public_key = r.bytes(0x100)?.to_vec();
+        r.magic(ACID_MAGIC, "missing ACID magic")?;
+        let _size = r.u32()?;
+        let version = r.u8()?;
+        r.pos += 3;
+        let flags = r.u32()?;
+        let program_id_min = r.u64()?;
+        let program_id_max = r.u64()?;
+        Ok(Acid {
+            signature,
+            public_key,
+            version,
+            flags,
+            program_id_min,
+            program_id_max,
+            fs_access: FsAccess { raw: table(&mut r, section)?.to_vec() },
+            services: ServiceAccess::decode(table(&mut r, section)?)?,
+            kernel_capabilities: KernelCapability::decode_bytes(table(&mut r, section)?)?,
+        })
+    }
+
+    fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        if self.signature.len() != 0x100 || self.public_key.len() != 0x100 {
+            return Err(invalid_input("ACID signature and public key must be 0x100 bytes"));
+        }
+        let mut buf = Vec::with_capacity(ACID_HEADER_SIZE);
+        buf.extend_from_slice(&self.signature);
+        buf.extend_from_slice(&self.public_key);
+        buf.extend_from_slice(ACID_MAGIC);
+        buf.put_u32(0);
+        buf.put_u8(self.version);
+        buf.pad_to(0x20c);
+        buf.put_u32(self.flags);
+        buf.put_u64(self.program_id_min);
+        buf.put_u64(self.program_id_max);
+        buf.pad_to(ACID_HEADER_SIZE);
+        let services = ServiceAccess::encode(&self.services)?;
+        let caps = KernelCapability::encode_bytes(&self.kernel_capabilities)?;
+        put_tables(&mut buf, 0x220, &[&self.fs_access.raw, &services, &caps]);
+        // The size covers everything after the signature.
+        let size = (buf.len() - 0x100) as u32;
+        set_u32(&mut buf, 0x204, size);
+        Ok(buf)
+    }
+}
+
+impl Npdm {
+    /// An unsigned NPDM for a 64-bit program whose ACI0 and ACID grant the
+    /// same access.
+    pub fn new(name: &str, program_id: u64, fs_access: u64, services: Vec<ServiceAccess>,
+               kernel_capabilities: Vec<KernelCapability>) -> Self {
+        Npdm {
+            signature_key_generation: 0,
+            flags: 0b111,
+            main_thread_priority: 44,
+            main_thread_core: 3,
+            system_resource_size: 0,
+            version: 0,
+            main_thread_stack_size: 0x10000,
+            name: name.to_owned(),
+            product_code: String::new(),
+            aci0: Aci0 {
+                program_id,
+                fs_access: FsAccess::aci0(fs_access),
+                services: services.clone(),
+                kernel_capabilities: kernel_capabilities.clone(),
+            },
+            acid: Acid {
+                signature: vec![0; 0x100],
+                public_key: vec![0; 0x100],
+                version: 0,
+                flags: 0,
+                program_id_min: program_id,
+                program_id_max: program_id,
+                fs_access: FsAccess::acid(fs_access),
+                services,
+                kernel_capabilities,
+            },
+        }
+    }
+
+    pub fn parse(buf: &[u8]) -> io::Result<Self> {
+        let mut r = Reader::new(buf);
+        r.magic(MAGIC, "missing META magic")?;
+        let signature_key_generation = r.u8()?;
+        r.pos = 0xc;
+        let flags = r.u8()?;
+        r.pos += 1;
+        let main_thread_priority = r.u8()?;
+        let main_thread_core = r.u8()?;
+        r.pos += 4;
+        let system_resource_size = r.u32()?;
+        let version = r.u32()?;
+        let main_thread_stack_size = r.u32()?;
+        let name = r.str(0x10)?;
+        let product_code = r.str(0x10)?;
+        r.pos = 0x70;
+        let aci0 = Aci0::parse(table(&mut r, buf)?)?;
+        let acid = Acid::parse(table(&mut r, buf)?)?;
+        Ok(Npdm {
+            signature_key_generation,
+            flags,
+            main_thread_priority,
+            main_thread_core,
+            system_resource_size,
+            version,
+            main_thread_stack_size,
+            name,
+            product_code,
+            aci0,
+            acid,
+        })
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Self::parse(&buf)
+    }
+
+    /// Lays out the NPDM as npdmtool does: the META header, then ACID, then
+    /// ACI0.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(META_SIZE);
+        buf.extend_from_slice(MAGIC);
+        buf.put_u8(self.signature_key_generation);
+        buf.pad_to(0xc);
+        buf.put_u8(self.flags);
+        buf.put_u8(0);
+        buf.put_u8(self.main_thread_priority);
+        buf.put_u8(self.main_thread_core);
+        buf.put_u32(0);
+        buf.put_u32(self.system_resource_size);
+        buf.put_u32(self.version);
+        buf.put_u32(self.main_thread_stack_size);
+        buf.put_str(&self.name, 0x10, "NPDM name is too long")?;
+        buf.put_str(&self.product_code, 0x10, "NPDM product code is too long")?;
+        buf.pad_to(META_SIZE);
+
+        let acid = self.acid.to_bytes()?;
+        let aci0 = self.aci0.to_bytes()?;
+        let acid_offset = META_SIZE;
+        let aci0_offset = align_up(acid_offset + acid.len(), SECTION_ALIGN);
+        set_u32(&mut buf, 0x70, aci0_offset as u32);
+        set_u32(&mut buf, 0x74, aci0.len() as u32);
+        set_u32(&mut buf, 0x78, acid_offset as u32);
+        set_u32(&mut buf, 0x7c, acid.len() as u32);
+        buf.extend_from_slice(&acid);
+        buf.pad_to(aci0_offset);
+        buf.extend_from_slice(&aci0);
+        Ok(buf)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_bytes()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps() -> Vec<KernelCapability> {
+        let mut caps = vec![
+            KernelCapability::ThreadInfo { max_priority: 63, min_priority: 44, min_core: 0, max_core: 3 },
+            KernelCapability::MapRange { address: 0x7000_0000, size: 0x1000, read_only: false, io: false },
+            KernelCapability::MapPage { address: 0x6000_6000 },
+            KernelCapability::Interrupts(Some(0x20), None),
+            KernelCapability::ProgramType(1),
+            KernelCapability::KernelVersion { major: 9, minor: 0 },
+            KernelCapability::HandleTableSize(512),
+            KernelCapability::DebugFlags { allow_debug: true, force_debug: false },
+        ];
+        caps.extend(KernelCapability::syscalls(&[0x01, 0x02, 0x1f, 0x7f]).unwrap());
+        caps
+    }
+
+    #[test]
+    fn encodes_kernel_capabilities() {
+        let descriptors = KernelCapability::encode(&caps()).unwrap();
+        assert_eq!(descriptors, [
+            3 << 24 | 44 << 10 | 63 << 4 | 0x7,
+            0x70000 << 7 | 0x3f,
+            1 << 31 | 1 << 7 | 0x3f,
+            0x60006 << 8 | 0x7f,
+            0x3ff << 22 | 0x20 << 12 | 0x7ff,
+            1 << 14 | 0x1fff,
+            9 << 19 | 0x3fff,
+            512 << 16 | 0x7fff,
+            1 << 17 | 0xffff,
+            0b110 << 5 | 0xf,
+            1 << 29 | 1 << (7 + 5) | 0xf,
+            5 << 29 | 1 << (7 + 5) | 0xf,
+        ]);
+        assert_eq!(KernelCapability::decode(&descriptors).unwrap(), caps());
+        assert_eq!(KernelCapability::syscall_ids(&caps()), [0x01, 0x02, 0x1f, 0x7f]);
+    }
+
+    #[test]
+    fn keeps_unknown_descriptors() {
+        let descriptors = [0xffff_ffff, 0x1ff | 1 << 12];
+        let caps = KernelCapability::decode(&descriptors).unwrap();
+        assert_eq!(caps, [KernelCapability::Raw(0xffff_ffff), KernelCapability::Raw(0x1ff | 1 << 12)]);
+        assert_eq!(KernelCapability::encode(&caps).unwrap(), descriptors);
+    }
+
+    #[test]
+    fn rejects_bad_kernel_capabilities() {
+        let bad = [
+            KernelCapability::ThreadInfo { max_priority: 64, min_priority: 0, min_core: 0, max_core: 3 },
+            KernelCapability::MapRange { address: 0x123, size: 0x1000, read_only: false, io: true },
+            KernelCapability::Interrupts(Some(0x3ff), None),
+            KernelCapability::HandleTableSize(1024),
+        ];
+        for cap in &bad {
+            assert_eq!(KernelCapability::encode(&[*cap]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+        assert!(KernelCapability::syscalls(&[0xc0]).is_err());
+        assert!(KernelCapability::decode(&[0x3f]).is_err());
+    }
+
+    #[test]
+    fn encodes_services() {
+        let services = vec![ServiceAccess::client("fsp-srv"), ServiceAccess::server("test:u"), ServiceAccess::client("*")];
+        let bytes = ServiceAccess::encode(&services).unwrap();
+        assert_eq!(&bytes[..8], b"\x06fsp-srv");
+        assert_eq!(bytes[8], 0x85);
+        assert_eq!(ServiceAccess::decode(&bytes).unwrap(), services);
+        assert!(ServiceAccess::encode(&[ServiceAccess::client("too-long-name")]).is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        let services = vec![ServiceAccess::client("sm:"), ServiceAccess::server("test")];
+        let mut npdm = Npdm::new("test", 0x0100_0000_0000_1337, 0xffff_ffff_ffff_ffff, services, caps());
+        npdm.acid.flags = 1 << 2;
+
+        let bytes = npdm.to_bytes().unwrap();
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(&bytes[0x80 + 0x200..0x80 + 0x204], ACID_MAGIC);
+        let aci0_offset = Reader::at(&bytes, 0x70).u32().unwrap() as usize;
+        assert_eq!(aci0_offset % SECTION_ALIGN, 0);
+        assert_eq!(&bytes[aci0_offset..aci0_offset + 4], ACI0_MAGIC);
+        assert_eq!(Reader::at(&bytes, 0x80 + 0x240).u64().unwrap() >> 32, 0xffff_ffff);
+
+        let parsed = Npdm::parse(&bytes).unwrap();
+        assert_eq!(parsed, npdm);
+        assert_eq!(parsed.aci0.fs_access.permissions(), 0xffff_ffff_ffff_ffff);
+        assert_eq!(parsed.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let npdm = Npdm::new("test", 1, 0, vec![], vec![]);
+        let mut bytes = npdm.to_bytes().unwrap();
+        assert!(Npdm::parse(&bytes[..0x100]).is_err());
+        bytes[0x80 + 0x200] = b'X';
+        assert_eq!(Npdm::parse(&bytes).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,391 @@
+//! NRO, the relocatable executable format homebrew is distributed in.
+//!
+//! An NRO is the text, ro and data segments laid out back to back as they are
+//! mapped, with the header embedded in the first bytes of text. It can be
+//! followed by an asset section holding the icon, NACP and RomFS shown and
+//! mounted by the homebrew menu.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::mod0::Mod0;
+use super::{align_up, invalid_data, Reader, WriteLe};
+
+pub const MAGIC: &[u8; 4] = b"NRO0";
+pub const ASSET_MAGIC: &[u8; 4] = b"ASET";
+/// Where the header starts. The bytes before it are the branch to the entry
+/// point and the MOD0 offset.
+pub const HEADER_OFFSET: usize = 0x10;
+/// Where the header ends, i.e. the smallest possible text segment.
+pub const HEADER_END: usize = 0x80;
+pub const ASSET_HEADER_SIZE: usize = 0x38;
+/// Segments are padded to the page size so they can be mapped in place.
+pub const SEGMENT_ALIGN: usize = 0x1000;
+
+/// An offset and size pair.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Extent {
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// The contents of the asset section. Empty fields are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Assets {
+    /// A 256x256 JPEG.
+    pub icon: Vec<u8>,
+    /// The application's control properties, see `formats::nacp`.
+    pub nacp: Vec<u8>,
+    pub romfs: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nro {
+    /// The text segment. Bytes `HEADER_OFFSET..HEADER_END` hold the header,
+    /// and are replaced with the current one when writing.
+    pub text: Vec<u8>,
+    pub ro: Vec<u8>,
+    pub data: Vec<u8>,
+    pub bss_size: u32,
+    pub flags: u32,
+    pub module_id: [u8; 0x20],
+    pub dso_handle_offset: u32,
+    /// The API info, `.dynstr` and `.dynsym` sections, relative to the start
+    /// of the ro segment.
+    pub api_info: Extent,
+    pub dynstr: Extent,
+    pub dynsym: Extent,
+    pub assets: Option<Assets>,
+}
+
+impl Nro {
+    /// An NRO with the given segments and nothing else. `text` must at least
+    /// have room for the header.
+    pub fn new(text: Vec<u8>, ro: Vec<u8>, data: Vec<u8>) -> Self {
+        Nro {
+            text,
+            ro,
+            data,
+            bss_size: 0,
+            flags: 0,
+            module_id: [0; 0x20],
+            dso_handle_offset: 0,
+            api_info: Extent::default(),
+            dynstr: Extent::default(),
+            dynsym: Extent::default(),
+            assets: None,
+        }
+    }
+
+    /// The MOD0 header's offset from the start of text, stored at 0x4.
+    pub fn mod0_offset(&self) -> u32 {
+        Reader::at(&self.text, 4).u32().unwrap_or(0)
+    }
+
+    pub fn set_mod0_offset(&mut self, offset: u32) {
+        if self.text.len() < 8 {
+            self.text.resize(8, 0);
+        }
+        self.text[4..8].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    /// The segments as they are laid out in memory, without the BSS.
+    pub fn image(&self) -> Vec<u8> {
+        let mut image = Vec::with_capacity(self.segment_extents()[2].offset as usize + self.data.len());
+        for segment in &[&self.text, &self.ro, &self.data] {
+            image.pad_to(align_up(image.len(), SEGMENT_ALIGN));
+            image.extend_from_slice(segment);
+        }
+        image
+    }
+
+    pub fn mod0(&self) -> io::Result<Mod0> {
+        Mod0::parse(&self.image(), self.mod0_offset() as usize)
+    }
+
+    fn segment_extents(&self) -> [Extent; 3] {
+        let mut offset = 0;
+        let mut extents = [Extent::default(); 3];
+        for (extent, segment) in extents.iter_mut().zip(&[&self.text, &self.ro, &self.data]) {
+            let size = align_up(segment.len(), SEGMENT_ALIGN);
+            *extent = Extent { offset: offset as u32, size: size as u32 };
+            offset += size;
+        }
+        extents
+    }
+
+    pub fn parse(buf: &[u8]) -> io::Result<Self> {
+        let mut r = Reader::at(buf, HEADER_OFFSET);
+        r.magic(MAGIC, "missing NRO0 magic")?;
+        let _version = r.u32()?;
+        let size = r.u32()? as usize;
+        let flags = r.u32()?;
+        let mut segments = [Extent::default(); 3];
+        for segment in &mut segments {
+            *segment = read_extent(&mut r)?;
+        }
+        let bss_size = r.u32()?;
+        let _reserved = r.u32()?;
+        let module_id = r.array::<[u8; 0x20]>()?;
+        let dso_handle_offset = r.u32()?;
+        let _reserved = r.u32()?;
+        let api_info = read_extent(&mut r)?;
+        let dynstr = read_extent(&mut r)?;
+        let dynsym = read_extent(&mut r)?;
+
+        if size > buf.len() {
+            return Err(invalid_data("NRO is truncated"));
+        }
+        let segment = |extent: Extent| -> io::Result<Vec<u8>> {
+            let start = extent.offset as usize;
+            let end = start + extent.size as usize;
+            if end > size {
+                return Err(invalid_data("NRO segment is out of bounds"));
+            }
+            Ok(buf[start..end].to_vec())
+        };
+        let text = segment(segments[0])?;
+        if text.len() < HEADER_END {
+            return Err(invalid_data("NRO text segment is smaller than its header"));
+        }
+
+        let mut nro = Nro {
+            text,
+            ro: segment(segments[1])?,
+            data: segment(segments[2])?,
+            bss_size,
+            flags,
+            module_id,
+            dso_handle_offset,
+            api_info,
+            dynstr,
+            dynsym,
+            assets: None,
+        };
+        if buf.len() > size {
+            nro.assets = Some(parse_assets(&buf[size..])?);
+        }
+        Ok(nro)
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Self::parse(&buf)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        if self.text.len() < HEADER_END {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "NRO text segment is smaller than its header"));
+        }
+        let segments = self.segment_extents();
+        let size = segments[2].offset + segments[2].size;
+
+        let mut header = Vec::with_capacity(HEADER_END - HEADER_OFFSET);
+        header.extend_from_slice(MAGIC);
+        header.put_u32(0);
+        header.put_u32(size);
+        header.put_u32(self.flags);
+        for segment in &segments {
+            write_extent(&mut header, *segment);
+        }
+        header.put_u32(self.bss_size);
+        header.put_u32(0);
+        header.extend_from_slice(&self.module_id);
+        header.put_u32(self.dso_handle_offset);
+        header.put_u32(0);
+        for extent in &[self.api_info, self.dynstr, self.dynsym] {
+            write_extent(&mut header, *extent);
+        }
+
+        let mut buf = self.image();
+        buf.pad_to(size as usize);
+        buf[HEADER_OFFSET..HEADER_END].copy_from_slice(&header);
+        if let Some(ref assets) = self.assets {
+            write_assets(&mut buf, assets);
+        }
+        Ok(buf)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_bytes()?)
+    }
+}
+
+/// Reads only the asset section of the NRO in `r`, without loading its
+/// segments. This is what a launcher needs to list icons and titles.
+pub fn read_assets<R: Read + Seek>(r: &mut R) -> io::Result<Option<Assets>> {
+    let start = r.seek(SeekFrom::Current(0))?;
+    let mut header = [0u8; HEADER_END];
+    r.read_exact(&mut header)?;
+    let mut hr = Reader::at(&header, HEADER_OFFSET);
+    hr.magic(MAGIC, "missing NRO0 magic")?;
+    hr.pos += 4;
+    let size = hr.u32()? as u64;
+
+    r.seek(SeekFrom::Start(start + size))?;
+    let mut asset_header = Vec::new();
+    r.by_ref().take(ASSET_HEADER_SIZE as u64).read_to_end(&mut asset_header)?;
+    if asset_header.is_empty() {
+        return Ok(None);
+    }
+
+    let extents = parse_asset_header(&asset_header)?;
+    let mut assets = Assets::default();
+    let base = start + size;
+    for (i, &(offset, len)) in extents.iter().enumerate() {
+        // The lengths come straight from the file, so read no more than is
+        // there rather than allocating them up front.
+        let mut section = Vec::new();
+        if len > 0 {
+            let offset = base.checked_add(offset).ok_or_else(|| invalid_data("asset offset out of range"))?;
+            r.seek(SeekFrom::Start(offset))?;
+            r.by_ref().take(len).read_to_end(&mut section)?;
+            if (section.len() as u64) < len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of data"));
+            }
+        }
+        match i {
+            0 => assets.icon = section,
+            1 => assets.nacp = section,
+            _ => assets.romfs = section,
+        }
+    }
+    Ok(Some(assets))
+}
+
+fn read_extent(r: &mut Reader) -> io::Result<Extent> {
+    Ok(Extent { offset: r.u32()?, size: r.u32()? })
+}
+
+fn write_extent(buf: &mut Vec<u8>, extent: Extent) {
+    buf.put_u32(extent.offset);
+    buf.put_u32(extent.size);
+}
+
+/// The icon, NACP and RomFS extents, relative to the asset header.
+fn parse_asset_header(buf: &[u8]) -> io::Result<[(u64, u64); 3]> {
+    let mut r = Reader::new(buf);
+    r.magic(ASSET_MAGIC, "missing ASET magic")?;
+    let _version = r.u32()?;
+    let mut extents = [(0, 0); 3];
+    for extent in &mut extents {
+        *extent = (r.u64()?, r.u64()?);
+    }
+    Ok(extents)
+}
+
+fn parse_assets(buf: &[u8]) -> io::Result<Assets> {
+    let extents = parse_asset_header(buf)?;
+    let section = |(offset, size): (u64, u64)| -> io::Result<Vec<u8>> {
+        Ok(Reader::at(buf, offset as usize).bytes(size as usize)?.to_vec())
+    };
+    Ok(Assets {
+        icon: section(extents[0])?,
+        nacp: section(extents[1])?,
+        romfs: section(extents[2])?,
+    })
+}
+
+fn write_assets(buf: &mut Vec<u8>, assets: &Assets) {
+    buf.extend_from_slice(ASSET_MAGIC);
+    buf.put_u32(0);
+    let mut offset = ASSET_HEADER_SIZE as u64;
+    for section in &[&assets.icon, &assets.nacp, &assets.romfs] {
+        buf.put_u64(offset);
+        buf.put_u64(section.len() as u64);
+        offset += section.len() as u64;
+    }
+    for section in &[&assets.icon, &assets.nacp, &assets.romfs] {
+        buf.extend_from_slice(section);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use formats::mod0;
+
+    fn sample() -> Nro {
+        let mut text = vec![0u8; 0x1000];
+        text[0..4].copy_from_slice(&0x1400_0020u32.to_le_bytes());
+        text[0x80..0x84].copy_from_slice(b"code");
+        let mod0 = Mod0 { dynamic_offset: 0x1f00, bss_start_offset: 0x2f00, bss_end_offset: 0x3f00, ..Mod0::default() };
+        text[0x100..0x100 + mod0::SIZE].copy_from_slice(&mod0.to_bytes());
+
+        let mut nro = Nro::new(text, b"rodata".to_vec(), b"data".to_vec());
+        nro.set_mod0_offset(0x100);
+        nro.bss_size = 0x1000;
+        nro.module_id[0] = 0xab;
+        nro.dynstr = Extent { offset: 0x10, size: 0x20 };
+        nro
+    }
+
+    #[test]
+    fn round_trips() {
+        let bytes = sample().to_bytes().unwrap();
+        assert_eq!(&bytes[0x10..0x14], MAGIC);
+        assert_eq!(bytes.len(), 0x3000);
+
+        let nro = Nro::parse(&bytes).unwrap();
+        assert_eq!(nro.mod0_offset(), 0x100);
+        assert_eq!(nro.bss_size, 0x1000);
+        assert_eq!(nro.module_id[0], 0xab);
+        assert_eq!(nro.dynstr, Extent { offset: 0x10, size: 0x20 });
+        assert_eq!(&nro.ro[..6], b"rodata");
+        assert_eq!(nro.data.len(), 0x1000);
+        assert_eq!(nro.assets, None);
+        assert_eq!(nro.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn finds_mod0() {
+        let mod0 = sample().mod0().unwrap();
+        assert_eq!(mod0.dynamic_offset, 0x1f00);
+        assert_eq!(Mod0::resolve(0x100, mod0.bss_start_offset).unwrap(), 0x3000);
+    }
+
+    #[test]
+    fn round_trips_assets() {
+        let mut nro = sample();
+        nro.assets = Some(Assets { icon: vec![1; 3], nacp: vec![2; 0x4000], romfs: vec![3; 5] });
+        let bytes = nro.to_bytes().unwrap();
+        assert_eq!(&bytes[0x3000..0x3004], ASSET_MAGIC);
+
+        let parsed = Nro::parse(&bytes).unwrap();
+        assert_eq!(parsed.assets, nro.assets);
+        assert_eq!(parsed.to_bytes().unwrap(), bytes);
+
+        let mut file = Cursor::new(bytes);
+        assert_eq!(read_assets(&mut file).unwrap(), nro.assets);
+    }
+
+    #[test]
+    fn rejects_oversized_assets() {
+        let mut nro = sample();
+        nro.assets = Some(Assets { icon: vec![1; 3], nacp: vec![], romfs: vec![] });
+        let mut bytes = nro.to_bytes().unwrap();
+        // The icon's length, claiming nearly all of the address space.
+        bytes[0x3010..0x3018].copy_from_slice(&[0xff; 8]);
+        assert_eq!(read_assets(&mut Cursor::new(&bytes)).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        // The icon's offset, pointing past the end of any file.
+        bytes[0x3008..0x3010].copy_from_slice(&[0xff; 8]);
+        assert_eq!(read_assets(&mut Cursor::new(&bytes)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn reads_assets_of_bare_nro() {
+        let mut file = Cursor::new(sample().to_bytes().unwrap());
+        assert_eq!(read_assets(&mut file).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut bytes = sample().to_bytes().unwrap();
+        assert_eq!(Nro::parse(&bytes[..0x2000]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        bytes[0x10] = b'X';
+        assert_eq!(Nro::parse(&bytes).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(Nro::new(vec![0; 0x10], vec![], vec![]).to_bytes().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
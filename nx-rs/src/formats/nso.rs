@@ -0,0 +1,323 @@
+//! NSO, the executable format of system modules and ExeFS programs.
+//!
+//! Unlike an NRO, an NSO has a header in front of its segments, and each
+//! segment can be LZ4-compressed and checked against a SHA-256 hash by the
+//! loader.
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
+use super::mod0::Mod0;
+use super::nro::Extent;
+use super::{align_up, invalid_data, lz4, Reader, WriteLe};
+
+pub const MAGIC: &[u8; 4] = b"NSO0";
+pub const HEADER_SIZE: usize = 0x100;
+/// Segments are mapped at page-aligned offsets.
+pub const SEGMENT_ALIGN: usize = 0x1000;
+
+const FLAG_COMPRESSED: u32 = 1;
+const FLAG_CHECK_HASH: u32 = 1 << 3;
+
+/// One of the text, ro and data segments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Segment {
+    /// Where the segment is mapped, relative to the module's base.
+    pub memory_offset: u32,
+    /// The decompressed contents.
+    pub data: Vec<u8>,
+    /// Whether to store the segment LZ4-compressed.
+    pub compressed: bool,
+    /// Whether the loader should check the segment against its hash.
+    pub check_hash: bool,
+}
+
+impl Segment {
+    pub fn hash(&self) -> [u8; 0x20] {
+        let mut hash = [0; 0x20];
+        hash.copy_from_slice(Sha256::digest(&self.data).as_slice());
+        hash
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nso {
+    pub text: Segment,
+    pub ro: Segment,
+    pub data: Segment,
+    pub bss_size: u32,
+    /// The module ID, also called the build ID, which identifies the build
+    /// to patches and crash reports.
+    pub module_id: [u8; 0x20],
+    /// Stored right after the header. Usually empty.
+    pub module_name: Vec<u8>,
+    /// The API info, `.dynstr` and `.dynsym` sections, relative to the start
+    /// of the ro segment.
+    pub api_info: Extent,
+    pub dynstr: Extent,
+    pub dynsym: Extent,
+}
+
+impl Nso {
+    /// An NSO with the given segments laid out back to back, compressed and
+    /// with hash checks enabled, the way the official tools build them.
+    pub fn new(text: Vec<u8>, ro: Vec<u8>, data: Vec<u8>) -> Self {
+        let ro_offset = align_up(text.len(), SEGMENT_ALIGN);
+        let data_offset = ro_offset + align_up(ro.len(), SEGMENT_ALIGN);
+        let segment = |memory_offset: usize, data: Vec<u8>| Segment {
+            memory_offset: memory_offset as u32,
+            data,
+            compressed: true,
+            check_hash: true,
+        };
+        Nso {
+            text: segment(0, text),
+            ro: segment(ro_offset, ro),
+            data: segment(data_offset, data),
+            bss_size: 0,
+            module_id: [0; 0x20],
+            module_name: Vec::new(),
+            api_info: Extent::default(),
+            dynstr: Extent::default(),
+            dynsym: Extent::default(),
+        }
+    }
+
+    fn segments(&self) -> [&Segment; 3] {
+        [&self.text, &self.ro, &self.data]
+    }
+
+    /// The module ID as the hex string patch directories are named after,
+    /// without trailing zero bytes.
+    pub fn module_id_string(&self) -> String {
+        let len = self.module_id.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        let mut id = String::with_capacity(len * 2);
+        for b in &self.module_id[..len] {
+            let _ = write!(id, "{:02X}", b);
+        }
+        id
+    }
+
+    /// The MOD0 header's offset from the start of text, stored at 0x4.
+    pub fn mod0_offset(&self) -> u32 {
+        Reader::at(&self.text.data, 4).u32().unwrap_or(0)
+    }
+
+    /// The segments as they are laid out in memory, without the BSS.
+    pub fn image(&self) -> Vec<u8> {
+        let mut image = Vec::new();
+        for segment in self.segments().iter() {
+            image.pad_to(segment.memory_offset as usize);
+            let start = segment.memory_offset as usize;
+            let end = start + segment.data.len();
+            image.pad_to(end);
+            image[start..end].copy_from_slice(&segment.data);
+        }
+        image
+    }
+
+    pub fn mod0(&self) -> io::Result<Mod0> {
+        Mod0::parse(&self.image(), self.mod0_offset() as usize)
+    }
+
+    /// Parses an NSO, decompressing its segments and checking the hashes of
+    /// those that have hash checks enabled.
+    pub fn parse(buf: &[u8]) -> io::Result<Self> {
+        let mut r = Reader::new(buf);
+        r.magic(MAGIC, "missing NSO0 magic")?;
+        let _version = r.u32()?;
+        let _reserved = r.u32()?;
+        let flags = r.u32()?;
+        // The segment headers are interleaved with the module name's offset
+        // and size and the BSS size.
+        let mut headers = [(0u32, 0u32, 0u32); 3];
+        let mut extras = [0u32; 3];
+        for (header, extra) in headers.iter_mut().zip(extras.iter_mut()) {
+            *header = (r.u32()?, r.u32()?, r.u32()?);
+            *extra = r.u32()?;
+        }
+        let (module_name_offset, module_name_size, bss_size) = (extras[0], extras[1], extras[2]);
+        let module_id = r.array::<[u8; 0x20]>()?;
+        let mut file_sizes = [0u32; 3];
+        for size in &mut file_sizes {
+            *size = r.u32()?;
+        }
+        r.pos += 0x1c;
+        let mut extents = [Extent::default(); 3];
+        for extent in &mut extents {
+            *extent = Extent { offset: r.u32()?, size: r.u32()? };
+        }
+        let mut hashes = [[0u8; 0x20]; 3];
+        for hash in &mut hashes {
+            *hash = r.array()?;
+        }
+
+        let module_name = Reader::at(buf, module_name_offset as usize)
+            .bytes(module_name_size as usize)?
+            .to_vec();
+
+        let segment = |i: usize| -> io::Result<Segment> {
+            let (file_offset, memory_offset, size) = headers[i];
+            let compressed = flags & (FLAG_COMPRESSED << i) != 0;
+            let check_hash = flags & (FLAG_CHECK_HASH << i) != 0;
+            let file_size = if compressed { file_sizes[i] } else { size };
+            let stored = Reader::at(buf, file_offset as usize).bytes(file_size as usize)?;
+            let segment = Segment {
+                memory_offset,
+                data: if compressed {
+                    lz4::decompress(stored, size as usize)?
+                } else {
+                    stored.to_vec()
+                },
+                compressed,
+                check_hash,
+            };
+            if check_hash && segment.hash() != hashes[i] {
+                return Err(invalid_data("NSO segment hash mismatch"));
+            }
+            Ok(segment)
+        };
+
+        Ok(Nso {
+            text: segment(0)?,
+            ro: segment(1)?,
+            data: segment(2)?,
+            bss_size,
+            module_id,
+            module_name,
+            api_info: extents[0],
+            dynstr: extents[1],
+            dynsym: extents[2],
+        })
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        Self::parse(&buf)
+    }
+
+    /// Builds the NSO, compressing segments and computing hashes as their
+    /// flags ask for.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let segments = self.segments();
+        let mut flags = 0;
+        let mut stored = Vec::with_capacity(3);
+        for (i, segment) in segments.iter().enumerate() {
+            if segment.compressed {
+                flags |= FLAG_COMPRESSED << i;
+                stored.push(lz4::compress(&segment.data));
+            } else {
+                stored.push(segment.data.clone());
+            }
+            if segment.check_hash {
+                flags |= FLAG_CHECK_HASH << i;
+            }
+        }
+
+        let module_name_offset = HEADER_SIZE as u32;
+        let module_name_size = self.module_name.len() as u32;
+        let mut file_offset = module_name_offset + module_name_size;
+        let mut buf = Vec::with_capacity(file_offset as usize + stored.iter().map(Vec::len).sum::<usize>());
+        buf.extend_from_slice(MAGIC);
+        buf.put_u32(0);
+        buf.put_u32(0);
+        buf.put_u32(flags);
+        let extras = [module_name_offset, module_name_size, self.bss_size];
+        for ((segment, data), &extra) in segments.iter().zip(&stored).zip(&extras) {
+            buf.put_u32(file_offset);
+            buf.put_u32(segment.memory_offset);
+            buf.put_u32(segment.data.len() as u32);
+            buf.put_u32(extra);
+            file_offset += data.len() as u32;
+        }
+        buf.extend_from_slice(&self.module_id);
+        for data in &stored {
+            buf.put_u32(data.len() as u32);
+        }
+        buf.pad_to(0x88);
+        for extent in &[self.api_info, self.dynstr, self.dynsym] {
+            buf.put_u32(extent.offset);
+            buf.put_u32(extent.size);
+        }
+        for segment in segments.iter() {
+            // Hashes are stored even when unchecked, as the official tools do.
+            buf.extend_from_slice(&segment.hash());
+        }
+        debug_assert_eq!(buf.len(), HEADER_SIZE);
+
+        buf.extend_from_slice(&self.module_name);
+        for data in &stored {
+            buf.extend_from_slice(data);
+        }
+        Ok(buf)
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_bytes()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use formats::mod0;
+
+    fn sample() -> Nso {
+        let mut text = vec![0u8; 0x1800];
+        text[4..8].copy_from_slice(&0x100u32.to_le_bytes());
+        let mod0 = Mod0 { dynamic_offset: 0x2f00, ..Mod0::default() };
+        text[0x100..0x100 + mod0::SIZE].copy_from_slice(&mod0.to_bytes());
+        for (i, b) in text[0x200..].iter_mut().enumerate() {
+            *b = (i % 251) as u8;
+        }
+
+        let mut nso = Nso::new(text, b"rodata rodata rodata rodata".to_vec(), vec![7; 0x40]);
+        nso.bss_size = 0x3000;
+        nso.module_id[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        nso.dynsym = Extent { offset: 0x8, size: 0x18 };
+        nso
+    }
+
+    #[test]
+    fn lays_out_segments() {
+        let nso = sample();
+        assert_eq!(nso.ro.memory_offset, 0x2000);
+        assert_eq!(nso.data.memory_offset, 0x3000);
+        assert_eq!(nso.image().len(), 0x3040);
+        assert_eq!(nso.mod0().unwrap().dynamic_offset, 0x2f00);
+        assert_eq!(nso.module_id_string(), "DEADBEEF");
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut nso = sample();
+        nso.ro.compressed = false;
+        nso.data.check_hash = false;
+        nso.module_name = b"\0".to_vec();
+
+        let bytes = nso.to_bytes().unwrap();
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(Reader::at(&bytes, 0xc).u32().unwrap(), 0b011_101);
+        assert_eq!(&bytes[0xa0..0xc0], &nso.text.hash());
+        assert!(bytes.len() < 0x100 + 0x1800);
+        assert_eq!(Nso::parse(&bytes).unwrap(), nso);
+    }
+
+    #[test]
+    fn checks_hashes() {
+        let nso = sample();
+        let mut bytes = nso.to_bytes().unwrap();
+        bytes[0xc0] ^= 1;
+        assert_eq!(Nso::parse(&bytes).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let bytes = sample().to_bytes().unwrap();
+        assert!(Nso::parse(&bytes[..0x80]).is_err());
+        assert!(Nso::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+//! PFS0, the flat archive ExeFS and sysmodule packages are stored in.
+//!
+//! A PFS0 is a header listing each file's offset, size and name, followed by
+//! the files' contents. `Pfs0` reads entries straight from the underlying
+//! stream, so large archives never have to be loaded into memory.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{align_up, invalid_data, Reader, WriteLe};
+pub use super::EntryReader;
+
+pub const MAGIC: &[u8; 4] = b"PFS0";
+const HEADER_SIZE: usize = 0x10;
+const ENTRY_SIZE: usize = 0x18;
+/// The builder pads the header, string table included, to this alignment.
+pub const HEADER_ALIGN: usize = 0x20;
+
+/// A file in the archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    /// The offset of the file's contents from the start of the archive.
+    pub offset: u64,
+    pub size: u64,
+}
+
+/// A PFS0 archive being read from `R`.
+pub struct Pfs0<R> {
+    inner: R,
+    /// Where the archive starts in `inner`.
+    base: u64,
+    entries: Vec<Entry>,
+}
+
+impl<R: Read + Seek> Pfs0<R> {
+    /// Reads the archive's header. The archive starts at `inner`'s current
+    /// position, so it can be nested in a larger file.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let base = inner.seek(SeekFrom::Current(0))?;
+        let len = inner.seek(SeekFrom::End(0))? - base;
+        inner.seek(SeekFrom::Start(base))?;
+
+        let mut header = [0u8; HEADER_SIZE];
+        inner.read_exact(&mut header)?;
+        let mut r = Reader::new(&header);
+        r.magic(MAGIC, "missing PFS0 magic")?;
+        let count = r.u32()? as u64;
+        let string_table_size = r.u32()? as u64;
+        let table_len = count * ENTRY_SIZE as u64 + string_table_size;
+        if HEADER_SIZE as u64 + table_len > len {
+            return Err(invalid_data("PFS0 header is truncated"));
+        }
+
+        let mut table = vec![0u8; table_len as usize];
+        inner.read_exact(&mut table)?;
+        let (entry_table, strings) = table.split_at(count as usize * ENTRY_SIZE);
+        let data_start = base + HEADER_SIZE as u64 + table_len;
+        let mut r = Reader::new(entry_table);
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let offset = r.u64()?;
+            let size = r.u64()?;
+            let name_offset = r.u32()? as usize;
+            let _reserved = r.u32()?;
+            let name = strings.get(name_offset..)
+                .and_then(|name| name.iter().position(|&b| b == 0).map(|end| &name[..end]))
+                .ok_or_else(|| invalid_data("PFS0 entry name is out of bounds"))?;
+            let offset = data_start.checked_add(offset)
+                .ok_or_else(|| invalid_data("PFS0 entry is out of bounds"))?;
+            if offset.checked_add(size).map_or(true, |end| end > base + len) {
+                return Err(invalid_data("PFS0 entry is out of bounds"));
+            }
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                offset: offset - base,
+                size,
+            });
+        }
+        Ok(Pfs0 { inner, base, entries })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Opens the file called `name` for reading.
+    pub fn open(&mut self, name: &str) -> io::Result<EntryReader<'_, R>> {
+        let index = self.entries.iter()
+            .position(|entry| entry.name == name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file in PFS0"))?;
+        self.open_index(index)
+    }
+
+    /// Opens the `index`th entry for reading.
+    pub fn open_index(&mut self, index: usize) -> io::Result<EntryReader<'_, R>> {
+        let entry = self.entries.get(index)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file in PFS0"))?;
+        Ok(EntryReader::new(&mut self.inner, self.base + entry.offset, entry.size))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl Pfs0<File> {
+    pub fn open_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Pfs0::new(File::open(path)?)
+    }
+}
+
+/// Lays out a PFS0 and streams the files' contents into it.
+#[derive(Default)]
+pub struct Builder<'a> {
+    files: Vec<(String, u64, Box<dyn Read + 'a>)>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new() -> Self {
+        Builder { files: Vec::new() }
+    }
+
+    /// Adds a file whose `size` bytes of contents are read from `data` when
+    /// the archive is written.
+    pub fn add<T: Read + 'a>(&mut self, name: &str, size: u64, data: T) -> &mut Self {
+        self.files.push((name.to_owned(), size, Box::new(data)));
+        self
+    }
+
+    pub fn add_bytes(&mut self, name: &str, data: Vec<u8>) -> &mut Self {
+        let size = data.len() as u64;
+        self.add(name, size, io::Cursor::new(data))
+    }
+
+    /// Adds the host file at `path`, opening it now and reading it when the
+    /// archive is written.
+    pub fn add_file<P: AsRef<Path>>(&mut self, name: &str, path: P) -> io::Result<&mut Self> {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        Ok(self.add(name, size, file))
+    }
+
+    /// The header, string table and padding that go in front of the files.
+    fn header(&self) -> io::Result<Vec<u8>> {
+        let mut names = HashSet::new();
+        let mut strings = Vec::new();
+        let mut name_offsets = Vec::with_capacity(self.files.len());
+        for (name, _, _) in &self.files {
+            if name.is_empty() || name.as_bytes().contains(&0) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid PFS0 file name"));
+            }
+            if !names.insert(name) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "duplicate PFS0 file name"));
+            }
+            name_offsets.push(strings.len() as u32);
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+        // The padding is counted as part of the string table, so the data
+        // that follows starts aligned.
+        let table_end = HEADER_SIZE + self.files.len() * ENTRY_SIZE + strings.len();
+        let padding = align_up(table_end, HEADER_ALIGN) - table_end;
+        let len = strings.len();
+        strings.pad_to(len + padding);
+
+        let mut header = Vec::with_capacity(table_end + padding);
+        header.extend_from_slice(MAGIC);
+        header.put_u32(self.files.len() as u32);
+        header.put_u32(strings.len() as u32);
+        header.put_u32(0);
+        let mut offset = 0;
+        for (&(_, size, _), name_offset) in self.files.iter().zip(name_offsets) {
+            header.put_u64(offset);
+            header.put_u64(size);
+            header.put_u32(name_offset);
+            header.put_u32(0);
+            offset += size;
+        }
+        header.extend_from_slice(&strings);
+        Ok(header)
+    }
+
+    pub fn write<W: Write>(self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.header()?)?;
+        for (name, size, data) in self.files {
+            let copied = io::copy(&mut data.take(size), w)?;
+            if copied != size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                          format!("{} is shorter than the size it was added with", name)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut builder = Builder::new();
+        builder.add_bytes("main", b"main contents".to_vec())
+            .add_bytes("main.npdm", b"META".to_vec())
+            .add("rtld", 3, &b"rtld and more"[..]);
+        builder.to_bytes().unwrap()
+    }
+
+    #[test]
+    fn lays_out_header() {
+        let bytes = sample();
+        assert_eq!(&bytes[..4], MAGIC);
+        let data_start = bytes.len() - 20;
+        assert_eq!(data_start % HEADER_ALIGN, 0);
+        assert_eq!(&bytes[data_start..data_start + 4], b"main");
+        assert_eq!(&bytes[0x58..0x68], b"main\0main.npdm\0r");
+    }
+
+    #[test]
+    fn reads_entries() {
+        let mut pfs0 = Pfs0::new(Cursor::new(sample())).unwrap();
+        let names: Vec<_> = pfs0.entries().iter().map(|entry| entry.name.clone()).collect();
+        assert_eq!(names, ["main", "main.npdm", "rtld"]);
+        assert_eq!(pfs0.entry("main.npdm").unwrap().size, 4);
+
+        let mut contents = String::new();
+        pfs0.open("main").unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "main contents");
+
+        let mut rtld = pfs0.open_index(2).unwrap();
+        let mut buf = Vec::new();
+        rtld.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"rtl");
+
+        assert_eq!(pfs0.open("nope").err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn seeks_within_entry() {
+        let mut pfs0 = Pfs0::new(Cursor::new(sample())).unwrap();
+        let mut main = pfs0.open("main").unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(main.seek(SeekFrom::End(-8)).unwrap(), 5);
+        main.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"cont");
+        assert_eq!(main.seek(SeekFrom::Current(-9)).unwrap(), 0);
+        assert!(main.seek(SeekFrom::Current(-1)).is_err());
+        main.seek(SeekFrom::Start(100)).unwrap();
+        assert_eq!(main.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_nested_archive() {
+        let mut file = vec![0xff; 0x30];
+        file.extend_from_slice(&sample());
+        let mut cursor = Cursor::new(file);
+        cursor.set_position(0x30);
+        let mut pfs0 = Pfs0::new(cursor).unwrap();
+        let mut buf = Vec::new();
+        pfs0.open("main.npdm").unwrap().read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"META");
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let bytes = sample();
+        assert!(Pfs0::new(Cursor::new(&bytes[..bytes.len() - 1])).is_err());
+        assert!(Pfs0::new(Cursor::new(&bytes[..0x20])).is_err());
+
+        let mut builder = Builder::new();
+        builder.add_bytes("a", vec![]).add_bytes("a", vec![]);
+        assert_eq!(builder.to_bytes().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        let mut builder = Builder::new();
+        builder.add("short", 10, &b"abc"[..]);
+        assert_eq!(builder.to_bytes().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
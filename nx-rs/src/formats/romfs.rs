@@ -0,0 +1,682 @@
+//! RomFS, the read-only filesystem bundled with NROs and mounted through
+//! libnx's romfs device.
+//!
+//! An image holds the files' contents followed by four tables: hash tables
+//! and metadata for directories and for files. Metadata entries form a tree
+//! through their child and sibling links, and the hash tables map a parent
+//! and name to an entry so paths can be resolved without walking the tree.
+//! `Builder` lays these out the way switch-tools' build_romfs does.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::{align_up, invalid_data, Reader, WriteLe};
+pub use super::EntryReader;
+
+const HEADER_SIZE: usize = 0x50;
+/// Where file data starts in images the builder makes.
+pub const FILE_DATA_OFFSET: u64 = 0x200;
+/// The alignment of each file's data.
+pub const FILE_ALIGN: u64 = 0x10;
+/// Marks a missing link in the metadata tables.
+const EMPTY: u32 = 0xffff_ffff;
+const DIR_ENTRY_SIZE: usize = 0x18;
+const FILE_ENTRY_SIZE: usize = 0x20;
+
+/// The hash of an entry's name, keyed on the offset of its parent.
+pub fn path_hash(parent: u32, name: &[u8]) -> u32 {
+    name.iter().fold(parent ^ 123_456_789, |hash, &b| hash.rotate_right(5) ^ b as u32)
+}
+
+/// The number of buckets in the hash table for `entries` entries: small
+/// counts are made odd, larger ones coprime to the primes up to 17.
+pub fn hash_table_len(entries: usize) -> usize {
+    if entries < 3 {
+        3
+    } else if entries < 19 {
+        entries | 1
+    } else {
+        let mut len = entries;
+        while [2, 3, 5, 7, 11, 13, 17].iter().any(|p| len % p == 0) {
+            len += 1;
+        }
+        len
+    }
+}
+
+/// The offsets and sizes of an image's sections.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Header {
+    dir_hash: (u64, u64),
+    dir_meta: (u64, u64),
+    file_hash: (u64, u64),
+    file_meta: (u64, u64),
+    file_data: u64,
+}
+
+impl Header {
+    fn parse(buf: &[u8]) -> io::Result<Self> {
+        let mut r = Reader::new(buf);
+        if r.u64()? != HEADER_SIZE as u64 {
+            return Err(invalid_data("unexpected RomFS header size"));
+        }
+        let mut tables = [(0, 0); 4];
+        for table in &mut tables {
+            *table = (r.u64()?, r.u64()?);
+        }
+        Ok(Header {
+            dir_hash: tables[0],
+            dir_meta: tables[1],
+            file_hash: tables[2],
+            file_meta: tables[3],
+            file_data: r.u64()?,
+        })
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        buf.put_u64(HEADER_SIZE as u64);
+        for &(offset, size) in &[self.dir_hash, self.dir_meta, self.file_hash, self.file_meta] {
+            buf.put_u64(offset);
+            buf.put_u64(size);
+        }
+        buf.put_u64(self.file_data);
+        buf
+    }
+}
+
+enum Source {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+}
+
+/// Collects files and directories and writes them out as a RomFS image.
+///
+/// Paths are relative to the root and use `/` as the separator.
+pub struct Builder {
+    dirs: BTreeSet<String>,
+    files: BTreeMap<String, (u64, Source)>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        let mut dirs = BTreeSet::new();
+        dirs.insert(String::new());
+        Builder { dirs, files: BTreeMap::new() }
+    }
+
+    /// A builder holding everything under the host directory `root`. Files
+    /// are read when the image is written.
+    pub fn from_dir<P: AsRef<Path>>(root: P) -> io::Result<Self> {
+        let mut builder = Builder::new();
+        builder.add_dir_contents("", root.as_ref())?;
+        Ok(builder)
+    }
+
+    fn add_dir_contents(&mut self, path: &str, host_path: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(host_path)? {
+            let entry = entry?;
+            let name = entry.file_name().into_string().map_err(|name| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{:?} is not valid UTF-8", name))
+            })?;
+            let child = join(path, &name);
+            let host_child = entry.path();
+            if fs::metadata(&host_child)?.is_dir() {
+                self.add_dir(&child)?;
+                self.add_dir_contents(&child, &host_child)?;
+            } else {
+                self.add_file(&child, host_child)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds an empty directory, along with any missing parents.
+    pub fn add_dir(&mut self, path: &str) -> io::Result<&mut Self> {
+        let path = normalize(path)?;
+        let mut dirs = vec![path.clone()];
+        let mut dir = &path[..];
+        while !dir.is_empty() {
+            dir = parent(dir);
+            dirs.push(dir.to_owned());
+        }
+        if dirs.iter().any(|dir| self.files.contains_key(dir)) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "a RomFS file already has this path"));
+        }
+        self.dirs.extend(dirs);
+        Ok(self)
+    }
+
+    pub fn add_bytes(&mut self, path: &str, data: Vec<u8>) -> io::Result<&mut Self> {
+        let size = data.len() as u64;
+        self.insert_file(path, size, Source::Bytes(data))
+    }
+
+    /// Adds the host file at `host_path`, which is read when the image is
+    /// written.
+    pub fn add_file<P: Into<PathBuf>>(&mut self, path: &str, host_path: P) -> io::Result<&mut Self> {
+        let host_path = host_path.into();
+        let size = fs::metadata(&host_path)?.len();
+        self.insert_file(path, size, Source::File(host_path))
+    }
+
+    fn insert_file(&mut self, path: &str, size: u64, source: Source) -> io::Result<&mut Self> {
+        let path = normalize(path)?;
+        if path.is_empty() || self.dirs.contains(&path) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "a RomFS directory already has this path"));
+        }
+        if let Some(end) = path.rfind('/') {
+            self.add_dir(&path[..end])?;
+        }
+        self.files.insert(path, (size, source));
+        Ok(self)
+    }
+
+    /// Writes the image: the header, the file data and then the tables.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let dirs: Vec<&str> = self.dirs.iter().map(String::as_str).collect();
+        let files: Vec<&str> = self.files.keys().map(String::as_str).collect();
+
+        let mut dir_offsets = HashMap::new();
+        let mut offset = 0;
+        for &dir in &dirs {
+            dir_offsets.insert(dir, offset);
+            offset += (DIR_ENTRY_SIZE + align_up(name(dir).len(), 4)) as u32;
+        }
+        let dir_meta_size = offset;
+        let mut file_offsets = Vec::with_capacity(files.len());
+        let mut offset = 0;
+        for &file in &files {
+            file_offsets.push(offset);
+            offset += (FILE_ENTRY_SIZE + align_up(name(file).len(), 4)) as u32;
+        }
+        let file_meta_size = offset;
+
+        // Children are linked in path order, which is name order since they
+        // share a parent.
+        let mut child_dir = HashMap::new();
+        let mut child_file = HashMap::new();
+        let mut next_sibling = HashMap::new();
+        let mut last_child: HashMap<(&str, bool), u32> = HashMap::new();
+        for &dir in &dirs[1..] {
+            let parent = parent(dir);
+            let offset = dir_offsets[dir];
+            match last_child.insert((parent, true), offset) {
+                Some(prev) => next_sibling.insert((true, prev), offset),
+                None => child_dir.insert(parent, offset),
+            };
+        }
+        for (&file, &offset) in files.iter().zip(&file_offsets) {
+            let parent = parent(file);
+            match last_child.insert((parent, false), offset) {
+                Some(prev) => next_sibling.insert((false, prev), offset),
+                None => child_file.insert(parent, offset),
+            };
+        }
+
+        let mut data_offsets = Vec::with_capacity(files.len());
+        let mut data_size = 0;
+        for file in self.files.values() {
+            data_size = align_up(data_size as usize, FILE_ALIGN as usize) as u64;
+            data_offsets.push(data_size);
+            data_size += file.0;
+        }
+
+        let mut dir_hash = vec![EMPTY; hash_table_len(dirs.len())];
+        let mut dir_meta = Vec::with_capacity(dir_meta_size as usize);
+        for &dir in &dirs {
+            let offset = dir_offsets[dir];
+            let parent = dir_offsets[parent(dir)];
+            let bucket = path_hash(parent, name(dir).as_bytes()) as usize % dir_hash.len();
+            dir_meta.put_u32(parent);
+            dir_meta.put_u32(*next_sibling.get(&(true, offset)).unwrap_or(&EMPTY));
+            dir_meta.put_u32(*child_dir.get(dir).unwrap_or(&EMPTY));
+            dir_meta.put_u32(*child_file.get(dir).unwrap_or(&EMPTY));
+            dir_meta.put_u32(dir_hash[bucket]);
+            dir_hash[bucket] = offset;
+            put_name(&mut dir_meta, name(dir));
+        }
+
+        let mut file_hash = vec![EMPTY; hash_table_len(files.len())];
+        let mut file_meta = Vec::with_capacity(file_meta_size as usize);
+        for (i, (&file, entry)) in files.iter().zip(self.files.values()).enumerate() {
+            let offset = file_offsets[i];
+            let parent = dir_offsets[parent(file)];
+            let bucket = path_hash(parent, name(file).as_bytes()) as usize % file_hash.len();
+            file_meta.put_u32(parent);
+            file_meta.put_u32(*next_sibling.get(&(false, offset)).unwrap_or(&EMPTY));
+            file_meta.put_u64(data_offsets[i]);
+            file_meta.put_u64(entry.0);
+            file_meta.put_u32(file_hash[bucket]);
+            file_hash[bucket] = offset;
+            put_name(&mut file_meta, name(file));
+        }
+
+        let dir_hash_offset = align_up((FILE_DATA_OFFSET + data_size) as usize, 4) as u64;
+        let dir_meta_offset = dir_hash_offset + dir_hash.len() as u64 * 4;
+        let file_hash_offset = dir_meta_offset + dir_meta.len() as u64;
+        let file_meta_offset = file_hash_offset + file_hash.len() as u64 * 4;
+        let header = Header {
+            dir_hash: (dir_hash_offset, dir_hash.len() as u64 * 4),
+            dir_meta: (dir_meta_offset, dir_meta.len() as u64),
+            file_hash: (file_hash_offset, file_hash.len() as u64 * 4),
+            file_meta: (file_meta_offset, file_meta.len() as u64),
+            file_data: FILE_DATA_OFFSET,
+        };
+
+        let mut head = header.to_bytes();
+        head.pad_to(FILE_DATA_OFFSET as usize);
+        w.write_all(&head)?;
+        let mut written = 0;
+        for (&(size, ref source), &offset) in self.files.values().zip(&data_offsets) {
+            w.write_all(&vec![0; (offset - written) as usize])?;
+            let copied = match *source {
+                Source::Bytes(ref data) => {
+                    w.write_all(data)?;
+                    data.len() as u64
+                }
+                Source::File(ref path) => io::copy(&mut File::open(path)?.take(size), w)?,
+            };
+            if copied != size {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "RomFS file shrank while being written"));
+            }
+            written = offset + size;
+        }
+
+        let mut tables = vec![0; (dir_hash_offset - FILE_DATA_OFFSET - written) as usize];
+        for &bucket in &dir_hash {
+            tables.put_u32(bucket);
+        }
+        tables.extend_from_slice(&dir_meta);
+        for &bucket in &file_hash {
+            tables.put_u32(bucket);
+        }
+        tables.extend_from_slice(&file_meta);
+        w.write_all(&tables)
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.write(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Strips leading and trailing slashes, and rejects empty components.
+fn normalize(path: &str) -> io::Result<String> {
+    let path = path.trim_matches('/');
+    if path.split('/').any(|c| c == "." || c == ".." || (c.is_empty() && !path.is_empty())) || path.contains('\0') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid RomFS path"));
+    }
+    Ok(path.to_owned())
+}
+
+fn join(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}/{}", parent, name)
+    }
+}
+
+fn parent(path: &str) -> &str {
+    path.rfind('/').map_or("", |end| &path[..end])
+}
+
+fn name(path: &str) -> &str {
+    path.rfind('/').map_or(path, |end| &path[end + 1..])
+}
+
+fn put_name(buf: &mut Vec<u8>, name: &str) {
+    buf.put_u32(name.len() as u32);
+    buf.extend_from_slice(name.as_bytes());
+    let len = align_up(buf.len(), 4);
+    buf.pad_to(len);
+}
+
+struct DirMeta<'a> {
+    sibling: u32,
+    child_dir: u32,
+    child_file: u32,
+    hash_sibling: u32,
+    parent: u32,
+    name: &'a [u8],
+}
+
+struct FileMeta<'a> {
+    parent: u32,
+    sibling: u32,
+    offset: u64,
+    size: u64,
+    hash_sibling: u32,
+    name: &'a [u8],
+}
+
+/// A RomFS image being read from `R`. The tables are loaded up front, and
+/// file contents are read on demand.
+pub struct RomFs<R> {
+    inner: R,
+    base: u64,
+    header: Header,
+    dir_hash: Vec<u32>,
+    dir_meta: Vec<u8>,
+    file_hash: Vec<u32>,
+    file_meta: Vec<u8>,
+}
+
+/// What kind of entry a `DirEntry` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    Dir,
+    File,
+}
+
+/// An entry returned by `RomFs::read_dir`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    /// The file's size, or zero for directories.
+    pub len: u64,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.kind == EntryKind::Dir
+    }
+}
+
+impl<R: Read + Seek> RomFs<R> {
+    /// Reads the image's header and tables. The image starts at `inner`'s
+    /// current position.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let base = inner.seek(SeekFrom::Current(0))?;
+        let len = inner.seek(SeekFrom::End(0))? - base;
+        inner.seek(SeekFrom::Start(base))?;
+        let mut head = [0u8; HEADER_SIZE];
+        inner.read_exact(&mut head)?;
+        let header = Header::parse(&head)?;
+
+        let (dir_hash, dir_meta, file_hash, file_meta) = {
+            let mut table = |(offset, size): (u64, u64)| -> io::Result<Vec<u8>> {
+                if offset.checked_add(size).map_or(true, |end| end > len) {
+                    return Err(invalid_data("RomFS table is out of bounds"));
+                }
+                inner.seek(SeekFrom::Start(base + offset))?;
+                let mut buf = vec![0; size as usize];
+                inner.read_exact(&mut buf)?;
+                Ok(buf)
+            };
+            let buckets = |buf: Vec<u8>| -> io::Result<Vec<u32>> {
+                let mut r = Reader::new(&buf);
+                (0..buf.len() / 4).map(|_| r.u32()).collect()
+            };
+            (buckets(table(header.dir_hash)?)?, table(header.dir_meta)?,
+             buckets(table(header.file_hash)?)?, table(header.file_meta)?)
+        };
+        if dir_hash.is_empty() || file_hash.is_empty() {
+            return Err(invalid_data("RomFS hash table is empty"));
+        }
+        Ok(RomFs { inner, base, header, dir_hash, dir_meta, file_hash, file_meta })
+    }
+
+    fn dir(&self, offset: u32) -> io::Result<DirMeta<'_>> {
+        let mut r = Reader::at(&self.dir_meta, offset as usize);
+        let parent = r.u32()?;
+        let sibling = r.u32()?;
+        let child_dir = r.u32()?;
+        let child_file = r.u32()?;
+        let hash_sibling = r.u32()?;
+        let len = r.u32()? as usize;
+        Ok(DirMeta { parent, sibling, child_dir, child_file, hash_sibling, name: r.bytes(len)? })
+    }
+
+    fn file(&self, offset: u32) -> io::Result<FileMeta<'_>> {
+        let mut r = Reader::at(&self.file_meta, offset as usize);
+        let parent = r.u32()?;
+        let sibling = r.u32()?;
+        let data_offset = r.u64()?;
+        let size = r.u64()?;
+        let hash_sibling = r.u32()?;
+        let len = r.u32()? as usize;
+        Ok(FileMeta { parent, sibling, offset: data_offset, size, hash_sibling, name: r.bytes(len)? })
+    }
+
+    /// Looks up `name` in the directory at `parent` through the hash tables.
+    fn lookup(&self, parent: u32, name: &str, kind: EntryKind) -> io::Result<Option<u32>> {
+        let table = if kind == EntryKind::Dir { &self.dir_hash } else { &self.file_hash };
+        let mut offset = table[path_hash(parent, name.as_bytes()) as usize % table.len()];
+        // A chain can't be longer than the number of entries, which bounds
+        // the walk on corrupt images.
+        for _ in 0..self.dir_meta.len().max(self.file_meta.len()) / DIR_ENTRY_SIZE + 1 {
+            if offset == EMPTY {
+                return Ok(None);
+            }
+            let (entry_parent, entry_name, next) = if kind == EntryKind::Dir {
+                let dir = self.dir(offset)?;
+                (dir.parent, dir.name, dir.hash_sibling)
+            } else {
+                let file = self.file(offset)?;
+                (file.parent, file.name, file.hash_sibling)
+            };
+            if entry_parent == parent && entry_name == name.as_bytes() {
+                return Ok(Some(offset));
+            }
+            offset = next;
+        }
+        Err(invalid_data("RomFS hash chain loops"))
+    }
+
+    /// Resolves `path` to an entry's offset in its metadata table.
+    fn resolve(&self, path: &str, kind: EntryKind) -> io::Result<u32> {
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "no such file or directory in RomFS");
+        let path = path.trim_matches('/');
+        let (dirs, last) = match path.rfind('/') {
+            Some(end) => (&path[..end], &path[end + 1..]),
+            None => ("", path),
+        };
+        let mut parent = 0;
+        for component in dirs.split('/').filter(|c| !c.is_empty()) {
+            parent = self.lookup(parent, component, EntryKind::Dir)?.ok_or_else(not_found)?;
+        }
+        if last.is_empty() {
+            return if kind == EntryKind::Dir { Ok(0) } else { Err(not_found()) };
+        }
+        self.lookup(parent, last, kind)?.ok_or_else(not_found)
+    }
+
+    /// Lists the directory at `path`: subdirectories first, then files,
+    /// each in the order the image stores them.
+    pub fn read_dir(&self, path: &str) -> io::Result<ReadDir<'_, R>> {
+        let dir = self.dir(self.resolve(path, EntryKind::Dir)?)?;
+        Ok(ReadDir {
+            romfs: self,
+            next_dir: dir.child_dir,
+            next_file: dir.child_file,
+            remaining: self.dir_meta.len() / DIR_ENTRY_SIZE + self.file_meta.len() / FILE_ENTRY_SIZE,
+        })
+    }
+
+    /// Opens the file at `path` for reading.
+    pub fn open(&mut self, path: &str) -> io::Result<EntryReader<'_, R>> {
+        let (offset, size) = {
+            let file = self.file(self.resolve(path, EntryKind::File)?)?;
+            (file.offset, file.size)
+        };
+        let start = self.base + self.header.file_data + offset;
+        Ok(EntryReader::new(&mut self.inner, start, size))
+    }
+
+    pub fn read(&mut self, path: &str) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.open(path)?.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl RomFs<File> {
+    pub fn open_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        RomFs::new(File::open(path)?)
+    }
+}
+
+/// An iterator over a directory's entries.
+pub struct ReadDir<'a, R: 'a> {
+    romfs: &'a RomFs<R>,
+    next_dir: u32,
+    next_file: u32,
+    /// Bounds the walk on corrupt images whose sibling links loop.
+    remaining: usize,
+}
+
+impl<'a, R: Read + Seek> Iterator for ReadDir<'a, R> {
+    type Item = io::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<io::Result<DirEntry>> {
+        if self.next_dir == EMPTY && self.next_file == EMPTY {
+            return None;
+        }
+        if self.remaining == 0 {
+            self.next_dir = EMPTY;
+            self.next_file = EMPTY;
+            return Some(Err(invalid_data("RomFS sibling links loop")));
+        }
+        self.remaining -= 1;
+        let romfs = self.romfs;
+        let entry = if self.next_dir != EMPTY {
+            romfs.dir(self.next_dir).map(|dir| (dir.name, EntryKind::Dir, 0, dir.sibling))
+        } else {
+            romfs.file(self.next_file).map(|file| (file.name, EntryKind::File, file.size, file.sibling))
+        };
+        match entry {
+            Ok((name, kind, len, sibling)) => {
+                if kind == EntryKind::Dir {
+                    self.next_dir = sibling;
+                } else {
+                    self.next_file = sibling;
+                }
+                Some(Ok(DirEntry { name: String::from_utf8_lossy(name).into_owned(), kind, len }))
+            }
+            Err(err) => {
+                self.next_dir = EMPTY;
+                self.next_file = EMPTY;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut builder = Builder::new();
+        builder.add_bytes("hello.txt", b"Hello, world!".to_vec()).unwrap();
+        builder.add_bytes("/data/b.bin", vec![0xbb; 0x21]).unwrap();
+        builder.add_bytes("data/a.bin", vec![0xaa; 3]).unwrap();
+        builder.add_bytes("data/nested/deep/empty", vec![]).unwrap();
+        builder.add_dir("empty").unwrap();
+        builder.to_bytes().unwrap()
+    }
+
+    fn names<R: Read + Seek>(romfs: &RomFs<R>, path: &str) -> Vec<String> {
+        romfs.read_dir(path).unwrap().map(|entry| entry.unwrap().name).collect()
+    }
+
+    #[test]
+    fn hashes_like_build_romfs() {
+        assert_eq!(path_hash(0, b""), 123_456_789);
+        assert_eq!(path_hash(0, b"a"), 123_456_789u32.rotate_right(5) ^ 0x61);
+        assert_eq!(hash_table_len(1), 3);
+        assert_eq!(hash_table_len(4), 5);
+        assert_eq!(hash_table_len(19), 19);
+        assert_eq!(hash_table_len(20), 23);
+    }
+
+    #[test]
+    fn lays_out_image() {
+        let image = sample();
+        let header = Header::parse(&image).unwrap();
+        assert_eq!(header.file_data, FILE_DATA_OFFSET);
+        // Files are stored in path order, each aligned.
+        assert_eq!(&image[0x200..0x203], &[0xaa; 3]);
+        assert_eq!(&image[0x210..0x231], &[0xbb; 0x21][..]);
+        assert_eq!(&image[0x240..0x24d], b"Hello, world!");
+        assert_eq!(header.dir_hash, (0x250, 5 * 4));
+        assert_eq!(header.dir_meta.0, 0x264);
+        assert_eq!(header.file_meta.0 + header.file_meta.1, image.len() as u64);
+    }
+
+    #[test]
+    fn reads_dirs_and_files() {
+        let mut romfs = RomFs::new(Cursor::new(sample())).unwrap();
+        assert_eq!(names(&romfs, "/"), ["data", "empty", "hello.txt"]);
+        assert_eq!(names(&romfs, "data"), ["nested", "a.bin", "b.bin"]);
+        assert_eq!(names(&romfs, "/data/nested/"), ["deep"]);
+        assert_eq!(names(&romfs, "empty"), Vec::<String>::new());
+
+        let entries: Vec<_> = romfs.read_dir("data").unwrap().map(Result::unwrap).collect();
+        assert!(entries[0].is_dir());
+        assert_eq!(entries[2].len, 0x21);
+
+        assert_eq!(romfs.read("hello.txt").unwrap(), b"Hello, world!");
+        assert_eq!(romfs.read("/data/b.bin").unwrap(), vec![0xbb; 0x21]);
+        assert_eq!(romfs.read("data/nested/deep/empty").unwrap(), b"");
+
+        let mut file = romfs.open("data/b.bin").unwrap();
+        file.seek(SeekFrom::End(-1)).unwrap();
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, [0xbb]);
+
+        for missing in &["nope", "data", "data/a.bin/x", "empty/x"] {
+            assert_eq!(romfs.open(missing).err().unwrap().kind(), io::ErrorKind::NotFound);
+        }
+        assert!(romfs.read_dir("hello.txt").is_err());
+    }
+
+    #[test]
+    fn builds_from_host_dir() {
+        let root = env::temp_dir().join(format!("nx-romfs-test-{}", ::std::process::id()));
+        fs::create_dir_all(root.join("sub/empty")).unwrap();
+        fs::write(root.join("top"), b"top").unwrap();
+        fs::write(root.join("sub/inner"), b"inner").unwrap();
+
+        let image = Builder::from_dir(&root).unwrap().to_bytes();
+        fs::remove_dir_all(&root).unwrap();
+        let mut romfs = RomFs::new(Cursor::new(image.unwrap())).unwrap();
+        assert_eq!(names(&romfs, ""), ["sub", "top"]);
+        assert_eq!(names(&romfs, "sub"), ["empty", "inner"]);
+        assert_eq!(romfs.read("sub/inner").unwrap(), b"inner");
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let mut builder = Builder::new();
+        builder.add_bytes("a", vec![]).unwrap();
+        assert!(builder.add_dir("a").is_err());
+        assert!(builder.add_bytes("a/b", vec![]).is_err());
+        assert!(builder.add_bytes("../b", vec![]).is_err());
+        assert!(builder.add_bytes("b//c", vec![]).is_err());
+
+        let image = sample();
+        assert!(RomFs::new(Cursor::new(&image[..image.len() - 1])).is_err());
+        assert!(RomFs::new(Cursor::new(&image[..0x40])).is_err());
+    }
+}
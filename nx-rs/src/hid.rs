@@ -33,23 +33,11 @@ pub enum JoyConHoldMode {
     Horizontal,
 }
 
-fn ctrlid_to_controller(id: ::libnx::HidControllerID) -> Controller {
-    match id {
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_1 => Controller::Player(1),
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_2 => Controller::Player(2),
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_3 => Controller::Player(3),
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_4 => Controller::Player(4),
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_5 => Controller::Player(5),
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_6 => Controller::Player(6),
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_7 => Controller::Player(7),
-        ::libnx::HidControllerID_CONTROLLER_PLAYER_8 => Controller::Player(8),
-        ::libnx::HidControllerID_CONTROLLER_HANDHELD => Controller::Handheld,
-        ::libnx::HidControllerID_CONTROLLER_P1_AUTO => Controller::Auto,
-        _ => Controller::Invalid,
-    }
-}
+// libnx 4.0 replaced HidControllerID and the hidKeys* functions with npad IDs and `PadState`, so
+// the functions below come in one version per API generation.
 
-fn controller_to_ctrlid(id: Controller) -> ::libnx::HidControllerID {
+#[cfg(not(libnx_v4))]
+pub(crate) fn controller_to_ctrlid(id: Controller) -> ::libnx::HidControllerID {
     match id {
         Controller::Player(1) => ::libnx::HidControllerID_CONTROLLER_PLAYER_1,
         Controller::Player(2) => ::libnx::HidControllerID_CONTROLLER_PLAYER_2,
@@ -65,16 +53,19 @@ fn controller_to_ctrlid(id: Controller) -> ::libnx::HidControllerID {
     }
 }
 
+#[cfg(not(libnx_v4))]
 pub fn is_controller_connected(ctrl: Controller) -> bool {
     unsafe { ::libnx::hidIsControllerConnected(controller_to_ctrlid(ctrl)) }
 }
 
+#[cfg(not(libnx_v4))]
 pub fn flush() {
     unsafe {
         ::libnx::hidScanInput();
     }
 }
 
+#[cfg(not(libnx_v4))]
 pub fn input_down(ctrl: Controller) -> u64 {
     flush();
     unsafe {
@@ -82,6 +73,7 @@ pub fn input_down(ctrl: Controller) -> u64 {
     }
 }
 
+#[cfg(not(libnx_v4))]
 pub fn input_up(ctrl: Controller) -> u64 {
     unsafe {
         flush();
@@ -89,6 +81,7 @@ pub fn input_up(ctrl: Controller) -> u64 {
     }
 }
 
+#[cfg(not(libnx_v4))]
 pub fn input_held(ctrl: Controller) -> u64 {
     unsafe {
         flush();
@@ -96,10 +89,12 @@ pub fn input_held(ctrl: Controller) -> u64 {
     }
 }
 
+#[cfg(not(libnx_v4))]
 pub fn get_touch_count() -> u32 {
     unsafe { ::libnx::hidTouchCount() }
 }
 
+#[cfg(not(libnx_v4))]
 pub fn get_touch_coords(index: u32) -> (u32, u32) {
     flush();
     unsafe {
@@ -108,3 +103,122 @@ pub fn get_touch_coords(index: u32) -> (u32, u32) {
         (tch.px, tch.py)
     }
 }
+
+#[cfg(libnx_v4)]
+mod pads {
+    use std::mem;
+    use std::ptr;
+    use std::sync::{Mutex, MutexGuard, Once};
+
+    use super::Controller;
+
+    const PAD_COUNT: usize = 10;
+
+    static INIT: Once = Once::new();
+    static mut PADS: *const Mutex<[::libnx::PadState; PAD_COUNT]> = ptr::null();
+
+    fn npad_mask(id: ::libnx::HidNpadIdType) -> u64 {
+        1 << id
+    }
+
+    /// The pad state slot and npad ID mask for `ctrl`. `Auto` reads player 1 or handheld mode,
+    /// like libnx's `padInitializeDefault`.
+    fn slot(ctrl: Controller) -> Option<(usize, u64)> {
+        match ctrl {
+            Controller::Player(n @ 1..=8) => Some((n as usize - 1, npad_mask(::libnx::HidNpadIdType_HidNpadIdType_No1 + n as u32 - 1))),
+            Controller::Handheld => Some((8, npad_mask(::libnx::HidNpadIdType_HidNpadIdType_Handheld))),
+            Controller::Auto => Some((9, npad_mask(::libnx::HidNpadIdType_HidNpadIdType_No1) | npad_mask(::libnx::HidNpadIdType_HidNpadIdType_Handheld))),
+            _ => None,
+        }
+    }
+
+    fn init() {
+        INIT.call_once(|| unsafe {
+            ::libnx::padConfigureInput(8, ::libnx::HidNpadStyleSet_HidNpadStyleSet_NpadStandard);
+            ::libnx::hidInitializeTouchScreen();
+            let mut pads: [::libnx::PadState; PAD_COUNT] = mem::zeroed();
+            for ctrl in (1..9).map(Controller::Player).chain(vec![Controller::Handheld, Controller::Auto]) {
+                let (index, mask) = slot(ctrl).unwrap();
+                ::libnx::padInitializeWithMask(&mut pads[index], mask);
+            }
+            PADS = Box::into_raw(Box::new(Mutex::new(pads)));
+        });
+    }
+
+    fn pads() -> MutexGuard<'static, [::libnx::PadState; PAD_COUNT]> {
+        init();
+        unsafe { (*PADS).lock().unwrap() }
+    }
+
+    /// Updates `ctrl`'s pad state and runs `f` on it. Invalid controllers read as the default
+    /// value.
+    pub fn with<T: Default, F: FnOnce(&mut ::libnx::PadState) -> T>(ctrl: Controller, f: F) -> T {
+        match slot(ctrl) {
+            Some((index, _)) => {
+                let mut pads = pads();
+                let pad = &mut pads[index];
+                unsafe {
+                    ::libnx::padUpdate(pad);
+                }
+                f(pad)
+            }
+            None => T::default(),
+        }
+    }
+
+    pub fn update_all() {
+        for pad in pads().iter_mut() {
+            unsafe {
+                ::libnx::padUpdate(pad);
+            }
+        }
+    }
+
+    pub fn touch_state() -> ::libnx::HidTouchScreenState {
+        init();
+        unsafe {
+            let mut state: ::libnx::HidTouchScreenState = mem::zeroed();
+            ::libnx::hidGetTouchScreenStates(&mut state, 1);
+            state
+        }
+    }
+}
+
+#[cfg(libnx_v4)]
+pub fn is_controller_connected(ctrl: Controller) -> bool {
+    pads::with(ctrl, |pad| unsafe { ::libnx::padIsConnected(pad) })
+}
+
+#[cfg(libnx_v4)]
+pub fn flush() {
+    pads::update_all();
+}
+
+#[cfg(libnx_v4)]
+pub fn input_down(ctrl: Controller) -> u64 {
+    pads::with(ctrl, |pad| unsafe { ::libnx::padGetButtonsDown(pad) })
+}
+
+#[cfg(libnx_v4)]
+pub fn input_up(ctrl: Controller) -> u64 {
+    pads::with(ctrl, |pad| unsafe { ::libnx::padGetButtonsUp(pad) })
+}
+
+#[cfg(libnx_v4)]
+pub fn input_held(ctrl: Controller) -> u64 {
+    pads::with(ctrl, |pad| unsafe { ::libnx::padGetButtons(pad) })
+}
+
+#[cfg(libnx_v4)]
+pub fn get_touch_count() -> u32 {
+    pads::touch_state().count as u32
+}
+
+#[cfg(libnx_v4)]
+pub fn get_touch_coords(index: u32) -> (u32, u32) {
+    let state = pads::touch_state();
+    match state.touches.get(index as usize) {
+        Some(touch) if (index as i32) < state.count => (touch.x, touch.y),
+        _ => (0, 0),
+    }
+}
@@ -0,0 +1,336 @@
+//! Native HIPC/CMIF messaging.
+//!
+//! `Request` and `Response` serialize to and parse from a plain byte buffer,
+//! laid out the way the kernel expects the thread-local IPC buffer. `Session`
+//! wraps a libnx `Service` and does the TLS round-trip.
+
+use std::mem;
+use std::slice;
+
+use os;
+use result::names::{MODULE_CMIF, MODULE_KERNEL, MODULE_LIBNX};
+use ResultCode;
+
+mod request;
+mod response;
+
+pub use self::request::Request;
+pub use self::response::Response;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Plain data `Request::push` and `Response::pop` can copy to and from the
+/// raw payload.
+///
+/// # Safety
+///
+/// The type must have no padding bytes, and any bytes a service sends back
+/// must be a valid value of it. That rules out types like `bool` and enums,
+/// so the trait is sealed and only implemented for integers and arrays of
+/// them.
+pub unsafe trait Pod: Copy + sealed::Sealed {}
+
+macro_rules! pod {
+    ($($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+            unsafe impl Pod for $ty {}
+        )*
+    };
+}
+
+macro_rules! pod_arrays {
+    ($($len:expr),*) => {
+        $(
+            impl<T: Pod> sealed::Sealed for [T; $len] {}
+            unsafe impl<T: Pod> Pod for [T; $len] {}
+        )*
+    };
+}
+
+pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+pod_arrays!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+            17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32,
+            0x30, 0x40, 0x80, 0x100);
+
+/// Size of the thread-local IPC message buffer.
+pub const TLS_MESSAGE_SIZE: usize = 0x100;
+
+/// The message does not fit in the buffer it is being written to.
+pub const RESULT_MESSAGE_TOO_LARGE: ResultCode = ResultCode::new(MODULE_KERNEL, 260);
+/// The message has more handles or descriptors than the header can encode.
+pub const RESULT_BAD_INPUT: ResultCode = ResultCode::new(MODULE_LIBNX, 11);
+/// The response is truncated or lacks the `SFCO` magic.
+pub const RESULT_INVALID_OUT_HEADER: ResultCode = ResultCode::new(MODULE_CMIF, 212);
+
+pub(crate) const CMIF_IN_HEADER_MAGIC: u32 = 0x4943_4653; // "SFCI"
+pub(crate) const CMIF_OUT_HEADER_MAGIC: u32 = 0x4f43_4653; // "SFCO"
+
+/// The HIPC message type, stored in the low half of the first header word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandType {
+    Invalid = 0,
+    LegacyRequest = 1,
+    Close = 2,
+    LegacyControl = 3,
+    Request = 4,
+    Control = 5,
+    RequestWithContext = 6,
+    ControlWithContext = 7,
+}
+
+/// The operation a domain message performs on its target object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DomainCommand {
+    SendMessage = 1,
+    Close = 2,
+}
+
+/// Memory mapping attributes for A/B/W buffers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferMode {
+    Normal = 0,
+    NonSecure = 1,
+    NonDevice = 3,
+}
+
+impl BufferMode {
+    fn from_bits(bits: u32) -> Self {
+        match bits & 3 {
+            1 => BufferMode::NonSecure,
+            3 => BufferMode::NonDevice,
+            _ => BufferMode::Normal,
+        }
+    }
+}
+
+/// A buffer descriptor as it appears on the wire.
+///
+/// For X and C descriptors `index` is the pointer buffer index and `mode` is
+/// unused. For A, B and W descriptors `index` is unused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Buffer {
+    pub address: u64,
+    pub size: u64,
+    pub mode: BufferMode,
+    pub index: u16,
+}
+
+impl Buffer {
+    pub fn new(address: u64, size: u64) -> Self {
+        Buffer {
+            address,
+            size,
+            mode: BufferMode::Normal,
+            index: 0,
+        }
+    }
+
+    pub fn from_slice<T>(data: &[T]) -> Self {
+        Self::new(data.as_ptr() as u64, mem::size_of_val(data) as u64)
+    }
+
+    pub fn with_mode(mut self, mode: BufferMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_index(mut self, index: u16) -> Self {
+        self.index = index;
+        self
+    }
+}
+
+/// An open session to a service, driven through the thread's IPC buffer.
+pub struct Session {
+    srv: ::libnx::Service,
+}
+
+impl Session {
+    pub fn from_service(srv: ::libnx::Service) -> Self {
+        Session { srv }
+    }
+
+    pub fn handle(&self) -> u32 {
+        self.srv.handle
+    }
+
+    pub fn is_domain(&self) -> bool {
+        self.srv.type_ == ::libnx::ServiceType_ServiceType_Domain
+            || self.srv.type_ == ::libnx::ServiceType_ServiceType_DomainSubservice
+    }
+
+    pub fn object_id(&self) -> u32 {
+        self.srv.object_id
+    }
+
+    /// Starts a request for `command_id`, addressed to this session's domain
+    /// object if it is one.
+    pub fn request(&self, command_id: u32) -> Request {
+        let mut req = Request::new(command_id);
+        if self.is_domain() {
+            req.set_domain(self.object_id());
+        }
+        req
+    }
+
+    /// Sends `req` and waits for the reply.
+    ///
+    /// The result carried in the reply's CMIF header is checked, so `Ok` means
+    /// the command itself succeeded.
+    pub fn dispatch(&mut self, req: &Request) -> os::Result<Response> {
+        unsafe {
+            let tls = slice::from_raw_parts_mut(::libnx::armGetTls() as *mut u8, TLS_MESSAGE_SIZE);
+            req.serialize(tls)?;
+            let rc = ::libnx::svcSendSyncRequest(self.srv.handle);
+            result_assert!(rc);
+            let resp = Response::parse(tls, req.domain_object().is_some())?;
+            if resp.result.is_failure() {
+                return Err(resp.result);
+            }
+            Ok(resp)
+        }
+    }
+
+    pub fn into_service(self) -> ::libnx::Service {
+        let srv = unsafe { ::std::ptr::read(&self.srv) };
+        mem::forget(self);
+        srv
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        unsafe {
+            ::libnx::serviceClose(&mut self.srv);
+        }
+    }
+}
+
+pub(crate) fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(buf: &[u8]) -> Vec<u32> {
+        buf.chunks(4).map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]])).collect()
+    }
+
+    #[test]
+    fn serializes_plain_request() {
+        let mut req = Request::new(3);
+        req.push(0x1122_3344u32).push(7u64);
+
+        let mut buf = [0xffu8; TLS_MESSAGE_SIZE];
+        let len = req.serialize(&mut buf).unwrap();
+        let w = words(&buf[..len]);
+
+        assert_eq!(w[0], 4);
+        // 16 bytes of CMIF header plus 16 of payload, plus 4 words of slack.
+        assert_eq!(w[1], 8 + 4);
+        assert_eq!(&w[2..4], &[0, 0]);
+        assert_eq!(&w[4..8], &[CMIF_IN_HEADER_MAGIC, 0, 3, 0]);
+        assert_eq!(&w[8..12], &[0x1122_3344, 0, 7, 0]);
+        assert_eq!(len, 8 + 12 * 4);
+    }
+
+    #[test]
+    fn serializes_handles_and_buffers() {
+        let mut req = Request::new(1);
+        req.send_pid()
+            .copy_handle(0x10)
+            .move_handle(0x20)
+            .pointer(Buffer::new(0x12_3456_7890, 0x40).with_index(2))
+            .send_buffer(Buffer::new(0x8_0000_1000, 0x1_0000_0200).with_mode(BufferMode::NonSecure))
+            .recv_pointer(Buffer::new(0x5000, 0x80));
+
+        let mut buf = [0u8; TLS_MESSAGE_SIZE];
+        let len = req.serialize(&mut buf).unwrap();
+        let w = words(&buf[..len]);
+
+        assert_eq!(w[0], 4 | 1 << 16 | 1 << 20);
+        assert_eq!(w[1] & 0x8000_0000, 0x8000_0000);
+        assert_eq!((w[1] >> 10) & 0xf, 3);
+        assert_eq!(w[2], 1 | 1 << 1 | 1 << 5);
+        assert_eq!(&w[5..7], &[0x10, 0x20]);
+        assert_eq!(w[7], 2 | 1 << 6 | 2 << 12 | 0x40 << 16);
+        assert_eq!(w[8], 0x3456_7890);
+        assert_eq!(&w[9..12], &[0x200, 0x1000, 1 | 1 << 24 | 8 << 28]);
+
+        // Descriptors end at word 12, so the CMIF header lands on word 12 too.
+        assert_eq!(w[12], CMIF_IN_HEADER_MAGIC);
+        let raw_words = (w[1] & 0x3ff) as usize;
+        assert_eq!(raw_words, 4 + 4 + 1);
+        let c = 12 + raw_words;
+        assert_eq!(&w[c - 1..c], &[0x80]);
+        assert_eq!(&w[c..c + 2], &[0x5000, 0x80 << 16]);
+    }
+
+    #[test]
+    fn serializes_domain_request() {
+        let mut req = Request::new(5);
+        req.set_domain(0x33).in_object(0x44).push(1u32);
+
+        let mut buf = [0u8; TLS_MESSAGE_SIZE];
+        let len = req.serialize(&mut buf).unwrap();
+        let w = words(&buf[..len]);
+
+        assert_eq!(w[4], 1 | 1 << 8 | 20 << 16);
+        assert_eq!(w[5], 0x33);
+        assert_eq!(&w[8..12], &[CMIF_IN_HEADER_MAGIC, 0, 5, 0]);
+        assert_eq!(&w[12..14], &[1, 0x44]);
+    }
+
+    #[test]
+    fn rejects_small_buffer() {
+        let mut req = Request::new(0);
+        req.push_bytes(&[0; 64]);
+        let mut buf = [0u8; 32];
+        assert_eq!(req.serialize(&mut buf).unwrap_err(), RESULT_MESSAGE_TOO_LARGE);
+    }
+
+    fn response(domain: bool, result: u32, payload: &[u32]) -> Vec<u8> {
+        let mut w = vec![0u32, 0x8000_0000, 1 | 1 << 1 | 1 << 5, 0x1234, 0, 0xaa, 0xbb, 0];
+        if domain {
+            w.extend_from_slice(&[payload.len() as u32 - 1, 0, 0, 0]);
+        }
+        w.extend_from_slice(&[CMIF_OUT_HEADER_MAGIC, 0, result, 0]);
+        w.extend_from_slice(payload);
+        w[1] |= (w.len() - 7 + 4) as u32;
+        w.resize(TLS_MESSAGE_SIZE / 4, 0);
+        w.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn parses_response() {
+        let buf = response(false, 0, &[0xdead_beef, 0, 5, 0, 0x0403_0201]);
+        let mut resp = Response::parse(&buf, false).unwrap();
+        assert!(resp.result.is_success());
+        assert_eq!(resp.pid, Some(0x1234));
+        assert_eq!(resp.copy_handles, vec![0xaa]);
+        assert_eq!(resp.move_handles, vec![0xbb]);
+        assert_eq!(resp.pop::<u32>().unwrap(), 0xdead_beef);
+        assert_eq!(resp.pop::<u64>().unwrap(), 5);
+        assert_eq!(resp.pop::<[u8; 4]>().unwrap(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn parses_domain_response() {
+        let buf = response(true, 0x202, &[9, 0x77]);
+        let resp = Response::parse(&buf, true).unwrap();
+        assert_eq!(resp.result, ResultCode::new(2, 1));
+        assert_eq!(resp.out_objects(4).unwrap(), vec![0x77]);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut buf = response(false, 0, &[]);
+        buf[32] = 0;
+        assert_eq!(Response::parse(&buf, false).unwrap_err(), RESULT_INVALID_OUT_HEADER);
+    }
+}
@@ -0,0 +1,269 @@
+use std::mem;
+use std::slice;
+
+use os;
+use super::{align_up, Buffer, CommandType, DomainCommand, Pod, CMIF_IN_HEADER_MAGIC, RESULT_BAD_INPUT, RESULT_MESSAGE_TOO_LARGE};
+
+/// An outgoing CMIF request.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub command_type: CommandType,
+    pub command_id: u32,
+    pub send_pid: bool,
+    pub copy_handles: Vec<u32>,
+    pub move_handles: Vec<u32>,
+    /// X descriptors.
+    pub pointers: Vec<Buffer>,
+    /// A descriptors.
+    pub sends: Vec<Buffer>,
+    /// B descriptors.
+    pub receives: Vec<Buffer>,
+    /// W descriptors.
+    pub exchanges: Vec<Buffer>,
+    /// C descriptors.
+    pub recv_pointers: Vec<Buffer>,
+    pub domain_command: DomainCommand,
+    pub in_objects: Vec<u32>,
+    domain_object: Option<u32>,
+    raw: Vec<u8>,
+}
+
+impl Request {
+    pub fn new(command_id: u32) -> Self {
+        Request {
+            command_type: CommandType::Request,
+            command_id,
+            send_pid: false,
+            copy_handles: Vec::new(),
+            move_handles: Vec::new(),
+            pointers: Vec::new(),
+            sends: Vec::new(),
+            receives: Vec::new(),
+            exchanges: Vec::new(),
+            recv_pointers: Vec::new(),
+            domain_command: DomainCommand::SendMessage,
+            in_objects: Vec::new(),
+            domain_object: None,
+            raw: Vec::new(),
+        }
+    }
+
+    /// Addresses the request to an object of a domain session.
+    pub fn set_domain(&mut self, object_id: u32) -> &mut Self {
+        self.domain_object = Some(object_id);
+        self
+    }
+
+    pub fn domain_object(&self) -> Option<u32> {
+        self.domain_object
+    }
+
+    pub fn send_pid(&mut self) -> &mut Self {
+        self.send_pid = true;
+        self
+    }
+
+    pub fn copy_handle(&mut self, handle: u32) -> &mut Self {
+        self.copy_handles.push(handle);
+        self
+    }
+
+    pub fn move_handle(&mut self, handle: u32) -> &mut Self {
+        self.move_handles.push(handle);
+        self
+    }
+
+    pub fn pointer(&mut self, buf: Buffer) -> &mut Self {
+        self.pointers.push(buf);
+        self
+    }
+
+    pub fn send_buffer(&mut self, buf: Buffer) -> &mut Self {
+        self.sends.push(buf);
+        self
+    }
+
+    pub fn receive_buffer(&mut self, buf: Buffer) -> &mut Self {
+        self.receives.push(buf);
+        self
+    }
+
+    pub fn exchange_buffer(&mut self, buf: Buffer) -> &mut Self {
+        self.exchanges.push(buf);
+        self
+    }
+
+    pub fn recv_pointer(&mut self, buf: Buffer) -> &mut Self {
+        self.recv_pointers.push(buf);
+        self
+    }
+
+    pub fn in_object(&mut self, object_id: u32) -> &mut Self {
+        self.in_objects.push(object_id);
+        self
+    }
+
+    /// Appends a `Pod` value to the raw payload, aligned to its natural
+    /// alignment as the C structs libnx sends would be.
+    pub fn push<T: Pod>(&mut self, value: T) -> &mut Self {
+        let aligned = align_up(self.raw.len(), mem::align_of::<T>());
+        self.raw.resize(aligned, 0);
+        let bytes = unsafe { slice::from_raw_parts(&value as *const T as *const u8, mem::size_of::<T>()) };
+        self.raw.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn push_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.raw.extend_from_slice(bytes);
+        self
+    }
+
+    /// The raw payload following the CMIF header.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Writes the message into `buf`, returning the number of bytes used.
+    ///
+    /// `buf` stands in for the thread's IPC buffer, so it is assumed to start
+    /// on a 16-byte boundary; the raw data section is padded relative to it.
+    pub fn serialize(&self, buf: &mut [u8]) -> os::Result<usize> {
+        if self.pointers.len() > 15 || self.sends.len() > 15 || self.receives.len() > 15
+            || self.exchanges.len() > 15 || self.recv_pointers.len() > 13
+            || self.copy_handles.len() > 15 || self.move_handles.len() > 15
+            || self.in_objects.len() > 0xff {
+            return Err(RESULT_BAD_INPUT);
+        }
+
+        let mut w = Writer { buf, pos: 0 };
+        w.word(self.command_type as u32
+            | (self.pointers.len() as u32) << 16
+            | (self.sends.len() as u32) << 20
+            | (self.receives.len() as u32) << 24
+            | (self.exchanges.len() as u32) << 28)?;
+
+        let size_word = w.pos;
+        let mut word1 = if self.recv_pointers.is_empty() { 0 } else { (self.recv_pointers.len() as u32 + 2) << 10 };
+        w.word(0)?;
+
+        if self.send_pid || !self.copy_handles.is_empty() || !self.move_handles.is_empty() {
+            word1 |= 0x8000_0000;
+            w.word(self.send_pid as u32
+                | (self.copy_handles.len() as u32) << 1
+                | (self.move_handles.len() as u32) << 5)?;
+            if self.send_pid {
+                // Filled in by the kernel.
+                w.word(0)?;
+                w.word(0)?;
+            }
+            for &handle in self.copy_handles.iter().chain(self.move_handles.iter()) {
+                w.word(handle)?;
+            }
+        }
+
+        for x in &self.pointers {
+            let addr = x.address;
+            let index = x.index as u32;
+            w.word((index & 0x3f)
+                | ((addr >> 36) as u32 & 7) << 6
+                | ((index >> 6) & 7) << 9
+                | ((addr >> 32) as u32 & 0xf) << 12
+                | (x.size as u32 & 0xffff) << 16)?;
+            w.word(addr as u32)?;
+        }
+
+        for abw in self.sends.iter().chain(self.receives.iter()).chain(self.exchanges.iter()) {
+            let addr = abw.address;
+            w.word(abw.size as u32)?;
+            w.word(addr as u32)?;
+            w.word(abw.mode as u32
+                | ((addr >> 36) as u32 & 7) << 2
+                | ((abw.size >> 32) as u32 & 0xf) << 24
+                | ((addr >> 32) as u32 & 0xf) << 28)?;
+        }
+
+        // The raw section is counted from the end of the descriptors and
+        // reserves four words for the alignment padding, as libnx does.
+        let desc_end = w.pos;
+        w.pad_to(align_up(desc_end, 16))?;
+
+        let cmif_size = 16 + self.raw.len();
+        if let Some(object_id) = self.domain_object {
+            w.bytes(&[self.domain_command as u8, self.in_objects.len() as u8])?;
+            w.bytes(&(cmif_size as u16).to_le_bytes())?;
+            w.word(object_id)?;
+            w.word(0)?;
+            w.word(0)?;
+        }
+        w.word(CMIF_IN_HEADER_MAGIC)?;
+        w.word(0)?;
+        w.word(self.command_id)?;
+        w.word(0)?;
+        w.bytes(&self.raw)?;
+        if self.domain_object.is_some() {
+            for &object_id in &self.in_objects {
+                w.word(object_id)?;
+            }
+        }
+
+        let data_size = w.pos - align_up(desc_end, 16);
+        let mut raw_words = (data_size + 3) / 4 + 4;
+        w.pad_to(desc_end + raw_words * 4)?;
+
+        if !self.recv_pointers.is_empty() {
+            for c in &self.recv_pointers {
+                let size = if c.size > 0xffff { 0 } else { c.size as u16 };
+                w.bytes(&size.to_le_bytes())?;
+            }
+            let u16_words = (2 * self.recv_pointers.len() + 3) / 4;
+            raw_words += u16_words;
+            w.pad_to(desc_end + raw_words * 4)?;
+
+            for c in &self.recv_pointers {
+                w.word(c.address as u32)?;
+                w.word(((c.address >> 32) as u32 & 0xffff) | (c.size as u32 & 0xffff) << 16)?;
+            }
+        }
+
+        if raw_words > 0x3ff {
+            return Err(RESULT_MESSAGE_TOO_LARGE);
+        }
+        let end = w.pos;
+        w.pos = size_word;
+        w.word(word1 | raw_words as u32)?;
+        Ok(end)
+    }
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Writer<'a> {
+    fn bytes(&mut self, data: &[u8]) -> os::Result<()> {
+        let end = self.pos + data.len();
+        if end > self.buf.len() {
+            return Err(RESULT_MESSAGE_TOO_LARGE);
+        }
+        self.buf[self.pos..end].copy_from_slice(data);
+        self.pos = end;
+        Ok(())
+    }
+
+    fn word(&mut self, value: u32) -> os::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// Zero-fills up to `end`, so stale TLS contents never end up in padding.
+    fn pad_to(&mut self, end: usize) -> os::Result<()> {
+        if end > self.buf.len() {
+            return Err(RESULT_MESSAGE_TOO_LARGE);
+        }
+        for byte in &mut self.buf[self.pos..end] {
+            *byte = 0;
+        }
+        self.pos = end;
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+use std::mem;
+use std::ptr;
+
+use os;
+use ResultCode;
+use super::{align_up, Buffer, BufferMode, Pod, CMIF_OUT_HEADER_MAGIC, RESULT_INVALID_OUT_HEADER};
+
+/// A parsed CMIF reply.
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub command_type: u16,
+    /// The result from the CMIF header, i.e. whether the command succeeded.
+    pub result: ResultCode,
+    pub pid: Option<u64>,
+    pub copy_handles: Vec<u32>,
+    pub move_handles: Vec<u32>,
+    /// X descriptors.
+    pub pointers: Vec<Buffer>,
+    /// A descriptors.
+    pub sends: Vec<Buffer>,
+    /// B descriptors.
+    pub receives: Vec<Buffer>,
+    /// W descriptors.
+    pub exchanges: Vec<Buffer>,
+    num_out_objects: usize,
+    raw: Vec<u8>,
+    cursor: usize,
+}
+
+impl Response {
+    /// Parses a reply from `buf`, which is assumed to start on a 16-byte
+    /// boundary like the thread's IPC buffer. `domain` says whether the reply
+    /// carries a domain header, i.e. whether the request was sent to a domain.
+    pub fn parse(buf: &[u8], domain: bool) -> os::Result<Self> {
+        let mut r = Reader { buf, pos: 0 };
+        let word0 = r.word()?;
+        let word1 = r.word()?;
+
+        let mut resp = Response {
+            command_type: word0 as u16,
+            result: ResultCode::SUCCESS,
+            pid: None,
+            copy_handles: Vec::new(),
+            move_handles: Vec::new(),
+            pointers: Vec::new(),
+            sends: Vec::new(),
+            receives: Vec::new(),
+            exchanges: Vec::new(),
+            num_out_objects: 0,
+            raw: Vec::new(),
+            cursor: 0,
+        };
+
+        if word1 & 0x8000_0000 != 0 {
+            let hdesc = r.word()?;
+            if hdesc & 1 != 0 {
+                let low = r.word()? as u64;
+                let high = r.word()? as u64;
+                resp.pid = Some(low | high << 32);
+            }
+            for _ in 0..(hdesc >> 1) & 0xf {
+                resp.copy_handles.push(r.word()?);
+            }
+            for _ in 0..(hdesc >> 5) & 0xf {
+                resp.move_handles.push(r.word()?);
+            }
+        }
+
+        for _ in 0..(word0 >> 16) & 0xf {
+            let packed = r.word()?;
+            let low = r.word()? as u64;
+            let index = (packed & 0x3f) | ((packed >> 9) & 7) << 6;
+            let address = low
+                | (((packed >> 12) & 0xf) as u64) << 32
+                | (((packed >> 6) & 7) as u64) << 36;
+            resp.pointers.push(Buffer::new(address, (packed >> 16) as u64).with_index(index as u16));
+        }
+
+        for (i, count) in [(word0 >> 20) & 0xf, (word0 >> 24) & 0xf, (word0 >> 28) & 0xf].iter().enumerate() {
+            for _ in 0..*count {
+                let size_low = r.word()? as u64;
+                let addr_low = r.word()? as u64;
+                let packed = r.word()?;
+                let address = addr_low
+                    | (((packed >> 28) & 0xf) as u64) << 32
+                    | (((packed >> 2) & 7) as u64) << 36;
+                let size = size_low | (((packed >> 24) & 0xf) as u64) << 32;
+                let buf = Buffer::new(address, size).with_mode(BufferMode::from_bits(packed));
+                match i {
+                    0 => resp.sends.push(buf),
+                    1 => resp.receives.push(buf),
+                    _ => resp.exchanges.push(buf),
+                }
+            }
+        }
+
+        let raw_end = r.pos + (word1 & 0x3ff) as usize * 4;
+        r.pos = align_up(r.pos, 16);
+        if raw_end > buf.len() || raw_end < r.pos {
+            return Err(RESULT_INVALID_OUT_HEADER);
+        }
+
+        if domain {
+            resp.num_out_objects = r.word()? as usize;
+            r.pos += 12;
+        }
+        if r.word()? != CMIF_OUT_HEADER_MAGIC {
+            return Err(RESULT_INVALID_OUT_HEADER);
+        }
+        let _version = r.word()?;
+        resp.result = ResultCode::from_raw(r.word()?);
+        let _padding = r.word()?;
+        if r.pos > raw_end {
+            return Err(RESULT_INVALID_OUT_HEADER);
+        }
+
+        resp.raw = buf[r.pos..raw_end].to_vec();
+        Ok(resp)
+    }
+
+    /// The raw payload following the CMIF header. It may include trailing
+    /// padding, since the header only counts whole words plus alignment slack.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Reads the next `Pod` value from the raw payload, honouring its
+    /// natural alignment the same way `Request::push` does.
+    pub fn pop<T: Pod>(&mut self) -> os::Result<T> {
+        let start = align_up(self.cursor, mem::align_of::<T>());
+        let end = start + mem::size_of::<T>();
+        if end > self.raw.len() {
+            return Err(RESULT_INVALID_OUT_HEADER);
+        }
+        self.cursor = end;
+        Ok(unsafe { ptr::read_unaligned(self.raw[start..].as_ptr() as *const T) })
+    }
+
+    /// The object IDs a domain reply returns. They follow the payload, so the
+    /// caller has to say how long the payload it expects is.
+    pub fn out_objects(&self, payload_size: usize) -> os::Result<Vec<u32>> {
+        let end = payload_size + self.num_out_objects * 4;
+        if end > self.raw.len() {
+            return Err(RESULT_INVALID_OUT_HEADER);
+        }
+        Ok(self.raw[payload_size..end]
+            .chunks(4)
+            .map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]))
+            .collect())
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn word(&mut self) -> os::Result<u32> {
+        let end = self.pos + 4;
+        if end > self.buf.len() {
+            return Err(RESULT_INVALID_OUT_HEADER);
+        }
+        let b = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
@@ -1,16 +1,39 @@
 #![macro_use]
+#[cfg(all(feature = "nx-sys", not(feature = "host-mock")))]
 extern crate nx_sys as libnx;
+extern crate sha2;
+
+#[cfg(feature = "host-mock")]
+pub mod mock;
+#[cfg(feature = "host-mock")]
+use mock::libnx;
 
 pub mod macros;
+pub mod result;
+pub mod formats;
+
+// The modules below wrap libnx, so they need either nx-sys or the host-mock
+// fakes. Without both only the modules above are built, e.g. for host tools.
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
 pub mod sm;
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
+pub mod ipc;
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
 pub mod console;
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
 pub mod hid;
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
 pub mod applet;
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
 pub mod os;
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
 pub mod usbcomms;
+#[cfg(any(feature = "nx-sys", feature = "host-mock"))]
+pub mod nxlink;
 
 mod util;
 pub use util::*;
+pub use result::ResultCode;
 
 #[cfg(feature = "twili")]
 pub mod twili;
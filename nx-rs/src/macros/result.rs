@@ -11,14 +11,14 @@ macro_rules! result_make {
 macro_rules! result_assert {
     ($rc:expr) => {{
         if $rc != 0 {
-            return Err($rc);
+            return Err($crate::ResultCode::from_raw($rc));
         }
     }};
 
     ($rc:expr, $cb:expr) => {{
         if $rc != 0 {
             $cb();
-            return Err($rc);
+            return Err($crate::ResultCode::from_raw($rc));
         }
     }};
 }
@@ -28,14 +28,14 @@ macro_rules! result_final {
     ($rc:expr) => {{
         return match $rc {
             0 => Ok(()),
-            _ => Err($rc),
+            _ => Err($crate::ResultCode::from_raw($rc)),
         };
     }};
 
     ($rc:expr, $val:expr) => {{
         return match $rc {
             0 => Ok($val),
-            _ => Err($rc),
+            _ => Err($crate::ResultCode::from_raw($rc)),
         };
     }};
 }
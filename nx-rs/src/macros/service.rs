@@ -1,29 +1,135 @@
 #![macro_use]
 
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::{Mutex, Once};
+
+/// Reference count shared by every `Handle` a `handle!` invocation hands out.
+///
+/// The service is initialized when the count leaves zero and exited when it
+/// returns to zero, with both transitions done under a lock so a concurrent
+/// `open` never sees a half-initialized service.
+pub struct RefCount {
+    init: Once,
+    count: UnsafeCell<*const Mutex<usize>>,
+}
+
+unsafe impl Sync for RefCount {}
+
+impl Default for RefCount {
+    fn default() -> Self {
+        RefCount::new()
+    }
+}
+
+impl RefCount {
+    pub const fn new() -> Self {
+        RefCount {
+            init: Once::new(),
+            count: UnsafeCell::new(ptr::null()),
+        }
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, usize> {
+        unsafe {
+            let count = self.count.get();
+            self.init.call_once(|| *count = Box::into_raw(Box::new(Mutex::new(0))));
+            match (**count).lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            }
+        }
+    }
+
+    /// Adds a reference, running `init` first if there were none.
+    pub fn acquire<E, F: FnOnce() -> Result<(), E>>(&self, init: F) -> Result<(), E> {
+        let mut count = self.lock();
+        if *count == 0 {
+            init()?;
+        }
+        *count += 1;
+        Ok(())
+    }
+
+    /// Adds the first reference, running `init`, or fails with `in_use` if
+    /// there already are some.
+    pub fn acquire_first<E, F: FnOnce() -> Result<(), E>>(&self, init: F, in_use: E) -> Result<(), E> {
+        let mut count = self.lock();
+        if *count > 0 {
+            return Err(in_use);
+        }
+        init()?;
+        *count += 1;
+        Ok(())
+    }
+
+    /// Drops a reference, running `exit` if it was the last one.
+    pub fn release<F: FnOnce()>(&self, exit: F) {
+        let mut count = self.lock();
+        *count -= 1;
+        if *count == 0 {
+            exit();
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        *self.lock()
+    }
+}
+
 #[macro_export]
 macro_rules! handle {
     (_ in $init:expr, $exit:expr, {$($impl:tt)*}) => {
+        /// A reference to the service. The service stays initialized for as
+        /// long as any clone of any handle is alive.
         #[derive(Debug)]
         pub struct Handle(());
 
-        static INITIALIZED: ::std::sync::atomic::AtomicBool =
-            ::std::sync::atomic::AtomicBool::new(false);
+        static REFS: $crate::macros::service::RefCount =
+            $crate::macros::service::RefCount::new();
+
+        impl Clone for Handle {
+            fn clone(&self) -> Self {
+                let _ = REFS.acquire(|| -> Result<(), ()> { Ok(()) });
+                Handle(())
+            }
+        }
 
         impl Drop for Handle {
             fn drop(&mut self) {
-                unsafe { $exit; };
+                REFS.release(|| unsafe { Handle::__exit() });
             }
         }
 
         impl Handle {
+            // `$init` and `$exit` are libnx calls. They go in unsafe fns
+            // rather than unsafe blocks, which clippy flags when they wrap
+            // code passed to a macro.
+            unsafe fn __init() {
+                $init;
+            }
+
+            unsafe fn __exit() {
+                $exit;
+            }
+
+            pub fn open() -> Self {
+                let _ = REFS.acquire(|| -> Result<(), ()> {
+                    unsafe { Handle::__init() };
+                    Ok(())
+                });
+                Handle(())
+            }
+
+            /// Compatibility shim for the single-handle API. Handles are
+            /// reference-counted now, so this always succeeds.
             pub fn new() -> Option<Self> {
-                if !INITIALIZED.swap(true, ::std::sync::atomic::Ordering::SeqCst) {
-                    unsafe { $init; };
+                Some(Self::open())
+            }
 
-                    Some(Handle(()))
-                } else {
-                    None
-                }
+            /// The number of handles to this service currently alive.
+            pub fn ref_count() -> usize {
+                REFS.count()
             }
 
             $($impl)*
@@ -31,33 +137,96 @@ macro_rules! handle {
     };
 
     ($ok:pat in $init:expr, $exit:expr, {$($impl:tt)*}) => {
+        /// A reference to the service. The service stays initialized for as
+        /// long as any clone of any handle is alive.
         #[derive(Debug)]
         pub struct Handle(());
 
-        static INITIALIZED: ::std::sync::atomic::AtomicBool =
-            ::std::sync::atomic::AtomicBool::new(false);
+        static REFS: $crate::macros::service::RefCount =
+            $crate::macros::service::RefCount::new();
+
+        impl Clone for Handle {
+            fn clone(&self) -> Self {
+                let _ = REFS.acquire(|| -> Result<(), ()> { Ok(()) });
+                Handle(())
+            }
+        }
 
         impl Drop for Handle {
             fn drop(&mut self) {
-                unsafe { $exit; };
+                REFS.release(|| unsafe { Handle::__exit() });
             }
         }
 
         impl Handle {
-            pub fn new() -> Option<Result<Self, u32>> {
-                if !INITIALIZED.swap(true, ::std::sync::atomic::Ordering::SeqCst) {
-                    let res = unsafe { $init };
+            // See the other arm.
+            unsafe fn __init() -> u32 {
+                $init as u32
+            }
 
-                    match res as u32 {
-                        $ok => Some(Ok(Handle(()))),
-                        err => Some(Err(err as u32)),
+            unsafe fn __exit() {
+                $exit;
+            }
+
+            pub fn open() -> Result<Self, $crate::ResultCode> {
+                REFS.acquire(|| {
+                    match unsafe { Handle::__init() } {
+                        $ok => Ok(()),
+                        err => Err($crate::ResultCode::from_raw(err)),
                     }
-                } else {
-                    None
-                }
+                })?;
+                Ok(Handle(()))
+            }
+
+            /// Compatibility shim for the single-handle API. Handles are
+            /// reference-counted now, so this never returns `None`.
+            pub fn new() -> Option<Result<Self, $crate::ResultCode>> {
+                Some(Self::open())
+            }
+
+            /// The number of handles to this service currently alive.
+            pub fn ref_count() -> usize {
+                REFS.count()
             }
 
             $($impl)*
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static INITS: AtomicUsize = AtomicUsize::new(0);
+    static EXITS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe fn init() -> u32 {
+        INITS.fetch_add(1, Ordering::SeqCst);
+        0
+    }
+
+    unsafe fn exit() {
+        EXITS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    handle!(0 in init(), exit(), {});
+
+    #[test]
+    fn exits_with_last_handle() {
+        let first = Handle::open().unwrap();
+        let second = Handle::open().unwrap();
+        let third = second.clone();
+        assert_eq!(INITS.load(Ordering::SeqCst), 1);
+        assert_eq!(Handle::ref_count(), 3);
+
+        drop(first);
+        drop(second);
+        assert_eq!(EXITS.load(Ordering::SeqCst), 0);
+        drop(third);
+        assert_eq!(EXITS.load(Ordering::SeqCst), 1);
+
+        let _again = Handle::new().unwrap().unwrap();
+        assert_eq!(INITS.load(Ordering::SeqCst), 2);
+    }
+}
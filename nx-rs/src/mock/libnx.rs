@@ -0,0 +1,526 @@
+//! The subset of the nx-sys surface this crate uses, backed by the fakes in
+//! `mock`. Names, types and signatures mirror the bindgen output, so the
+//! wrappers compile against either one.
+//!
+//! # Safety
+//!
+//! Each fake is `unsafe` because the libnx function it stands in for is, and
+//! has the same contract: pointers must be valid for what that function
+//! reads or writes through them. The fakes check no more than libnx does.
+
+#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals)]
+// The contract is the libnx one, documented once above rather than on every
+// fake.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::UnsafeCell;
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_int;
+use std::ptr;
+use std::slice;
+
+use super::{call, hid, with};
+
+pub type Result = u32;
+pub type Handle = u32;
+
+// sm
+
+pub type ServiceType = u32;
+pub const ServiceType_ServiceType_Uninitialized: ServiceType = 0;
+pub const ServiceType_ServiceType_Normal: ServiceType = 1;
+pub const ServiceType_ServiceType_Domain: ServiceType = 2;
+pub const ServiceType_ServiceType_DomainSubservice: ServiceType = 3;
+pub const ServiceType_ServiceType_Override: ServiceType = 4;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Service {
+    pub handle: Handle,
+    pub object_id: u32,
+    pub type_: ServiceType,
+}
+
+pub unsafe fn smInitialize() -> Result {
+    call("smInitialize")
+}
+
+pub unsafe fn smExit() {
+    call("smExit");
+}
+
+pub unsafe fn smGetService(service_out: *mut Service, _name: *const u8) -> Result {
+    let rc = call("smGetService");
+    if rc == 0 {
+        let handle = with(|state| {
+            state.next_handle += 1;
+            state.next_handle
+        });
+        *service_out = Service {
+            handle,
+            object_id: 0,
+            type_: ServiceType_ServiceType_Normal,
+        };
+    }
+    rc
+}
+
+pub unsafe fn serviceClose(s: *mut Service) {
+    call("serviceClose");
+    (*s).type_ = ServiceType_ServiceType_Uninitialized;
+}
+
+// ipc
+
+thread_local! {
+    static TLS: UnsafeCell<[u64; 0x40]> = UnsafeCell::new([0; 0x40]);
+}
+
+pub unsafe fn armGetTls() -> *mut c_void {
+    TLS.with(|tls| tls.get() as *mut c_void)
+}
+
+pub unsafe fn svcSendSyncRequest(session: Handle) -> Result {
+    let rc = call("svcSendSyncRequest");
+    if rc != 0 {
+        return rc;
+    }
+    let handler = with(|state| state.ipc_handler.take());
+    match handler {
+        Some(mut handler) => {
+            let tls = slice::from_raw_parts_mut(armGetTls() as *mut u8, 0x200);
+            let rc = handler(session, tls);
+            with(|state| state.ipc_handler = Some(handler));
+            rc
+        }
+        // Nothing is listening on the other end.
+        None => ::ResultCode::new(1, 123).raw(),
+    }
+}
+
+// console
+
+#[repr(C)]
+pub struct PrintConsole {
+    _private: [u8; 0],
+}
+
+pub unsafe fn consoleInit(console: *mut PrintConsole) -> *mut PrintConsole {
+    call("consoleInit");
+    with(|state| state.console_initialized = true);
+    console
+}
+
+pub unsafe fn consoleExit(_console: *mut PrintConsole) {
+    call("consoleExit");
+    with(|state| state.console_initialized = false);
+}
+
+pub unsafe fn consoleClear() {
+    call("consoleClear");
+    with(|state| state.console.clear());
+}
+
+pub unsafe fn consoleUpdate(_console: *mut PrintConsole) {
+    call("consoleUpdate");
+}
+
+// hid
+
+pub type HidControllerID = u32;
+pub const HidControllerID_CONTROLLER_PLAYER_1: HidControllerID = 0;
+pub const HidControllerID_CONTROLLER_PLAYER_2: HidControllerID = 1;
+pub const HidControllerID_CONTROLLER_PLAYER_3: HidControllerID = 2;
+pub const HidControllerID_CONTROLLER_PLAYER_4: HidControllerID = 3;
+pub const HidControllerID_CONTROLLER_PLAYER_5: HidControllerID = 4;
+pub const HidControllerID_CONTROLLER_PLAYER_6: HidControllerID = 5;
+pub const HidControllerID_CONTROLLER_PLAYER_7: HidControllerID = 6;
+pub const HidControllerID_CONTROLLER_PLAYER_8: HidControllerID = 7;
+pub const HidControllerID_CONTROLLER_HANDHELD: HidControllerID = 8;
+pub const HidControllerID_CONTROLLER_UNKNOWN: HidControllerID = 9;
+pub const HidControllerID_CONTROLLER_P1_AUTO: HidControllerID = 10;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct touchPosition {
+    pub id: u32,
+    pub px: u32,
+    pub py: u32,
+    pub dx: u32,
+    pub dy: u32,
+    pub angle: u32,
+}
+
+pub unsafe fn hidIsControllerConnected(id: HidControllerID) -> bool {
+    call("hidIsControllerConnected");
+    id != HidControllerID_CONTROLLER_UNKNOWN && with(|state| !state.disconnected.contains(&id))
+}
+
+pub unsafe fn hidScanInput() {
+    call("hidScanInput");
+    hid::scan();
+}
+
+pub unsafe fn hidKeysDown(_id: HidControllerID) -> u64 {
+    call("hidKeysDown");
+    with(|state| state.held & !state.prev_held)
+}
+
+pub unsafe fn hidKeysUp(_id: HidControllerID) -> u64 {
+    call("hidKeysUp");
+    with(|state| state.prev_held & !state.held)
+}
+
+pub unsafe fn hidKeysHeld(_id: HidControllerID) -> u64 {
+    call("hidKeysHeld");
+    with(|state| state.held)
+}
+
+pub unsafe fn hidTouchCount() -> u32 {
+    call("hidTouchCount");
+    with(|state| state.touches.len() as u32)
+}
+
+pub unsafe fn hidTouchRead(pos: *mut touchPosition, point_id: u32) {
+    call("hidTouchRead");
+    let (px, py) = with(|state| state.touches.get(point_id as usize).cloned().unwrap_or((0, 0)));
+    *pos = touchPosition { id: point_id, px, py, dx: 0, dy: 0, angle: 0 };
+}
+
+// applet
+
+pub type AppletId = u32;
+pub type LibAppletMode = u32;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct AppletHolder {
+    pub s: Service,
+    pub creatingSelf: bool,
+    pub mode: LibAppletMode,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct LibAppletArgs {
+    pub CommonArgs_version: u32,
+    pub CommonArgs_size: u32,
+    pub LaVersion: u32,
+    pub ExpectedThemeColor: i32,
+    pub PlayStartupSound: u8,
+    pub pad: [u8; 7],
+    pub tick: u64,
+}
+
+pub unsafe fn appletCreateLibraryApplet(h: *mut AppletHolder, _id: AppletId, mode: LibAppletMode) -> Result {
+    let rc = call("appletCreateLibraryApplet");
+    if rc == 0 {
+        (*h).mode = mode;
+        (*h).creatingSelf = true;
+    }
+    rc
+}
+
+pub unsafe fn libappletArgsCreate(a: *mut LibAppletArgs, version: u32) {
+    call("libappletArgsCreate");
+    *a = LibAppletArgs {
+        CommonArgs_version: 1,
+        CommonArgs_size: 0x20,
+        LaVersion: version,
+        ExpectedThemeColor: 0,
+        PlayStartupSound: 0,
+        pad: [0; 7],
+        tick: 0,
+    };
+}
+
+pub unsafe fn libappletArgsPush(_a: *mut LibAppletArgs, _h: *mut AppletHolder) -> Result {
+    call("libappletArgsPush")
+}
+
+pub unsafe fn libappletPushInData(_h: *mut AppletHolder, buffer: *const c_void, size: usize) -> Result {
+    let rc = call("libappletPushInData");
+    if rc == 0 {
+        let data = slice::from_raw_parts(buffer as *const u8, size).to_vec();
+        with(|state| state.applet_in.push(data));
+    }
+    rc
+}
+
+pub unsafe fn libappletPopOutData(_h: *mut AppletHolder, buffer: *mut c_void, size: usize, transfer_size: *mut usize) -> Result {
+    let rc = call("libappletPopOutData");
+    if rc != 0 {
+        return rc;
+    }
+    match with(|state| state.applet_out.pop_front()) {
+        Some(data) => {
+            let len = data.len().min(size);
+            ptr::copy_nonoverlapping(data[..len].as_ptr(), buffer as *mut u8, len);
+            if !transfer_size.is_null() {
+                *transfer_size = len;
+            }
+            0
+        }
+        // LibnxError_LibAppletBadExit
+        None => ::ResultCode::new(345, 46).raw(),
+    }
+}
+
+pub unsafe fn appletHolderStart(_h: *mut AppletHolder) -> Result {
+    call("appletHolderStart")
+}
+
+pub unsafe fn appletHolderWaitInteractiveOut(_h: *mut AppletHolder) -> bool {
+    call("appletHolderWaitInteractiveOut");
+    false
+}
+
+pub unsafe fn appletHolderJoin(_h: *mut AppletHolder) {
+    call("appletHolderJoin");
+}
+
+pub unsafe fn appletHolderClose(_h: *mut AppletHolder) {
+    call("appletHolderClose");
+}
+
+// swkbd
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SwkbdConfig {
+    pub preset: u32,
+    pub initialized: bool,
+}
+
+pub unsafe fn swkbdCreate(c: *mut SwkbdConfig, _max_dictwords: i32) -> Result {
+    let rc = call("swkbdCreate");
+    if rc == 0 {
+        (*c).initialized = true;
+    }
+    rc
+}
+
+pub unsafe fn swkbdClose(c: *mut SwkbdConfig) {
+    call("swkbdClose");
+    (*c).initialized = false;
+}
+
+pub unsafe fn swkbdConfigMakePresetDefault(c: *mut SwkbdConfig) {
+    call("swkbdConfigMakePresetDefault");
+    (*c).preset = 0;
+}
+
+pub unsafe fn swkbdConfigMakePresetPassword(c: *mut SwkbdConfig) {
+    call("swkbdConfigMakePresetPassword");
+    (*c).preset = 1;
+}
+
+pub unsafe fn swkbdConfigMakePresetUserName(c: *mut SwkbdConfig) {
+    call("swkbdConfigMakePresetUserName");
+    (*c).preset = 2;
+}
+
+pub unsafe fn swkbdConfigMakePresetDownloadCode(c: *mut SwkbdConfig) {
+    call("swkbdConfigMakePresetDownloadCode");
+    (*c).preset = 3;
+}
+
+pub unsafe fn swkbdConfigSetOkButtonText(_c: *mut SwkbdConfig, _str: *const u8) {
+    call("swkbdConfigSetOkButtonText");
+}
+
+pub unsafe fn swkbdShow(_c: *mut SwkbdConfig, out_string: *mut u8, out_string_size: usize) -> Result {
+    let rc = call("swkbdShow");
+    if rc == 0 && out_string_size > 0 {
+        let text = with(|state| state.swkbd_text.clone());
+        let len = text.len().min(out_string_size - 1);
+        ptr::copy_nonoverlapping(text.as_ptr(), out_string, len);
+        ptr::write_bytes(out_string.add(len), 0, out_string_size - len);
+    }
+    rc
+}
+
+// usbcomms
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct UsbCommsInterfaceInfo {
+    pub bInterfaceClass: u8,
+    pub bInterfaceSubClass: u8,
+    pub bInterfaceProtocol: u8,
+}
+
+pub unsafe fn usbCommsInitialize() -> Result {
+    call("usbCommsInitialize")
+}
+
+pub unsafe fn usbCommsInitializeEx(_num_interfaces: u32, _infos: *const UsbCommsInterfaceInfo) -> Result {
+    call("usbCommsInitializeEx")
+}
+
+pub unsafe fn usbCommsExit() {
+    call("usbCommsExit");
+}
+
+pub unsafe fn usbCommsRead(buffer: *mut c_void, size: usize) -> usize {
+    usbCommsReadEx(buffer, size, 0)
+}
+
+pub unsafe fn usbCommsReadEx(buffer: *mut c_void, size: usize, _interface: u32) -> usize {
+    call("usbCommsRead");
+    let out = slice::from_raw_parts_mut(buffer as *mut u8, size);
+    with(|state| {
+        let len = state.usb_in.len().min(size);
+        for (dst, src) in out.iter_mut().zip(state.usb_in.drain(..len)) {
+            *dst = src;
+        }
+        len
+    })
+}
+
+pub unsafe fn usbCommsWrite(buffer: *const c_void, size: usize) -> usize {
+    usbCommsWriteEx(buffer, size, 0)
+}
+
+pub unsafe fn usbCommsWriteEx(buffer: *const c_void, size: usize, _interface: u32) -> usize {
+    call("usbCommsWrite");
+    let data = slice::from_raw_parts(buffer as *const u8, size);
+    with(|state| state.usb_out.extend_from_slice(data));
+    size
+}
+
+// env / setsys
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SetSysFirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub micro: u8,
+    pub padding1: u8,
+    pub revision_major: u8,
+    pub revision_minor: u8,
+    pub padding2: u8,
+    pub padding3: u8,
+    pub platform: [u8; 0x20],
+    pub version_hash: [u8; 0x40],
+    pub display_version: [u8; 0x18],
+    pub display_title: [u8; 0x80],
+}
+
+pub unsafe fn envIsNso() -> bool {
+    call("envIsNso");
+    with(|state| state.nso)
+}
+
+pub unsafe fn envHasNextLoad() -> bool {
+    call("envHasNextLoad");
+    with(|state| state.has_next_load)
+}
+
+pub unsafe fn envSetNextLoad(path: *const u8, argv: *const u8) -> Result {
+    let rc = call("envSetNextLoad");
+    if rc == 0 {
+        let path = CStr::from_ptr(path as *const _).to_string_lossy().into_owned();
+        let argv = CStr::from_ptr(argv as *const _).to_string_lossy().into_owned();
+        with(|state| state.next_load = Some((path, argv)));
+    }
+    rc
+}
+
+pub unsafe fn setsysInitialize() -> Result {
+    call("setsysInitialize")
+}
+
+pub unsafe fn setsysExit() {
+    call("setsysExit");
+}
+
+pub unsafe fn setsysGetFirmwareVersion(out: *mut SetSysFirmwareVersion) -> Result {
+    let rc = call("setsysGetFirmwareVersion");
+    if rc == 0 {
+        let (version, title) = with(|state| (state.display_version.clone(), state.display_title.clone()));
+        let out = &mut *out;
+        let len = version.len().min(out.display_version.len() - 1);
+        out.display_version[..len].copy_from_slice(&version.as_bytes()[..len]);
+        let len = title.len().min(out.display_title.len() - 1);
+        out.display_title[..len].copy_from_slice(&title.as_bytes()[..len]);
+    }
+    rc
+}
+
+// socket / nxlink
+
+pub unsafe fn socketInitializeDefault() -> Result {
+    let rc = call("socketInitializeDefault");
+    if rc != 0 {
+        return rc;
+    }
+    with(|state| {
+        if state.sockets_initialized {
+            ::result::names::RESULT_ALREADY_INITIALIZED.raw()
+        } else {
+            state.sockets_initialized = true;
+            0
+        }
+    })
+}
+
+pub unsafe fn socketExit() {
+    call("socketExit");
+    with(|state| state.sockets_initialized = false);
+}
+
+// Connects to the host and points stdout and stderr at the new socket.
+pub unsafe fn nxlinkStdio() -> c_int {
+    call("nxlinkStdio");
+    with(|state| {
+        if !state.sockets_initialized || state.nxlink_host == [0; 4] || state.nxlink_refuse {
+            return -1;
+        }
+        let sock = state.open_fd("nxlink");
+        state.fds[1] = Some("nxlink");
+        state.fds[2] = Some("nxlink");
+        sock
+    })
+}
+
+pub unsafe fn dup(fd: c_int) -> c_int {
+    call("dup");
+    with(|state| match state.fds.get(fd as usize).cloned() {
+        Some(Some(target)) => state.open_fd(target),
+        _ => -1,
+    })
+}
+
+pub unsafe fn dup2(fd: c_int, fd2: c_int) -> c_int {
+    call("dup2");
+    with(|state| match state.fds.get(fd as usize).cloned() {
+        Some(Some(target)) if (fd2 as usize) < state.fds.len() => {
+            state.fds[fd2 as usize] = Some(target);
+            fd2
+        }
+        _ => -1,
+    })
+}
+
+pub unsafe fn close(fd: c_int) -> c_int {
+    call("close");
+    with(|state| match state.fds.get_mut(fd as usize) {
+        Some(slot) if slot.is_some() => {
+            *slot = None;
+            0
+        }
+        _ => -1,
+    })
+}
+
+// twili
+
+pub unsafe fn twiliInitialize() -> Result {
+    call("twiliInitialize")
+}
+
+pub unsafe fn twiliExit() {
+    call("twiliExit");
+}
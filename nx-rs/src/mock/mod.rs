@@ -0,0 +1,401 @@
+//! In-process fakes standing in for libnx under the `host-mock` feature.
+//!
+//! With `host-mock` enabled, `::libnx` resolves to `mock::libnx` instead of
+//! nx-sys, so the wrappers in this crate run unmodified on the host. The
+//! functions here script those fakes.
+//!
+//! The fakes are shared by the whole process, as libnx's state is, and so are
+//! the reference counts of the service handles wrapping them. Tests using them
+//! therefore run one at a time: start each with `let _mock = mock::reset();`,
+//! which waits for any other test holding the fakes, puts them back in their
+//! initial state and keeps them for this test until the guard is dropped.
+//!
+//! Only the libnx calls are faked. Output from `println!` and other writes to
+//! stdout still goes to the test's own stdout rather than `console::output`,
+//! as std has no stable way to redirect it, so code meant to be tested under
+//! the mock should print through `nx::console::print`.
+
+use std::collections::VecDeque;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, Once};
+
+use ResultCode;
+
+pub mod libnx;
+
+/// What answers `svcSendSyncRequest`, see `ipc::set_handler`.
+type IpcHandler = Box<dyn FnMut(u32, &mut [u8]) -> u32 + Send>;
+
+struct State {
+    calls: Vec<&'static str>,
+    failures: Vec<(&'static str, u32)>,
+    console: String,
+    console_initialized: bool,
+    frames: VecDeque<u64>,
+    held: u64,
+    prev_held: u64,
+    disconnected: Vec<u32>,
+    touches: Vec<(u32, u32)>,
+    swkbd_text: String,
+    applet_in: Vec<Vec<u8>>,
+    applet_out: VecDeque<Vec<u8>>,
+    usb_in: VecDeque<u8>,
+    usb_out: Vec<u8>,
+    display_version: String,
+    display_title: String,
+    nso: bool,
+    has_next_load: bool,
+    next_load: Option<(String, String)>,
+    ipc_handler: Option<IpcHandler>,
+    next_handle: u32,
+    nxlink_host: [u8; 4],
+    nxlink_refuse: bool,
+    sockets_initialized: bool,
+    /// What each file descriptor refers to, `None` for closed ones.
+    fds: Vec<Option<&'static str>>,
+}
+
+impl State {
+    fn new() -> Self {
+        State {
+            calls: Vec::new(),
+            failures: Vec::new(),
+            console: String::new(),
+            console_initialized: false,
+            frames: VecDeque::new(),
+            held: 0,
+            prev_held: 0,
+            disconnected: Vec::new(),
+            touches: Vec::new(),
+            swkbd_text: String::new(),
+            applet_in: Vec::new(),
+            applet_out: VecDeque::new(),
+            usb_in: VecDeque::new(),
+            usb_out: Vec::new(),
+            display_version: String::from("0.0.0"),
+            display_title: String::from("NintendoSDK Firmware for NX 0.0.0"),
+            nso: false,
+            has_next_load: true,
+            next_load: None,
+            ipc_handler: None,
+            next_handle: 0x100,
+            nxlink_host: [0; 4],
+            nxlink_refuse: false,
+            sockets_initialized: false,
+            fds: vec![Some("stdin"), Some("stdout"), Some("stderr")],
+        }
+    }
+
+    /// Opens the lowest free descriptor, referring to `target`.
+    fn open_fd(&mut self, target: &'static str) -> i32 {
+        match self.fds.iter().position(|fd| fd.is_none()) {
+            Some(fd) => {
+                self.fds[fd] = Some(target);
+                fd as i32
+            }
+            None => {
+                self.fds.push(Some(target));
+                self.fds.len() as i32 - 1
+            }
+        }
+    }
+}
+
+struct Shared {
+    /// Held by the test currently using the fakes, see `reset`.
+    test: Mutex<()>,
+    state: Mutex<State>,
+}
+
+static INIT: Once = Once::new();
+static mut SHARED: *const Shared = ptr::null();
+
+fn shared() -> &'static Shared {
+    unsafe {
+        INIT.call_once(|| SHARED = Box::into_raw(Box::new(Shared {
+            test: Mutex::new(()),
+            state: Mutex::new(State::new()),
+        })));
+        &*SHARED
+    }
+}
+
+/// Locks `mutex`, carrying on if a test panicked while holding it; `reset`
+/// clears whatever it left behind.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+fn with<T, F: FnOnce(&mut State) -> T>(f: F) -> T {
+    f(&mut lock(&shared().state))
+}
+
+/// Records a call to `name`, returning the failure scripted for it, if any.
+fn call(name: &'static str) -> u32 {
+    with(|state| {
+        state.calls.push(name);
+        match state.failures.iter().position(|&(call, _)| call == name) {
+            Some(idx) => state.failures.remove(idx).1,
+            None => 0,
+        }
+    })
+}
+
+/// Keeps the fakes for one test, see `reset`.
+pub struct Guard {
+    _test: MutexGuard<'static, ()>,
+}
+
+/// Waits until no other test is using the fakes, then puts every one of them
+/// back in its initial state. Other tests' calls to `reset` wait until the
+/// returned guard is dropped, so keep it alive for the whole test.
+#[must_use = "other tests can change the fakes as soon as the guard is dropped"]
+pub fn reset() -> Guard {
+    let guard = Guard { _test: lock(&shared().test) };
+    with(|state| *state = State::new());
+    guard
+}
+
+/// The libnx functions called so far, in order.
+pub fn calls() -> Vec<&'static str> {
+    with(|state| state.calls.clone())
+}
+
+/// Makes the next call to the libnx function `name` fail with `rc`.
+///
+/// Failures queue up, so scripting the same function twice fails its next
+/// two calls.
+pub fn fail_next(name: &'static str, rc: ResultCode) {
+    with(|state| state.failures.push((name, rc.raw())));
+}
+
+pub mod hid {
+    use std::mem;
+
+    use hid::{controller_to_ctrlid, Controller};
+    use super::with;
+
+    /// Queues the keys held during one future `hidScanInput`.
+    ///
+    /// Frames are consumed one per scan; once the queue is empty the last
+    /// frame stays held.
+    pub fn push_frame(keys: u64) {
+        with(|state| state.frames.push_back(keys));
+    }
+
+    /// Holds `keys` for one scan and releases them on the next.
+    pub fn press(keys: u64) {
+        push_frame(keys);
+        push_frame(0);
+    }
+
+    pub fn set_connected(ctrl: Controller, connected: bool) {
+        let id = controller_to_ctrlid(ctrl);
+        with(|state| {
+            state.disconnected.retain(|&other| other != id);
+            if !connected {
+                state.disconnected.push(id);
+            }
+        });
+    }
+
+    pub fn set_touches(touches: &[(u32, u32)]) {
+        with(|state| state.touches = touches.to_vec());
+    }
+
+    pub(super) fn scan() {
+        with(|state| {
+            state.prev_held = mem::replace(&mut state.held, 0);
+            state.held = state.frames.pop_front().unwrap_or(state.prev_held);
+        });
+    }
+}
+
+/// What the app printed through `nx::console::print`, which doesn't include
+/// `println!`, see above.
+pub mod console {
+    use super::with;
+
+    /// Everything printed to the console since it was last cleared.
+    pub fn output() -> String {
+        with(|state| state.console.clone())
+    }
+
+    /// Like `output`, but also clears the captured text.
+    pub fn take_output() -> String {
+        with(|state| ::std::mem::replace(&mut state.console, String::new()))
+    }
+
+    pub fn is_initialized() -> bool {
+        with(|state| state.console_initialized)
+    }
+
+    pub(crate) fn write(text: &str) {
+        with(|state| state.console.push_str(text));
+    }
+}
+
+pub mod applet {
+    use super::with;
+
+    /// Sets the text the next software keyboard returns.
+    pub fn set_swkbd_text(text: &str) {
+        with(|state| state.swkbd_text = String::from(text));
+    }
+
+    /// Queues a storage the library applet hands back through `pop_data`.
+    pub fn push_out_data(data: &[u8]) {
+        with(|state| state.applet_out.push_back(data.to_vec()));
+    }
+
+    /// The storages pushed to library applets so far.
+    pub fn take_in_data() -> Vec<Vec<u8>> {
+        with(|state| ::std::mem::replace(&mut state.applet_in, Vec::new()))
+    }
+}
+
+pub mod usb {
+    use super::with;
+
+    /// Queues bytes for the host side to "send", returned by `read`.
+    pub fn push_input(data: &[u8]) {
+        with(|state| state.usb_in.extend(data.iter().cloned()));
+    }
+
+    /// The bytes written so far.
+    pub fn take_output() -> Vec<u8> {
+        with(|state| ::std::mem::replace(&mut state.usb_out, Vec::new()))
+    }
+}
+
+pub mod os {
+    use super::with;
+
+    pub fn set_firmware_version(display_version: &str, display_title: &str) {
+        with(|state| {
+            state.display_version = String::from(display_version);
+            state.display_title = String::from(display_title);
+        });
+    }
+
+    pub fn set_nso(nso: bool) {
+        with(|state| state.nso = nso);
+    }
+
+    /// Whether the loader looks like it can start another NRO on exit.
+    pub fn set_has_next_load(supported: bool) {
+        with(|state| state.has_next_load = supported);
+    }
+
+    /// The path and argv string passed to `envSetNextLoad`, if it was called.
+    pub fn next_load() -> Option<(String, String)> {
+        with(|state| state.next_load.clone())
+    }
+}
+
+pub mod nxlink {
+    use std::net::Ipv4Addr;
+
+    use super::with;
+
+    /// Makes the app look launched by `nxlink -s` from `host`, or not
+    /// launched by nxlink at all.
+    pub fn set_host(host: Option<Ipv4Addr>) {
+        with(|state| state.nxlink_host = host.map_or([0; 4], |host| host.octets()));
+    }
+
+    /// Makes `nxlinkStdio` fail, as if the host wasn't listening.
+    pub fn set_refuse(refuse: bool) {
+        with(|state| state.nxlink_refuse = refuse);
+    }
+
+    /// What file descriptor `fd` refers to: "stdout", "stderr" or "nxlink"
+    /// for the host socket.
+    pub fn fd_target(fd: i32) -> Option<&'static str> {
+        with(|state| state.fds.get(fd as usize).cloned().and_then(|target| target))
+    }
+
+    /// How many file descriptors are open, the standard three included.
+    pub fn open_fds() -> usize {
+        with(|state| state.fds.iter().filter(|fd| fd.is_some()).count())
+    }
+
+    pub fn sockets_initialized() -> bool {
+        with(|state| state.sockets_initialized)
+    }
+
+    pub(crate) fn host_addr() -> [u8; 4] {
+        with(|state| state.nxlink_host)
+    }
+}
+
+pub mod ipc {
+    use super::with;
+
+    /// Answers `svcSendSyncRequest` with `handler`, which gets the session
+    /// handle and the TLS message buffer to rewrite into a reply. Its return
+    /// value is the result of the syscall itself.
+    pub fn set_handler<F: FnMut(u32, &mut [u8]) -> u32 + Send + 'static>(handler: F) {
+        with(|state| state.ipc_handler = Some(Box::new(handler)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hid::{input_down, input_held, input_up, Controller, Key};
+
+    #[test]
+    fn scripts_button_presses() {
+        let _mock = reset();
+        hid::press(Key::A as u64 | Key::B as u64);
+        assert_eq!(input_down(Controller::Auto), Key::A as u64 | Key::B as u64);
+        assert_eq!(input_up(Controller::Auto), Key::A as u64 | Key::B as u64);
+        assert_eq!(input_held(Controller::Auto), 0);
+    }
+
+    #[test]
+    fn scripts_service_failures() {
+        let _mock = reset();
+        fail_next("smInitialize", ResultCode::new(21, 1));
+        assert_eq!(::sm::Handle::open().unwrap_err(), ResultCode::new(21, 1));
+        assert_eq!(::sm::Handle::ref_count(), 0);
+
+        let sm = ::sm::Handle::open().unwrap();
+        assert!(sm.get_service("fsp-srv\0").is_ok());
+        assert_eq!(calls(), vec!["smInitialize", "smInitialize", "smGetService"]);
+    }
+
+    #[test]
+    fn shares_usbcomms_between_handles() {
+        let _mock = reset();
+        let first = ::usbcomms::Handle::open().unwrap();
+        let second = ::usbcomms::Handle::new().unwrap().unwrap();
+        assert_eq!(::usbcomms::Handle::open_ex(&[]).unwrap_err(), ::result::names::RESULT_ALREADY_INITIALIZED);
+        assert_eq!(::usbcomms::Handle::ref_count(), 2);
+
+        drop(first);
+        assert!(!calls().contains(&"usbCommsExit"));
+        drop(second);
+        assert_eq!(calls(), vec!["usbCommsInitialize", "usbCommsExit"]);
+
+        drop(::usbcomms::Handle::open_ex(&[]).unwrap());
+        assert_eq!(&calls()[2..], ["usbCommsInitializeEx", "usbCommsExit"]);
+    }
+
+    #[test]
+    fn captures_console_output() {
+        let _mock = reset();
+        ::console::initialize();
+        ::console::print("hello ");
+        ::console::print("world");
+        assert!(console::is_initialized());
+        assert_eq!(console::take_output(), "hello world");
+        ::console::print("gone");
+        ::console::clear();
+        assert_eq!(console::output(), "");
+    }
+}
@@ -0,0 +1,234 @@
+//! Sending stdout and stderr back to the PC an app was launched from with
+//! `nxlink -s`, which waits for the app's output on `CLIENT_PORT`.
+//!
+//! ```no_run
+//! let _redirect = nx::nxlink::Redirect::stdio();
+//! println!("this shows up in the terminal nxlink runs in");
+//! ```
+
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::os::raw::c_int;
+
+use result::names::RESULT_ALREADY_INITIALIZED;
+use ResultCode;
+
+/// The port nxlink's stdio server listens on.
+pub const CLIENT_PORT: u16 = 28771;
+
+const STDOUT_FILENO: c_int = 1;
+const STDERR_FILENO: c_int = 2;
+
+#[cfg(not(feature = "host-mock"))]
+mod newlib {
+    use std::os::raw::c_int;
+
+    extern "C" {
+        pub fn dup(fd: c_int) -> c_int;
+        pub fn dup2(fd: c_int, fd2: c_int) -> c_int;
+        pub fn close(fd: c_int) -> c_int;
+    }
+}
+
+#[cfg(feature = "host-mock")]
+use mock::libnx as newlib;
+
+/// `__nxlink_host`, which libnx sets from the arguments nxlink adds.
+#[cfg(not(feature = "host-mock"))]
+fn host_addr() -> [u8; 4] {
+    // The struct in_addr is opaque to bindgen; it holds the address in
+    // network order.
+    unsafe { *(&::libnx::__nxlink_host as *const _ as *const [u8; 4]) }
+}
+
+#[cfg(feature = "host-mock")]
+fn host_addr() -> [u8; 4] {
+    ::mock::nxlink::host_addr()
+}
+
+/// The address of the PC running nxlink's stdio server, if the app was
+/// launched by `nxlink -s`.
+pub fn host() -> Option<Ipv4Addr> {
+    match host_addr() {
+        [0, 0, 0, 0] => None,
+        [a, b, c, d] => Some(Ipv4Addr::new(a, b, c, d)),
+    }
+}
+
+/// Whether the app was launched by nxlink with a stdio server waiting.
+pub fn is_nxlink() -> bool {
+    host().is_some()
+}
+
+/// Redirects stdout and/or stderr to the nxlink host while it is alive, and
+/// restores them when dropped.
+///
+/// Sockets are initialized for the redirect if nothing else has done so yet,
+/// and exited again when it is dropped.
+#[derive(Debug)]
+pub struct Redirect {
+    socket: c_int,
+    /// Each redirected descriptor and the copy of it to restore.
+    saved: Vec<(c_int, c_int)>,
+    owns_sockets: bool,
+}
+
+impl Redirect {
+    /// Redirects both stdout and stderr.
+    pub fn stdio() -> io::Result<Redirect> {
+        Redirect::new(true, true)
+    }
+
+    pub fn stdout() -> io::Result<Redirect> {
+        Redirect::new(true, false)
+    }
+
+    pub fn stderr() -> io::Result<Redirect> {
+        Redirect::new(false, true)
+    }
+
+    pub fn new(stdout: bool, stderr: bool) -> io::Result<Redirect> {
+        if !is_nxlink() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "the app wasn't launched by nxlink with a stdio server"));
+        }
+
+        let rc = unsafe { ::libnx::socketInitializeDefault() };
+        let owns_sockets = match ResultCode::from_raw(rc) {
+            rc if rc.is_success() => true,
+            // The app set up sockets itself, so they're left to it.
+            rc if rc == RESULT_ALREADY_INITIALIZED => false,
+            rc => return Err(rc.into()),
+        };
+        let mut redirect = Redirect { socket: -1, saved: Vec::new(), owns_sockets };
+
+        // nxlinkStdio always takes over both descriptors, so copies of both
+        // are kept, and the one not asked for is put back right away.
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for &fd in &[STDOUT_FILENO, STDERR_FILENO] {
+            let copy = unsafe { newlib::dup(fd) };
+            if copy < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            redirect.saved.push((fd, copy));
+        }
+        redirect.socket = unsafe { ::libnx::nxlinkStdio() };
+        if redirect.socket < 0 {
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, "couldn't connect to the nxlink host"));
+        }
+        for &(fd, wanted) in &[(STDOUT_FILENO, stdout), (STDERR_FILENO, stderr)] {
+            if !wanted {
+                redirect.restore(fd);
+            }
+        }
+        Ok(redirect)
+    }
+
+    /// Points `fd` back at what it was before the redirect.
+    fn restore(&mut self, fd: c_int) {
+        if let Some(index) = self.saved.iter().position(|&(saved, _)| saved == fd) {
+            let (fd, copy) = self.saved.remove(index);
+            unsafe {
+                newlib::dup2(copy, fd);
+                newlib::close(copy);
+            }
+        }
+    }
+
+    pub fn redirects_stdout(&self) -> bool {
+        self.socket >= 0 && self.saved.iter().any(|&(fd, _)| fd == STDOUT_FILENO)
+    }
+
+    pub fn redirects_stderr(&self) -> bool {
+        self.socket >= 0 && self.saved.iter().any(|&(fd, _)| fd == STDERR_FILENO)
+    }
+}
+
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        // Whatever is still buffered belongs to the host.
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        self.restore(STDOUT_FILENO);
+        self.restore(STDERR_FILENO);
+        unsafe {
+            if self.socket >= 0 {
+                newlib::close(self.socket);
+            }
+            if self.owns_sockets {
+                ::libnx::socketExit();
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "host-mock"))]
+mod tests {
+    use super::*;
+    use mock::{self, nxlink::{fd_target, open_fds, set_host, set_refuse, sockets_initialized}};
+    use result::names::MODULE_LIBNX;
+
+    #[test]
+    fn reports_host() {
+        let _mock = mock::reset();
+        assert_eq!(host(), None);
+        assert_eq!(Redirect::stdio().unwrap_err().kind(), io::ErrorKind::NotFound);
+        set_host(Some(Ipv4Addr::new(192, 168, 1, 20)));
+        assert!(is_nxlink());
+        assert_eq!(host(), Some(Ipv4Addr::new(192, 168, 1, 20)));
+    }
+
+    #[test]
+    fn redirects_and_restores() {
+        let _mock = mock::reset();
+        set_host(Some(Ipv4Addr::new(10, 0, 0, 2)));
+        {
+            let redirect = Redirect::stdio().unwrap();
+            assert!(redirect.redirects_stdout() && redirect.redirects_stderr());
+            assert_eq!(fd_target(STDOUT_FILENO), Some("nxlink"));
+            assert_eq!(fd_target(STDERR_FILENO), Some("nxlink"));
+            assert!(sockets_initialized());
+        }
+        assert_eq!(fd_target(STDOUT_FILENO), Some("stdout"));
+        assert_eq!(fd_target(STDERR_FILENO), Some("stderr"));
+        assert_eq!(open_fds(), 3);
+        assert!(!sockets_initialized());
+
+        let redirect = Redirect::stderr().unwrap();
+        assert!(!redirect.redirects_stdout() && redirect.redirects_stderr());
+        assert_eq!(fd_target(STDOUT_FILENO), Some("stdout"));
+        assert_eq!(fd_target(STDERR_FILENO), Some("nxlink"));
+        drop(redirect);
+        assert_eq!(fd_target(STDERR_FILENO), Some("stderr"));
+        assert_eq!(open_fds(), 3);
+    }
+
+    #[test]
+    fn leaves_sockets_it_didnt_initialize() {
+        let _mock = mock::reset();
+        set_host(Some(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(unsafe { ::libnx::socketInitializeDefault() }, 0);
+        drop(Redirect::stdout().unwrap());
+        assert!(sockets_initialized());
+        assert!(!mock::calls().contains(&"socketExit"));
+    }
+
+    #[test]
+    fn cleans_up_after_failing() {
+        let _mock = mock::reset();
+        set_host(Some(Ipv4Addr::new(10, 0, 0, 2)));
+        set_refuse(true);
+        assert_eq!(Redirect::stdio().unwrap_err().kind(), io::ErrorKind::ConnectionRefused);
+        assert_eq!(fd_target(STDOUT_FILENO), Some("stdout"));
+        assert_eq!(open_fds(), 3);
+        assert!(!sockets_initialized());
+    }
+
+    #[test]
+    fn reports_socket_failures() {
+        let _mock = mock::reset();
+        set_host(Some(Ipv4Addr::new(10, 0, 0, 2)));
+        mock::fail_next("socketInitializeDefault", ResultCode::new(MODULE_LIBNX, 1));
+        assert_eq!(ResultCode::from_io_error(&Redirect::stdio().unwrap_err()), Some(ResultCode::new(MODULE_LIBNX, 1)));
+    }
+}
@@ -1,6 +1,17 @@
 
 
-pub type Result<T> = std::result::Result<T, u32>;
+use std::ffi::CString;
+
+use result::names::MODULE_LIBNX;
+pub use result::ResultCode;
+
+pub type Result<T> = std::result::Result<T, ResultCode>;
+
+/// There is no loader to start another NRO, as for an NSP. libnx's
+/// `LibnxError_NotFound`, which `envSetNextLoad` fails with in that case too.
+pub const RESULT_NO_NEXT_LOAD: ResultCode = ResultCode::new(MODULE_LIBNX, 9);
+/// The path or argv string contains a NUL. libnx's `LibnxError_BadInput`.
+pub const RESULT_BAD_INPUT: ResultCode = ResultCode::new(MODULE_LIBNX, 11);
 
 pub fn get_current_thread_handle() -> u32 {
     0xffff_8000
@@ -18,10 +29,42 @@ pub fn is_nro() -> bool {
     !is_nso()
 }
 
-pub fn env_exec_nro(path: &str, args: &str) {
-    unsafe {
-        ::libnx::envSetNextLoad(path.as_ptr(), args.as_ptr());
+/// Whether the loader can start another NRO once this one exits. The
+/// homebrew menu can, but an NSP has no loader to ask.
+pub fn has_next_load() -> bool {
+    unsafe { ::libnx::envHasNextLoad() }
+}
+
+/// Has the loader start the NRO at `path` once this app exits. `argv` is
+/// passed to it as is, so it should start with `path` and quote arguments
+/// with spaces in them, as in `sdmc:/switch/app.nro "two words"`.
+pub fn set_next_load(path: &str, argv: &str) -> Result<()> {
+    if !has_next_load() {
+        return Err(RESULT_NO_NEXT_LOAD);
     }
+    let path = CString::new(path).map_err(|_| RESULT_BAD_INPUT)?;
+    let argv = CString::new(argv).map_err(|_| RESULT_BAD_INPUT)?;
+    let rc = unsafe { ::libnx::envSetNextLoad(path.as_ptr() as *const _, argv.as_ptr() as *const _) };
+    match ResultCode::from_raw(rc) {
+        rc if rc.is_success() => Ok(()),
+        rc => Err(rc),
+    }
+}
+
+/// Sets the next NRO to load, see `set_next_load`, and exits so the loader
+/// starts it. Only returns if it couldn't be set, with the reason.
+#[must_use]
+pub fn exec_nro(path: &str, argv: &str) -> ResultCode {
+    match set_next_load(path, argv) {
+        Ok(()) => std::process::exit(0),
+        Err(rc) => rc,
+    }
+}
+
+/// Like `exec_nro`, but exits even if the next NRO couldn't be set, in which
+/// case the loader just comes back.
+pub fn env_exec_nro(path: &str, argv: &str) {
+    let _ = set_next_load(path, argv);
     std::process::exit(0);
 }
 
@@ -63,4 +106,37 @@ pub fn get_version() -> Result<Version> {
         rc = ::libnx::setsysGetFirmwareVersion(&mut ver.ver);
         result_final!(rc, ver)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(all(test, feature = "host-mock"))]
+mod tests {
+    use super::*;
+    use mock;
+
+    #[test]
+    fn sets_next_load() {
+        let _mock = mock::reset();
+        assert!(has_next_load());
+        set_next_load("sdmc:/switch/app.nro", "sdmc:/switch/app.nro \"two words\"").unwrap();
+        assert_eq!(mock::os::next_load(), Some(("sdmc:/switch/app.nro".to_owned(),
+                                                "sdmc:/switch/app.nro \"two words\"".to_owned())));
+    }
+
+    #[test]
+    fn reports_missing_loader() {
+        let _mock = mock::reset();
+        mock::os::set_has_next_load(false);
+        assert!(!has_next_load());
+        assert_eq!(set_next_load("sdmc:/switch/app.nro", ""), Err(RESULT_NO_NEXT_LOAD));
+        assert!(!mock::calls().contains(&"envSetNextLoad"));
+    }
+
+    #[test]
+    fn reports_why_next_load_failed() {
+        let _mock = mock::reset();
+        assert_eq!(exec_nro("app\0.nro", ""), RESULT_BAD_INPUT);
+        mock::fail_next("envSetNextLoad", ResultCode::new(346, 1));
+        assert_eq!(exec_nro("sdmc:/switch/app.nro", ""), ResultCode::new(346, 1));
+        assert_eq!(mock::os::next_load(), None);
+    }
+}
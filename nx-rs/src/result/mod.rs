@@ -0,0 +1,221 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+pub mod names;
+
+/// A Horizon result code, as returned by libnx and by the services behind it.
+///
+/// The raw value packs a module in the low 9 bits and a description in the
+/// following 13 bits, the same layout `result_make!` produces. A raw value of
+/// zero means success.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResultCode(u32);
+
+impl ResultCode {
+    pub const SUCCESS: ResultCode = ResultCode(0);
+
+    pub const fn new(module: u32, description: u32) -> Self {
+        ResultCode((module & 0x1ff) | (description & 0x1fff) << 9)
+    }
+
+    pub const fn from_raw(raw: u32) -> Self {
+        ResultCode(raw)
+    }
+
+    pub fn raw(self) -> u32 {
+        self.0
+    }
+
+    pub fn module(self) -> u32 {
+        self.0 & 0x1ff
+    }
+
+    pub fn description(self) -> u32 {
+        (self.0 >> 9) & 0x1fff
+    }
+
+    pub fn is_success(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_failure(self) -> bool {
+        !self.is_success()
+    }
+
+    pub fn module_name(self) -> Option<&'static str> {
+        names::module_name(self.module())
+    }
+
+    pub fn description_name(self) -> Option<&'static str> {
+        names::description_name(self.module(), self.description())
+    }
+
+    /// The `io::ErrorKind` this result corresponds to, `Other` if there is none.
+    pub fn kind(self) -> io::ErrorKind {
+        use self::names::*;
+
+        match (self.module(), self.description()) {
+            (MODULE_KERNEL, 101) | (MODULE_KERNEL, 102) |
+            (MODULE_KERNEL, 114) | (MODULE_KERNEL, 120) => io::ErrorKind::InvalidInput,
+            (MODULE_KERNEL, 117) => io::ErrorKind::TimedOut,
+            (MODULE_KERNEL, 118) => io::ErrorKind::Interrupted,
+            (MODULE_KERNEL, 121) => io::ErrorKind::NotFound,
+            (MODULE_KERNEL, 123) => io::ErrorKind::ConnectionAborted,
+            (MODULE_KERNEL, 131) => io::ErrorKind::ConnectionRefused,
+
+            (MODULE_FS, 1) | (MODULE_FS, 1001) | (MODULE_FS, 1002) => io::ErrorKind::NotFound,
+            (MODULE_FS, 2) | (MODULE_FS, 60) => io::ErrorKind::AlreadyExists,
+            (MODULE_FS, 6001..=6199) => io::ErrorKind::InvalidInput,
+            (MODULE_FS, 6400..=6449) => io::ErrorKind::PermissionDenied,
+
+            (MODULE_HIPC, 301) => io::ErrorKind::ConnectionAborted,
+
+            (MODULE_SM, 4) => io::ErrorKind::AlreadyExists,
+            (MODULE_SM, 6) => io::ErrorKind::InvalidInput,
+            (MODULE_SM, 7) => io::ErrorKind::NotFound,
+            (MODULE_SM, 8) => io::ErrorKind::PermissionDenied,
+
+            (MODULE_LIBNX, 7) => io::ErrorKind::AlreadyExists,
+            (MODULE_LIBNX, 9) => io::ErrorKind::NotFound,
+            (MODULE_LIBNX, 11) => io::ErrorKind::InvalidInput,
+            (MODULE_LIBNX, 28) | (MODULE_LIBNX, 29) => io::ErrorKind::BrokenPipe,
+
+            (MODULE_LIBNX_NVIDIA, 5) => io::ErrorKind::InvalidInput,
+            (MODULE_LIBNX_NVIDIA, 6) => io::ErrorKind::TimedOut,
+
+            (MODULE_LIBNX_BINDER, 4) => io::ErrorKind::InvalidInput,
+            (MODULE_LIBNX_BINDER, 6) => io::ErrorKind::NotFound,
+            (MODULE_LIBNX_BINDER, 7) => io::ErrorKind::PermissionDenied,
+            (MODULE_LIBNX_BINDER, 9) => io::ErrorKind::AlreadyExists,
+            (MODULE_LIBNX_BINDER, 14) => io::ErrorKind::WouldBlock,
+            (MODULE_LIBNX_BINDER, 15) => io::ErrorKind::TimedOut,
+
+            _ => io::ErrorKind::Other,
+        }
+    }
+
+    /// Recovers the result code behind an `io::Error`.
+    ///
+    /// This covers errors converted from a `ResultCode` as well as errors
+    /// `std::fs` reports with a raw result in place of an errno.
+    pub fn from_io_error(err: &io::Error) -> Option<Self> {
+        if let Some(rc) = err.get_ref().and_then(|inner| inner.downcast_ref::<ResultCode>()) {
+            return Some(*rc);
+        }
+        match err.raw_os_error() {
+            Some(code) if code as u32 > 0x1ff => Some(ResultCode(code as u32)),
+            _ => None,
+        }
+    }
+}
+
+/// The human-readable form of a result code, as produced by `describe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Description {
+    pub module: Option<&'static str>,
+    pub description: Option<&'static str>,
+}
+
+/// Names the module and description of a result code, where known.
+pub fn describe<R: Into<ResultCode>>(code: R) -> Description {
+    let rc = code.into();
+    Description {
+        module: rc.module_name(),
+        description: rc.description_name(),
+    }
+}
+
+impl From<u32> for ResultCode {
+    fn from(raw: u32) -> Self {
+        ResultCode(raw)
+    }
+}
+
+impl From<ResultCode> for u32 {
+    fn from(rc: ResultCode) -> Self {
+        rc.0
+    }
+}
+
+/// Formats the code the way the system error applet shows it, e.g. `2002-0001`.
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:04}", 2000 + self.module(), self.description())
+    }
+}
+
+impl fmt::Debug for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rc = *self;
+        write!(f, "ResultCode({}, {:#x}", rc, rc.0)?;
+        match (rc.module_name(), rc.description_name()) {
+            (Some(module), Some(desc)) => write!(f, ", {}::{}", module, desc)?,
+            (Some(module), None) => write!(f, ", {}::{}", module, rc.description())?,
+            _ => {}
+        }
+        write!(f, ")")
+    }
+}
+
+impl error::Error for ResultCode {}
+
+impl From<ResultCode> for io::Error {
+    fn from(rc: ResultCode) -> Self {
+        io::Error::new(rc.kind(), rc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_module_and_description() {
+        let rc = ResultCode::new(2, 1);
+        assert_eq!(rc.raw(), result_make!(2, 1));
+        assert_eq!(rc.module(), 2);
+        assert_eq!(rc.description(), 1);
+        assert!(rc.is_failure());
+        assert!(ResultCode::SUCCESS.is_success());
+    }
+
+    #[test]
+    fn formats_error_code() {
+        assert_eq!(ResultCode::new(2, 1).to_string(), "2002-0001");
+        assert_eq!(ResultCode::from_raw(0x2EE202).to_string(), "2002-6001");
+        assert_eq!(ResultCode::new(345, 12).to_string(), "2345-0012");
+    }
+
+    #[test]
+    fn describes_known_codes() {
+        assert_eq!(describe(0x202u32), Description { module: Some("FS"), description: Some("PathNotFound") });
+        assert_eq!(describe(ResultCode::new(names::MODULE_SM, 7)).description, Some("NotRegistered"));
+        assert_eq!(describe(ResultCode::new(names::MODULE_LIBNX, 8)).description, Some("NotInitialized"));
+        assert_eq!(describe(ResultCode::new(names::MODULE_LIBNX_BINDER, 10)).description, Some("DeadObject"));
+        assert_eq!(describe(ResultCode::new(names::MODULE_LIBNX_NVIDIA, 19)).description, Some("IoctlFailed"));
+        assert_eq!(describe(ResultCode::new(names::MODULE_HID, 100)), Description { module: Some("HID"), description: None });
+        assert_eq!(describe(ResultCode::new(500, 1)), Description { module: None, description: None });
+    }
+
+    #[test]
+    fn converts_to_io_error() {
+        let err: io::Error = ResultCode::new(2, 1).into();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(ResultCode::from_io_error(&err), Some(ResultCode::new(2, 1)));
+
+        let err: io::Error = ResultCode::new(2, 6003).into();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let err = io::Error::from_raw_os_error(0x402);
+        assert_eq!(ResultCode::from_io_error(&err), Some(ResultCode::new(2, 2)));
+        assert_eq!(ResultCode::from_io_error(&io::Error::from_raw_os_error(2)), None);
+    }
+
+    #[test]
+    fn debug_includes_names() {
+        assert_eq!(format!("{:?}", ResultCode::new(2, 2)), "ResultCode(2002-0002, 0x402, FS::PathAlreadyExists)");
+        assert_eq!(format!("{:?}", ResultCode::new(202, 100)), "ResultCode(2202-0100, 0xc8ca, HID::100)");
+        assert_eq!(format!("{:?}", ResultCode::new(500, 1)), "ResultCode(2500-0001, 0x3f4)");
+    }
+}
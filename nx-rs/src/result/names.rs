@@ -0,0 +1,255 @@
+//! Names for well-known result modules and descriptions.
+//!
+//! Module numbers follow switchbrew's error code list. The libnx ranges match
+//! the `LibnxError`, `LibnxBinderError` and `LibnxNvidiaError` enums from
+//! `switch/result.h`.
+
+use super::ResultCode;
+
+pub const MODULE_KERNEL: u32 = 1;
+pub const MODULE_FS: u32 = 2;
+pub const MODULE_OS: u32 = 3;
+pub const MODULE_NCM: u32 = 5;
+pub const MODULE_LR: u32 = 8;
+pub const MODULE_LOADER: u32 = 9;
+pub const MODULE_CMIF: u32 = 10;
+pub const MODULE_HIPC: u32 = 11;
+pub const MODULE_PM: u32 = 15;
+pub const MODULE_NS: u32 = 16;
+pub const MODULE_SM: u32 = 21;
+pub const MODULE_RO: u32 = 22;
+pub const MODULE_SPL: u32 = 26;
+pub const MODULE_SETTINGS: u32 = 105;
+pub const MODULE_NIFM: u32 = 110;
+pub const MODULE_VI: u32 = 114;
+pub const MODULE_TIME: u32 = 116;
+pub const MODULE_ACCOUNT: u32 = 124;
+pub const MODULE_APPLET: u32 = 128;
+pub const MODULE_USB: u32 = 140;
+pub const MODULE_HID: u32 = 202;
+pub const MODULE_LIBNX: u32 = 345;
+pub const MODULE_HOMEBREW_ABI: u32 = 346;
+pub const MODULE_HOMEBREW_LOADER: u32 = 347;
+pub const MODULE_LIBNX_NVIDIA: u32 = 348;
+pub const MODULE_LIBNX_BINDER: u32 = 349;
+
+/// `LibnxError_AlreadyInitialized`, e.g. from `socketInitializeDefault` when
+/// sockets are already set up.
+pub const RESULT_ALREADY_INITIALIZED: ResultCode = ResultCode::new(MODULE_LIBNX, 7);
+
+static MODULES: &[(u32, &str)] = &[
+    (MODULE_KERNEL, "Kernel"),
+    (MODULE_FS, "FS"),
+    (MODULE_OS, "OS"),
+    (MODULE_NCM, "NCM"),
+    (MODULE_LR, "LR"),
+    (MODULE_LOADER, "Loader"),
+    (MODULE_CMIF, "CMIF"),
+    (MODULE_HIPC, "HIPC"),
+    (MODULE_PM, "PM"),
+    (MODULE_NS, "NS"),
+    (MODULE_SM, "SM"),
+    (MODULE_RO, "RO"),
+    (MODULE_SPL, "SPL"),
+    (MODULE_SETTINGS, "Settings"),
+    (MODULE_NIFM, "NIFM"),
+    (MODULE_VI, "VI"),
+    (MODULE_TIME, "Time"),
+    (MODULE_ACCOUNT, "Account"),
+    (MODULE_APPLET, "Applet"),
+    (MODULE_USB, "USB"),
+    (MODULE_HID, "HID"),
+    (MODULE_LIBNX, "Libnx"),
+    (MODULE_HOMEBREW_ABI, "HomebrewAbi"),
+    (MODULE_HOMEBREW_LOADER, "HomebrewLoader"),
+    (MODULE_LIBNX_NVIDIA, "LibnxNvidia"),
+    (MODULE_LIBNX_BINDER, "LibnxBinder"),
+];
+
+static DESCRIPTIONS: &[(u32, u32, &str)] = &[
+    (MODULE_KERNEL, 7, "OutOfSessions"),
+    (MODULE_KERNEL, 14, "InvalidArgument"),
+    (MODULE_KERNEL, 33, "NotImplemented"),
+    (MODULE_KERNEL, 59, "TerminationRequested"),
+    (MODULE_KERNEL, 101, "InvalidSize"),
+    (MODULE_KERNEL, 102, "InvalidAddress"),
+    (MODULE_KERNEL, 103, "OutOfResource"),
+    (MODULE_KERNEL, 104, "OutOfMemory"),
+    (MODULE_KERNEL, 105, "OutOfHandles"),
+    (MODULE_KERNEL, 106, "InvalidCurrentMemory"),
+    (MODULE_KERNEL, 108, "InvalidNewMemoryPermission"),
+    (MODULE_KERNEL, 110, "InvalidMemoryRegion"),
+    (MODULE_KERNEL, 112, "InvalidPriority"),
+    (MODULE_KERNEL, 113, "InvalidCoreId"),
+    (MODULE_KERNEL, 114, "InvalidHandle"),
+    (MODULE_KERNEL, 115, "InvalidPointer"),
+    (MODULE_KERNEL, 116, "InvalidCombination"),
+    (MODULE_KERNEL, 117, "TimedOut"),
+    (MODULE_KERNEL, 118, "Cancelled"),
+    (MODULE_KERNEL, 119, "OutOfRange"),
+    (MODULE_KERNEL, 120, "InvalidEnumValue"),
+    (MODULE_KERNEL, 121, "NotFound"),
+    (MODULE_KERNEL, 122, "Busy"),
+    (MODULE_KERNEL, 123, "SessionClosed"),
+    (MODULE_KERNEL, 124, "NotHandled"),
+    (MODULE_KERNEL, 125, "InvalidState"),
+    (MODULE_KERNEL, 126, "ReservedUsed"),
+    (MODULE_KERNEL, 127, "NotSupported"),
+    (MODULE_KERNEL, 128, "Debug"),
+    (MODULE_KERNEL, 129, "NoThread"),
+    (MODULE_KERNEL, 130, "UnknownThread"),
+    (MODULE_KERNEL, 131, "PortClosed"),
+    (MODULE_KERNEL, 132, "LimitReached"),
+    (MODULE_KERNEL, 133, "InvalidMemoryPool"),
+    (MODULE_KERNEL, 258, "ReceiveListBroken"),
+    (MODULE_KERNEL, 259, "OutOfAddressSpace"),
+    (MODULE_KERNEL, 260, "MessageTooLarge"),
+
+    (MODULE_FS, 1, "PathNotFound"),
+    (MODULE_FS, 2, "PathAlreadyExists"),
+    (MODULE_FS, 7, "TargetLocked"),
+    (MODULE_FS, 8, "DirectoryNotEmpty"),
+    (MODULE_FS, 13, "DirectoryStatusChanged"),
+    (MODULE_FS, 30, "UsableSpaceNotEnough"),
+    (MODULE_FS, 60, "MountNameAlreadyExists"),
+    (MODULE_FS, 1001, "PartitionNotFound"),
+    (MODULE_FS, 1002, "TargetNotFound"),
+    (MODULE_FS, 2001, "SdCardNotPresent"),
+    (MODULE_FS, 6001, "InvalidArgument"),
+    (MODULE_FS, 6003, "TooLongPath"),
+    (MODULE_FS, 6004, "InvalidCharacter"),
+    (MODULE_FS, 6005, "InvalidPathFormat"),
+    (MODULE_FS, 6006, "DirectoryUnobtainable"),
+    (MODULE_FS, 6007, "NotNormalized"),
+    (MODULE_FS, 6300, "UnsupportedOperation"),
+    (MODULE_FS, 6400, "PermissionDenied"),
+
+    (MODULE_CMIF, 202, "InvalidHeaderSize"),
+    (MODULE_CMIF, 211, "InvalidInHeader"),
+    (MODULE_CMIF, 212, "InvalidOutHeader"),
+    (MODULE_CMIF, 221, "UnknownCommandId"),
+    (MODULE_CMIF, 232, "InvalidOutRawSize"),
+    (MODULE_CMIF, 235, "InvalidNumInObjects"),
+    (MODULE_CMIF, 236, "InvalidNumOutObjects"),
+    (MODULE_CMIF, 239, "InvalidInObject"),
+    (MODULE_CMIF, 261, "TargetNotFound"),
+    (MODULE_CMIF, 301, "OutOfDomainEntries"),
+
+    (MODULE_HIPC, 102, "OutOfSessionMemory"),
+    (MODULE_HIPC, 131, "OutOfSessions"),
+    (MODULE_HIPC, 141, "PointerBufferTooSmall"),
+    (MODULE_HIPC, 200, "OutOfDomains"),
+    (MODULE_HIPC, 301, "SessionClosed"),
+    (MODULE_HIPC, 402, "InvalidRequestSize"),
+    (MODULE_HIPC, 403, "UnknownCommandType"),
+    (MODULE_HIPC, 420, "InvalidCmifRequest"),
+    (MODULE_HIPC, 491, "TargetNotDomain"),
+    (MODULE_HIPC, 492, "DomainObjectNotFound"),
+
+    (MODULE_SM, 1, "OutOfProcesses"),
+    (MODULE_SM, 2, "InvalidClient"),
+    (MODULE_SM, 3, "OutOfSessions"),
+    (MODULE_SM, 4, "AlreadyRegistered"),
+    (MODULE_SM, 5, "OutOfServices"),
+    (MODULE_SM, 6, "InvalidServiceName"),
+    (MODULE_SM, 7, "NotRegistered"),
+    (MODULE_SM, 8, "NotAllowed"),
+    (MODULE_SM, 9, "TooLargeAccessControl"),
+
+    (MODULE_LIBNX, 1, "BadReloc"),
+    (MODULE_LIBNX, 2, "OutOfMemory"),
+    (MODULE_LIBNX, 3, "AlreadyMapped"),
+    (MODULE_LIBNX, 4, "BadGetInfo_Stack"),
+    (MODULE_LIBNX, 5, "BadGetInfo_Heap"),
+    (MODULE_LIBNX, 6, "BadQueryMemory"),
+    (MODULE_LIBNX, 7, "AlreadyInitialized"),
+    (MODULE_LIBNX, 8, "NotInitialized"),
+    (MODULE_LIBNX, 9, "NotFound"),
+    (MODULE_LIBNX, 10, "IoError"),
+    (MODULE_LIBNX, 11, "BadInput"),
+    (MODULE_LIBNX, 12, "BadReEnt"),
+    (MODULE_LIBNX, 13, "BufferProducerError"),
+    (MODULE_LIBNX, 14, "HandleTooEarly"),
+    (MODULE_LIBNX, 15, "HeapAllocFailed"),
+    (MODULE_LIBNX, 16, "TooManyOverrides"),
+    (MODULE_LIBNX, 17, "ParcelError"),
+    (MODULE_LIBNX, 18, "BadGfxInit"),
+    (MODULE_LIBNX, 19, "BadGfxEventWait"),
+    (MODULE_LIBNX, 20, "BadGfxQueueBuffer"),
+    (MODULE_LIBNX, 21, "BadGfxDequeueBuffer"),
+    (MODULE_LIBNX, 22, "AppletCmdidNotFound"),
+    (MODULE_LIBNX, 23, "BadAppletReceiveMessage"),
+    (MODULE_LIBNX, 24, "BadAppletNotifyRunning"),
+    (MODULE_LIBNX, 25, "BadAppletGetCurrentFocusState"),
+    (MODULE_LIBNX, 26, "BadAppletGetOperationMode"),
+    (MODULE_LIBNX, 27, "BadAppletGetPerformanceMode"),
+    (MODULE_LIBNX, 28, "BadUsbCommsRead"),
+    (MODULE_LIBNX, 29, "BadUsbCommsWrite"),
+    (MODULE_LIBNX, 30, "InitFail_SM"),
+    (MODULE_LIBNX, 31, "InitFail_AM"),
+    (MODULE_LIBNX, 32, "InitFail_HID"),
+    (MODULE_LIBNX, 33, "InitFail_FS"),
+    (MODULE_LIBNX, 34, "BadGetInfo_Rng"),
+    (MODULE_LIBNX, 35, "JitUnavailable"),
+    (MODULE_LIBNX, 36, "WeirdKernel"),
+    (MODULE_LIBNX, 37, "IncompatSysVer"),
+    (MODULE_LIBNX, 38, "InitFail_Time"),
+    (MODULE_LIBNX, 39, "TooManyDevOpTabs"),
+    (MODULE_LIBNX, 40, "DomainMessageUnknownType"),
+    (MODULE_LIBNX, 41, "DomainMessageTooManyObjectIds"),
+    (MODULE_LIBNX, 42, "AppletFailedToInitialize"),
+    (MODULE_LIBNX, 43, "ApmFailedToInitialize"),
+    (MODULE_LIBNX, 44, "NvinfoFailedToInitialize"),
+    (MODULE_LIBNX, 45, "NvbufFailedToInitialize"),
+    (MODULE_LIBNX, 46, "LibAppletBadExit"),
+
+    (MODULE_LIBNX_BINDER, 1, "Unknown"),
+    (MODULE_LIBNX_BINDER, 2, "NoMemory"),
+    (MODULE_LIBNX_BINDER, 3, "InvalidOperation"),
+    (MODULE_LIBNX_BINDER, 4, "BadValue"),
+    (MODULE_LIBNX_BINDER, 5, "BadType"),
+    (MODULE_LIBNX_BINDER, 6, "NameNotFound"),
+    (MODULE_LIBNX_BINDER, 7, "PermissionDenied"),
+    (MODULE_LIBNX_BINDER, 8, "NoInit"),
+    (MODULE_LIBNX_BINDER, 9, "AlreadyExists"),
+    (MODULE_LIBNX_BINDER, 10, "DeadObject"),
+    (MODULE_LIBNX_BINDER, 11, "FailedTransaction"),
+    (MODULE_LIBNX_BINDER, 12, "BadIndex"),
+    (MODULE_LIBNX_BINDER, 13, "NotEnoughData"),
+    (MODULE_LIBNX_BINDER, 14, "WouldBlock"),
+    (MODULE_LIBNX_BINDER, 15, "TimedOut"),
+    (MODULE_LIBNX_BINDER, 16, "UnknownTransaction"),
+    (MODULE_LIBNX_BINDER, 17, "FdsNotAllowed"),
+
+    (MODULE_LIBNX_NVIDIA, 1, "Unknown"),
+    (MODULE_LIBNX_NVIDIA, 2, "NotImplemented"),
+    (MODULE_LIBNX_NVIDIA, 3, "NotSupported"),
+    (MODULE_LIBNX_NVIDIA, 4, "NotInitialized"),
+    (MODULE_LIBNX_NVIDIA, 5, "BadParameter"),
+    (MODULE_LIBNX_NVIDIA, 6, "Timeout"),
+    (MODULE_LIBNX_NVIDIA, 7, "InsufficientMemory"),
+    (MODULE_LIBNX_NVIDIA, 8, "ReadOnlyAttribute"),
+    (MODULE_LIBNX_NVIDIA, 9, "InvalidState"),
+    (MODULE_LIBNX_NVIDIA, 10, "InvalidAddress"),
+    (MODULE_LIBNX_NVIDIA, 11, "InvalidSize"),
+    (MODULE_LIBNX_NVIDIA, 12, "BadValue"),
+    (MODULE_LIBNX_NVIDIA, 13, "AlreadyAllocated"),
+    (MODULE_LIBNX_NVIDIA, 14, "Busy"),
+    (MODULE_LIBNX_NVIDIA, 15, "ResourceError"),
+    (MODULE_LIBNX_NVIDIA, 16, "CountMismatch"),
+    (MODULE_LIBNX_NVIDIA, 17, "SharedMemoryTooSmall"),
+    (MODULE_LIBNX_NVIDIA, 18, "FileOperationFailed"),
+    (MODULE_LIBNX_NVIDIA, 19, "IoctlFailed"),
+];
+
+/// Looks up the name of a result module, e.g. `"FS"` for module 2.
+pub fn module_name(module: u32) -> Option<&'static str> {
+    MODULES.iter().find(|&&(m, _)| m == module).map(|&(_, name)| name)
+}
+
+/// Looks up the name of a description within a result module.
+pub fn description_name(module: u32, description: u32) -> Option<&'static str> {
+    DESCRIPTIONS.iter()
+        .find(|&&(m, d, _)| m == module && d == description)
+        .map(|&(_, _, name)| name)
+}
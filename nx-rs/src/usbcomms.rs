@@ -2,47 +2,69 @@
 
 pub use ::libnx::UsbCommsInterfaceInfo;
 
-use std::sync::atomic::{AtomicBool, Ordering};
-
+use macros::service::RefCount;
+use result::names::RESULT_ALREADY_INITIALIZED;
 use os;
+
+/// A reference to usbcomms. It stays initialized for as long as any clone of
+/// any handle is alive.
 #[derive(Debug)]
 pub struct Handle(());
 
-static INITIALIZED: AtomicBool = AtomicBool::new(false);
+static REFS: RefCount = RefCount::new();
+
+fn init_result(rc: u32) -> os::Result<()> {
+    match rc {
+        0 => Ok(()),
+        err => Err(os::ResultCode::from_raw(err)),
+    }
+}
+
+impl Clone for Handle {
+    fn clone(&self) -> Self {
+        let _ = REFS.acquire(|| -> Result<(), ()> { Ok(()) });
+        Handle(())
+    }
+}
 
 impl Drop for Handle {
     fn drop(&mut self) {
-        if INITIALIZED.swap(false, Ordering::SeqCst) {
-            unsafe { ::libnx::usbCommsExit() };
-        }
+        REFS.release(|| unsafe { ::libnx::usbCommsExit() });
     }
 }
 
 impl Handle {
-    pub fn new() -> Option<os::Result<Handle>> {
-        if !INITIALIZED.swap(true, Ordering::SeqCst) {
-            let res = unsafe { ::libnx::usbCommsInitialize() };
-            match res {
-                0 => Some(Ok(Handle(()))),
-                err => Some(Err(err)),
-            }
-        } else {
-            None
-        }
+    /// Opens usbcomms with its default interface, unless it's already open.
+    pub fn open() -> os::Result<Handle> {
+        REFS.acquire(|| init_result(unsafe { ::libnx::usbCommsInitialize() }))?;
+        Ok(Handle(()))
     }
 
-    pub fn new_ex(infos: &[UsbCommsInterfaceInfo]) -> Option<os::Result<Handle>> {
+    /// Opens usbcomms with the interfaces in `infos`. They can't change while
+    /// it's open, so this fails with `RESULT_ALREADY_INITIALIZED` if another
+    /// handle is alive.
+    pub fn open_ex(infos: &[UsbCommsInterfaceInfo]) -> os::Result<Handle> {
         let num_interfaces = infos.len() as u32;
         let infos_ptr = infos as *const _ as *const _;
-        if !INITIALIZED.swap(true, Ordering::SeqCst) {
-            let res = unsafe { ::libnx::usbCommsInitializeEx(num_interfaces, infos_ptr) };
-            match res {
-                0 => Some(Ok(Handle(()))),
-                err => Some(Err(err)),
-            }
-        } else {
-            None
-        }
+        REFS.acquire_first(|| init_result(unsafe { ::libnx::usbCommsInitializeEx(num_interfaces, infos_ptr) }),
+                           RESULT_ALREADY_INITIALIZED)?;
+        Ok(Handle(()))
+    }
+
+    /// Compatibility shim for the single-handle API. Handles are
+    /// reference-counted now, so this never returns `None`.
+    pub fn new() -> Option<os::Result<Handle>> {
+        Some(Handle::open())
+    }
+
+    /// Compatibility shim for the single-handle API, see `open_ex`.
+    pub fn new_ex(infos: &[UsbCommsInterfaceInfo]) -> Option<os::Result<Handle>> {
+        Some(Handle::open_ex(infos))
+    }
+
+    /// The number of handles to usbcomms currently alive.
+    pub fn ref_count() -> usize {
+        REFS.count()
     }
 
     pub fn read_ex(&mut self, buffer: &mut [u8], interface: u32) -> usize {
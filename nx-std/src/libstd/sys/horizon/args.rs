@@ -8,24 +8,55 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use ffi::OsString;
+use ffi::{CStr, OsString};
 use marker::PhantomData;
+use ptr;
+use sys::argv;
+use sys::ext::ffi::OsStringExt;
+use sys_common::mutex::Mutex;
 use vec;
 
+// The argv string the loader passes in its argv config entry. It lives for
+// as long as the app, and is split anew each time args are read.
+static mut ARGV: *const u8 = ptr::null();
+// We never call `LOCK.init()`, so it is UB to attempt to acquire this mutex
+// reentrantly!
+static LOCK: Mutex = Mutex::new();
+
+// The argc and argv from `main` are ignored: libnx has already split them
+// from the same string, but an app without a C `main` doesn't get them.
 pub unsafe fn init(_argc: isize, _argv: *const *const u8) {
-    // Currently null because we haven't implemented args yet
+    let _guard = LOCK.lock();
+    if libnx::envHasArgv() {
+        ARGV = libnx::envGetArgv() as *const u8;
+    }
 }
 
 pub unsafe fn cleanup() {
+    let _guard = LOCK.lock();
+    ARGV = ptr::null();
 }
 
 pub fn args() -> Args {
-    return Args {
-        iter: Vec::new().into_iter(),
+    Args {
+        iter: clone().into_iter(),
         _dont_send_or_sync_me: PhantomData,
     }
 }
 
+fn clone() -> Vec<OsString> {
+    unsafe {
+        let _guard = LOCK.lock();
+        if ARGV.is_null() {
+            return Vec::new();
+        }
+        let args = CStr::from_ptr(ARGV as *const _).to_bytes();
+        argv::parse(args).into_iter()
+            .map(|arg| OsStringExt::from_vec(arg.to_vec()))
+            .collect()
+    }
+}
+
 pub struct Args {
     iter: vec::IntoIter<OsString>,
     _dont_send_or_sync_me: PhantomData<*mut ()>,
@@ -0,0 +1,169 @@
+//! Splitting the argv string the homebrew loader passes to an app.
+//!
+//! This follows libnx's own `argvSetup`, so an app sees the same arguments
+//! whether it reads them through `std::env::args` or `__system_argv`:
+//!
+//! * arguments are separated by runs of whitespace;
+//! * an argument starting with `"` runs to the next `"`, and the quotes
+//!   aren't part of it. There are no escapes, so a quoted argument can't
+//!   contain `"`;
+//! * a `"` anywhere else is an ordinary character;
+//! * an unterminated quote runs to the end of the string;
+//! * a last argument starting with `_NXLINK_`, which nxlink adds for its
+//!   stdio server, is dropped.
+//!
+//! `join` goes the other way, for the argv string of the next NRO to load.
+//!
+//! Nothing here depends on the rest of libstd, so the tests run on the host
+//! with `rustc --test src/libstd/sys/horizon/argv.rs && ./argv`.
+
+const NXLINK_ARG: &[u8] = b"_NXLINK_";
+
+/// The characters C's `isspace` accepts.
+fn is_space(c: u8) -> bool {
+    c == b' ' || (b'\t' <= c && c <= b'\r')
+}
+
+/// Splits `args`, which ends at its first NUL if it has one.
+pub fn parse(args: &[u8]) -> Vec<&[u8]> {
+    let len = args.iter().position(|&c| c == 0).unwrap_or(args.len());
+    let args = &args[..len];
+    let mut parsed = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < args.len() && is_space(args[i]) {
+            i += 1;
+        }
+        if i == args.len() {
+            break;
+        }
+        let quoted = args[i] == b'"';
+        if quoted {
+            i += 1;
+        }
+        let start = i;
+        while i < args.len() && !(if quoted { args[i] == b'"' } else { is_space(args[i]) }) {
+            i += 1;
+        }
+        parsed.push(&args[start..i]);
+        // Skip the closing quote, which doesn't need whitespace after it.
+        if quoted && i < args.len() {
+            i += 1;
+        }
+    }
+    if parsed.last().map_or(false, |arg| arg.starts_with(NXLINK_ARG)) {
+        parsed.pop();
+    }
+    parsed
+}
+
+/// Joins `args` into a string `parse` splits back into them, quoting those
+/// that need it. Returns `None` if one of them can't be written that way:
+/// one with a NUL, one that contains `"` but has to be quoted, or a last one
+/// that would be taken for nxlink's.
+pub fn join(args: &[&[u8]]) -> Option<Vec<u8>> {
+    let mut joined = Vec::new();
+    for (i, &arg) in args.iter().enumerate() {
+        if arg.contains(&0) || (i == args.len() - 1 && arg.starts_with(NXLINK_ARG)) {
+            return None;
+        }
+        if i > 0 {
+            joined.push(b' ');
+        }
+        if arg.is_empty() || arg[0] == b'"' || arg.iter().any(|&c| is_space(c)) {
+            if arg.contains(&b'"') {
+                return None;
+            }
+            joined.push(b'"');
+            joined.extend_from_slice(arg);
+            joined.push(b'"');
+        } else {
+            joined.extend_from_slice(arg);
+        }
+    }
+    Some(joined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{join, parse};
+
+    fn strs(args: &str) -> Vec<&str> {
+        parse(args.as_bytes()).into_iter().map(|arg| ::std::str::from_utf8(arg).unwrap()).collect()
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(strs("sdmc:/switch/app.nro -v  --level 3"), ["sdmc:/switch/app.nro", "-v", "--level", "3"]);
+        assert_eq!(strs(" \t\r\na\x0bb\x0c "), ["a", "b"]);
+        assert!(strs("").is_empty());
+        assert!(strs("   ").is_empty());
+    }
+
+    #[test]
+    fn strips_quotes() {
+        assert_eq!(strs("app \"two words\" three"), ["app", "two words", "three"]);
+        assert_eq!(strs("\"\" x"), ["", "x"]);
+        assert_eq!(strs("\"a\"b"), ["a", "b"]);
+        assert_eq!(strs("\"  padded  \""), ["  padded  "]);
+    }
+
+    #[test]
+    fn keeps_quotes_inside_arguments() {
+        assert_eq!(strs("say=\"hi there\""), ["say=\"hi", "there\""]);
+        assert_eq!(strs("a\"b"), ["a\"b"]);
+    }
+
+    #[test]
+    fn unterminated_quote_runs_to_the_end() {
+        assert_eq!(strs("app \"no end  here"), ["app", "no end  here"]);
+        assert_eq!(strs("app \""), ["app", ""]);
+    }
+
+    #[test]
+    fn stops_at_nul() {
+        assert_eq!(parse(b"app one\0two"), [&b"app"[..], &b"one"[..]]);
+    }
+
+    #[test]
+    fn drops_nxlink_argument() {
+        assert_eq!(strs("app.nro -v _NXLINK_"), ["app.nro", "-v"]);
+        assert_eq!(strs("app.nro _NXLINK_ -v"), ["app.nro", "_NXLINK_", "-v"]);
+        assert!(strs("_NXLINK_").is_empty());
+    }
+
+    fn join_strs(args: &[&str]) -> Option<String> {
+        let args: Vec<&[u8]> = args.iter().map(|arg| arg.as_bytes()).collect();
+        join(&args).map(|joined| String::from_utf8(joined).unwrap())
+    }
+
+    #[test]
+    fn joins_and_quotes() {
+        assert_eq!(join_strs(&["sdmc:/switch/app.nro", "-v"]).unwrap(), "sdmc:/switch/app.nro -v");
+        assert_eq!(join_strs(&["app", "two words", "", "tab\there"]).unwrap(),
+                   "app \"two words\" \"\" \"tab\there\"");
+        assert_eq!(join_strs(&["app", "say=\"hi\""]).unwrap(), "app say=\"hi\"");
+        assert_eq!(join_strs(&[]).unwrap(), "");
+    }
+
+    #[test]
+    fn join_round_trips() {
+        let cases: &[&[&str]] = &[
+            &["app"],
+            &["sdmc:/switch/my app.nro", "--name", "a b  c", "", "x\"y", "_NXLINK_", "end"],
+            &["app", " leading", "trailing ", "\t"],
+        ];
+        for &args in cases {
+            let joined = join_strs(args).unwrap();
+            assert_eq!(strs(&joined), args, "{:?}", joined);
+        }
+    }
+
+    #[test]
+    fn join_rejects_what_cant_be_parsed_back() {
+        assert_eq!(join_strs(&["app", "two \"words\""]), None);
+        assert_eq!(join_strs(&["app", "\"quoted"]), None);
+        assert_eq!(join_strs(&["app", "nul\0"]), None);
+        assert_eq!(join_strs(&["app", "_NXLINK_"]), None);
+    }
+}
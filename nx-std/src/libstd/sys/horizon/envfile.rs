@@ -0,0 +1,91 @@
+//! Reading the `.env`-style file an app's environment is seeded from.
+//!
+//! Each line is `KEY=VALUE`, optionally preceded by `export `. Whitespace
+//! around keys and values is ignored, and a value wrapped in matching `"` or
+//! `'` quotes has them removed, with no escapes inside. Blank lines, lines
+//! starting with `#` and lines without a key are skipped. Everything after
+//! the first `=` is the value, including any `#`.
+//!
+//! Nothing here depends on the rest of libstd, so the tests run on the host
+//! with `rustc --test src/libstd/sys/horizon/envfile.rs && ./envfile`.
+
+fn is_blank(c: u8) -> bool {
+    c == b' ' || c == b'\t' || c == b'\r'
+}
+
+fn trim(mut s: &[u8]) -> &[u8] {
+    while !s.is_empty() && is_blank(s[0]) {
+        s = &s[1..];
+    }
+    while !s.is_empty() && is_blank(s[s.len() - 1]) {
+        s = &s[..s.len() - 1];
+    }
+    s
+}
+
+fn unquote(s: &[u8]) -> &[u8] {
+    match (s.first(), s.last()) {
+        (Some(&open), Some(&close)) if s.len() >= 2 && open == close && (open == b'"' || open == b'\'') => {
+            &s[1..s.len() - 1]
+        }
+        _ => s,
+    }
+}
+
+/// The variables set by `contents`, in order. Later ones win over earlier
+/// ones with the same key.
+pub fn parse(contents: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut vars = Vec::new();
+    for line in contents.split(|&c| c == b'\n') {
+        let mut line = trim(line);
+        if line.is_empty() || line[0] == b'#' {
+            continue;
+        }
+        if line.starts_with(b"export") && line.len() > 6 && is_blank(line[6]) {
+            line = trim(&line[6..]);
+        }
+        let eq = match line.iter().position(|&c| c == b'=') {
+            Some(eq) => eq,
+            None => continue,
+        };
+        let key = trim(&line[..eq]);
+        // A NUL couldn't be passed on to C anyway.
+        if key.is_empty() || line.contains(&0) {
+            continue;
+        }
+        vars.push((key, unquote(trim(&line[eq + 1..]))));
+    }
+    vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    fn strs(contents: &str) -> Vec<(&str, &str)> {
+        parse(contents.as_bytes()).into_iter()
+            .map(|(k, v)| (::std::str::from_utf8(k).unwrap(), ::std::str::from_utf8(v).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_assignments() {
+        assert_eq!(strs("RUST_LOG=debug\nRUST_BACKTRACE = 1 \r\nEMPTY=\n"),
+                   [("RUST_LOG", "debug"), ("RUST_BACKTRACE", "1"), ("EMPTY", "")]);
+        assert_eq!(strs("URL=http://host/?a=b#frag"), [("URL", "http://host/?a=b#frag")]);
+    }
+
+    #[test]
+    fn skips_comments_and_junk() {
+        assert_eq!(strs("# a comment\n\n   \nnot an assignment\n=no key\nA=1"), [("A", "1")]);
+        assert_eq!(strs("BAD=a\0b\nGOOD=c"), [("GOOD", "c")]);
+    }
+
+    #[test]
+    fn strips_export_and_quotes() {
+        assert_eq!(strs("export A=1\nexported=2\nexport\tB='two words'"),
+                   [("A", "1"), ("exported", "2"), ("B", "two words")]);
+        assert_eq!(strs("A=\"x\"\nB=\"unbalanced'\nC=\"\nD=''"),
+                   [("A", "x"), ("B", "\"unbalanced'"), ("C", "\""), ("D", "")]);
+    }
+}
@@ -39,6 +39,7 @@
 pub mod io;
 pub mod ffi;
 pub mod fs;
+pub mod process;
 pub mod raw;
 
 /// A prelude for conveniently writing platform-specific code.
@@ -56,4 +57,6 @@ pub mod prelude {
     pub use super::fs::DirEntryExt;
     #[doc(no_inline)] #[stable(feature = "file_offset", since = "1.15.0")]
     pub use super::fs::FileExt;
+    #[doc(no_inline)] #[stable(feature = "rust1", since = "1.0.0")]
+    pub use super::process::CommandExt;
 }
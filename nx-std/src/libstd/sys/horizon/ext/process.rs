@@ -0,0 +1,56 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Horizon-specific extensions to primitives in the `std::process` module.
+
+#![stable(feature = "rust1", since = "1.0.0")]
+
+use io;
+use process;
+use sys;
+use sys_common::AsInnerMut;
+
+/// Horizon-specific extensions to the [`process::Command`] builder.
+///
+/// [`process::Command`]: ../../../../std/process/struct.Command.html
+#[stable(feature = "rust1", since = "1.0.0")]
+pub trait CommandExt {
+    /// Has the homebrew loader start this command's program, an NRO path such
+    /// as `sdmc:/switch/app.nro`, with its arguments once this app exits, and
+    /// exits.
+    ///
+    /// Horizon can't run two apps side by side, so this is the only way to
+    /// run a `Command`. The environment, working directory and stdio settings
+    /// aren't passed on.
+    ///
+    /// On success this function will not return, and otherwise it will return
+    /// an error indicating why the next load couldn't be set up. Exiting has
+    /// the same implications as calling [`process::exit`].
+    ///
+    /// [`process::exit`]: ../../../process/fn.exit.html
+    #[stable(feature = "process_exec2", since = "1.9.0")]
+    fn exec(&mut self) -> io::Error;
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl CommandExt for process::Command {
+    fn exec(&mut self) -> io::Error {
+        self.as_inner_mut().exec()
+    }
+}
+
+/// Whether the loader can start another NRO when this app exits, so that
+/// [`CommandExt::exec`] can work.
+///
+/// [`CommandExt::exec`]: trait.CommandExt.html#tymethod.exec
+#[unstable(feature = "horizon_next_load", issue = "0")]
+pub fn next_load_supported() -> bool {
+    sys::process::next_load_supported()
+}
@@ -37,12 +37,14 @@ pub use libc::strlen;
 pub mod weak;
 
 pub mod args;
+pub mod argv;
 pub mod android;
 #[cfg(feature = "backtrace")]
 pub mod backtrace;
 pub mod cmath;
 pub mod condvar;
 pub mod env;
+pub mod envfile;
 pub mod ext;
 pub mod fast_thread_local;
 pub mod fd;
@@ -61,6 +63,7 @@ pub mod path;
 pub mod pipe;
 pub mod process;
 pub mod rand;
+pub mod result;
 pub mod rwlock;
 pub mod stack_overflow;
 pub mod thread;
@@ -107,6 +110,10 @@ pub fn unsupported_err() -> io::Error {
 pub enum Void {}
 
 pub fn decode_error_kind(errno: i32) -> ErrorKind {
+    if result::is_result(errno) {
+        return result::decode_result_kind(errno as u32);
+    }
+
     match errno as libc::c_int {
         libc::ECONNREFUSED => ErrorKind::ConnectionRefused,
         libc::ECONNRESET => ErrorKind::ConnectionReset,
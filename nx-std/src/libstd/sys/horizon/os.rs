@@ -8,17 +8,23 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use collections::BTreeMap;
 use error::Error as StdError;
 use ffi::{CString, CStr, OsString, OsStr};
 use fmt;
+use fs;
 use io;
 use iter;
 use libc::{self, c_int, c_char};
+use marker::PhantomData;
 use path::{self, PathBuf};
+use ptr;
 use slice;
 use str;
-use sys::{unsupported, Void};
+use sys::{args, envfile, result};
 use sys::horizon::ext::ffi::{OsStrExt, OsStringExt};
+use sys_common::mutex::Mutex;
+use vec;
 
 const TMPBUF_SZ: usize = 128;
 
@@ -41,6 +47,10 @@ pub fn set_errno(e: i32) {
 
 /// Gets a detailed string description for the given error number.
 pub fn error_string(errno: i32) -> String {
+    if result::is_result(errno) {
+        return result::result_string(errno as u32);
+    }
+
     extern {
     #[cfg_attr(any(target_os = "linux", target_env = "newlib"),
                    link_name = "__xpg_strerror_r")]
@@ -151,41 +161,130 @@ impl StdError for JoinPathsError {
     fn description(&self) -> &str { "failed to join paths" }
 }
 
+/// The NRO the loader started, from the path it passes as the first
+/// argument. Titles started some other way, such as installed NSPs, don't
+/// get one.
 pub fn current_exe() -> io::Result<PathBuf> {
-    unsupported()
+    let nro = match args::args().next() {
+        Some(nro) => PathBuf::from(nro),
+        None => return Err(io::Error::new(io::ErrorKind::NotFound,
+                                          "the loader didn't pass the NRO's path")),
+    };
+    // Paths with a device, like `sdmc:/switch/app.nro`, are relative as far
+    // as `Path` is concerned.
+    if nro.as_os_str().as_bytes().windows(2).any(|w| w == b":/") {
+        return Ok(nro);
+    }
+    Ok(getcwd()?.join(nro))
+}
+
+// Horizon has no environment to inherit or pass on, so it lives only in this
+// process. It's created on first use, from the `.env`-style file beside the
+// NRO (`app.env` for `app.nro`) if there is one.
+static mut ENV: *mut BTreeMap<OsString, OsString> = ptr::null_mut();
+// We never call `ENV_LOCK.init()`, so it is UB to attempt to acquire this
+// mutex reentrantly!
+static ENV_LOCK: Mutex = Mutex::new();
+
+fn with_env<T, F: FnOnce(&mut BTreeMap<OsString, OsString>) -> T>(f: F) -> T {
+    unsafe {
+        let _guard = ENV_LOCK.lock();
+        if ENV.is_null() {
+            ENV = Box::into_raw(Box::new(initial_env()));
+        }
+        f(&mut *ENV)
+    }
 }
 
-pub struct Env(Void);
+fn initial_env() -> BTreeMap<OsString, OsString> {
+    let mut env = BTreeMap::new();
+    let path = match args::args().next() {
+        Some(nro) => PathBuf::from(nro).with_extension("env"),
+        None => return env,
+    };
+    if let Ok(contents) = fs::read(&path) {
+        for (key, value) in envfile::parse(&contents) {
+            env.insert(OsString::from_vec(key.to_vec()), OsString::from_vec(value.to_vec()));
+        }
+    }
+    env
+}
+
+fn check_var(s: &OsStr) -> io::Result<()> {
+    if s.as_bytes().contains(&0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "environment variables can't contain NUL"));
+    }
+    Ok(())
+}
+
+pub struct Env {
+    iter: vec::IntoIter<(OsString, OsString)>,
+    _dont_send_or_sync_me: PhantomData<*mut ()>,
+}
 
 impl Iterator for Env {
     type Item = (OsString, OsString);
-    fn next(&mut self) -> Option<(OsString, OsString)> {
-        match self.0 {}
-    }
+    fn next(&mut self) -> Option<(OsString, OsString)> { self.iter.next() }
+    fn size_hint(&self) -> (usize, Option<usize>) { self.iter.size_hint() }
 }
 
 pub fn env() -> Env {
-    panic!("not supported on 3DS yet")
+    let vars = with_env(|env| {
+        env.iter().map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>()
+    });
+    Env {
+        iter: vars.into_iter(),
+        _dont_send_or_sync_me: PhantomData,
+    }
 }
 
-pub fn getenv(_k: &OsStr) -> io::Result<Option<OsString>> {
-    return Ok(None)
+pub fn getenv(k: &OsStr) -> io::Result<Option<OsString>> {
+    Ok(with_env(|env| env.get(k).cloned()))
 }
 
-pub fn setenv(_k: &OsStr, _v: &OsStr) -> io::Result<()> {
-    unsupported()
+pub fn setenv(k: &OsStr, v: &OsStr) -> io::Result<()> {
+    check_var(k)?;
+    check_var(v)?;
+    with_env(|env| env.insert(k.to_os_string(), v.to_os_string()));
+    Ok(())
 }
 
-pub fn unsetenv(_n: &OsStr) -> io::Result<()> {
-    unsupported()
+pub fn unsetenv(n: &OsStr) -> io::Result<()> {
+    check_var(n)?;
+    with_env(|env| env.remove(n));
+    Ok(())
+}
+
+/// `sdmc:/switch/<app>`, where homebrew keeps its files, named after the NRO.
+/// It is `sdmc:/switch` if the NRO's path isn't known.
+fn app_dir() -> PathBuf {
+    let mut dir = PathBuf::from("sdmc:/switch");
+    if let Some(name) = current_exe().ok().as_ref().and_then(|nro| nro.file_stem()) {
+        dir.push(name);
+    }
+    dir
+}
+
+/// Creates `dir` if it's missing, as there is no system-wide directory that
+/// is sure to exist. Failing is left for whoever uses it to report.
+fn created(dir: PathBuf) -> PathBuf {
+    let _ = fs::create_dir_all(&dir);
+    dir
 }
 
 pub fn temp_dir() -> PathBuf {
-     PathBuf::from("/tmp")
+    match getenv(OsStr::new("TMPDIR")) {
+        Ok(Some(dir)) => PathBuf::from(dir),
+        _ => created(app_dir().join("tmp")),
+    }
 }
 
 pub fn home_dir() -> Option<PathBuf> {
-    None
+    match getenv(OsStr::new("HOME")) {
+        Ok(Some(dir)) => Some(PathBuf::from(dir)),
+        _ => Some(created(app_dir())),
+    }
 }
 
 pub fn exit(code: i32) -> ! {
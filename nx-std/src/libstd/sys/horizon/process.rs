@@ -8,19 +8,29 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use ffi::OsStr;
+use ffi::{CString, OsStr, OsString};
 use fmt;
 use io;
+use process;
+use sys::argv;
+use sys::ext::ffi::OsStrExt;
 use sys::fs::File;
 use sys::pipe::AnonPipe;
-use sys::{unsupported, Void};
+use sys::Void;
 use sys_common::process::{CommandEnv, DefaultEnvKey};
 
 ////////////////////////////////////////////////////////////////////////////////
 // Command
 ////////////////////////////////////////////////////////////////////////////////
 
+// Horizon can't run processes side by side. The only thing a command can do
+// is be the NRO the loader starts once this one exits, so only the program
+// and its arguments matter; the environment, working directory and stdio
+// aren't passed on.
 pub struct Command {
+    program: OsString,
+    // The program comes first, as the loader expects.
+    args: Vec<OsString>,
     env: CommandEnv<DefaultEnvKey>
 }
 
@@ -36,16 +46,20 @@ pub enum Stdio {
     Inherit,
     Null,
     MakePipe,
+    Fd(File),
 }
 
 impl Command {
-    pub fn new(_program: &OsStr) -> Command {
+    pub fn new(program: &OsStr) -> Command {
         Command {
+            program: program.to_os_string(),
+            args: vec![program.to_os_string()],
             env: Default::default()
         }
     }
 
-    pub fn arg(&mut self, _arg: &OsStr) {
+    pub fn arg(&mut self, arg: &OsStr) {
+        self.args.push(arg.to_os_string());
     }
 
     pub fn env_mut(&mut self) -> &mut CommandEnv<DefaultEnvKey> {
@@ -66,10 +80,47 @@ impl Command {
 
     pub fn spawn(&mut self, _default: Stdio, _needs_stdin: bool)
         -> io::Result<(Process, StdioPipes)> {
-        unsupported()
+        Err(io::Error::new(io::ErrorKind::Other,
+                           "processes can't be spawned on Horizon; \
+                            `exec` can hand over to another NRO instead"))
+    }
+
+    /// Has the loader start the program once this app exits, then exits.
+    /// Only returns if that can't be arranged.
+    pub fn exec(&mut self) -> io::Error {
+        if !next_load_supported() {
+            return io::Error::new(io::ErrorKind::Other,
+                                  "the loader can't start another NRO");
+        }
+        let path = match CString::new(self.program.as_bytes()) {
+            Ok(path) => path,
+            Err(err) => return err.into(),
+        };
+        let args: Vec<&[u8]> = self.args.iter().map(|arg| arg.as_bytes()).collect();
+        let argv = match argv::join(&args) {
+            // `join` leaves out NULs, so this can't fail.
+            Some(argv) => CString::new(argv).unwrap(),
+            None => return io::Error::new(io::ErrorKind::InvalidInput,
+                                          "an argument can't be passed on: quoted arguments \
+                                           can't contain `\"`, nor any argument a NUL"),
+        };
+        let rc = unsafe {
+            libnx::envSetNextLoad(path.as_ptr() as *const _, argv.as_ptr() as *const _)
+        };
+        if rc != 0 {
+            return io::Error::from_raw_os_error(rc as i32);
+        }
+        // The loader only takes over once this app has exited.
+        process::exit(0)
     }
 }
 
+/// Whether the loader can start another NRO when this one exits. The
+/// homebrew menu can; an app run as an installed title has no loader at all.
+pub fn next_load_supported() -> bool {
+    unsafe { libnx::envHasNextLoad() }
+}
+
 impl From<AnonPipe> for Stdio {
     fn from(pipe: AnonPipe) -> Stdio {
         pipe.diverge()
@@ -77,14 +128,17 @@ impl From<AnonPipe> for Stdio {
 }
 
 impl From<File> for Stdio {
-    fn from(_file: File) -> Stdio {
-        //file.diverge()
-        unimplemented!()
+    fn from(file: File) -> Stdio {
+        Stdio::Fd(file)
     }
 }
 
 impl fmt::Debug for Command {
-    fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.program)?;
+        for arg in &self.args[1..] {
+            write!(f, " {:?}", arg)?;
+        }
         Ok(())
     }
 }
@@ -0,0 +1,85 @@
+// Copyright 2019 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Horizon result codes carried in `io::Error`.
+//!
+//! libnx's devoptabs hand unknown failures back through errno as the raw
+//! `Result` value, and nx-rs builds `io::Error`s from them the same way. A
+//! result packs a 9-bit module with a 13-bit description, and a description
+//! of zero never denotes a failure, so any code at or above 0x200 is a result
+//! rather than a newlib errno.
+
+use io::ErrorKind;
+
+const MODULE_KERNEL: u32 = 1;
+const MODULE_FS: u32 = 2;
+const MODULE_HIPC: u32 = 11;
+const MODULE_SM: u32 = 21;
+const MODULE_LIBNX: u32 = 345;
+const MODULE_LIBNX_NVIDIA: u32 = 348;
+const MODULE_LIBNX_BINDER: u32 = 349;
+
+pub fn is_result(code: i32) -> bool {
+    code as u32 > 0x1ff
+}
+
+pub fn module(rc: u32) -> u32 {
+    rc & 0x1ff
+}
+
+pub fn description(rc: u32) -> u32 {
+    (rc >> 9) & 0x1fff
+}
+
+pub fn decode_result_kind(rc: u32) -> ErrorKind {
+    match (module(rc), description(rc)) {
+        (MODULE_KERNEL, 101) | (MODULE_KERNEL, 102) |
+        (MODULE_KERNEL, 114) | (MODULE_KERNEL, 120) => ErrorKind::InvalidInput,
+        (MODULE_KERNEL, 117) => ErrorKind::TimedOut,
+        (MODULE_KERNEL, 118) => ErrorKind::Interrupted,
+        (MODULE_KERNEL, 121) => ErrorKind::NotFound,
+        (MODULE_KERNEL, 123) => ErrorKind::ConnectionAborted,
+        (MODULE_KERNEL, 131) => ErrorKind::ConnectionRefused,
+
+        (MODULE_FS, 1) | (MODULE_FS, 1001) | (MODULE_FS, 1002) => ErrorKind::NotFound,
+        (MODULE_FS, 2) | (MODULE_FS, 60) => ErrorKind::AlreadyExists,
+        (MODULE_FS, 6001..=6199) => ErrorKind::InvalidInput,
+        (MODULE_FS, 6400..=6449) => ErrorKind::PermissionDenied,
+
+        (MODULE_HIPC, 301) => ErrorKind::ConnectionAborted,
+
+        (MODULE_SM, 4) => ErrorKind::AlreadyExists,
+        (MODULE_SM, 6) => ErrorKind::InvalidInput,
+        (MODULE_SM, 7) => ErrorKind::NotFound,
+        (MODULE_SM, 8) => ErrorKind::PermissionDenied,
+
+        (MODULE_LIBNX, 7) => ErrorKind::AlreadyExists,
+        (MODULE_LIBNX, 9) => ErrorKind::NotFound,
+        (MODULE_LIBNX, 11) => ErrorKind::InvalidInput,
+        (MODULE_LIBNX, 28) | (MODULE_LIBNX, 29) => ErrorKind::BrokenPipe,
+
+        (MODULE_LIBNX_NVIDIA, 5) => ErrorKind::InvalidInput,
+        (MODULE_LIBNX_NVIDIA, 6) => ErrorKind::TimedOut,
+
+        (MODULE_LIBNX_BINDER, 4) => ErrorKind::InvalidInput,
+        (MODULE_LIBNX_BINDER, 6) => ErrorKind::NotFound,
+        (MODULE_LIBNX_BINDER, 7) => ErrorKind::PermissionDenied,
+        (MODULE_LIBNX_BINDER, 9) => ErrorKind::AlreadyExists,
+        (MODULE_LIBNX_BINDER, 14) => ErrorKind::WouldBlock,
+        (MODULE_LIBNX_BINDER, 15) => ErrorKind::TimedOut,
+
+        _ => ErrorKind::Other,
+    }
+}
+
+/// Describes a result using the `2XXX-YYYY` code the error applet shows.
+pub fn result_string(rc: u32) -> String {
+    format!("result code {:04}-{:04} ({:#x})", 2000 + module(rc), description(rc), rc)
+}
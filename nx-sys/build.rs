@@ -1,144 +1,539 @@
-extern crate bindgen;
+extern crate cc;
 extern crate cfg_if;
-use bindgen::callbacks::{ EnumVariantCustomBehavior, EnumVariantValue, IntKind, MacroParsingBehavior, ParseCallbacks };
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
 use cfg_if::cfg_if;
 
-#[derive(Debug)]
-struct CustomCallbacks;
+#[path = "build/inline.rs"]
+mod inline;
+use inline::{ collect_headers, find_inline_functions, inline_shim, InlineFunction };
 
-impl ParseCallbacks for CustomCallbacks {
-    fn will_parse_macro(&self, _name: &str) -> MacroParsingBehavior {
-        MacroParsingBehavior::Default
+// Build modes:
+//
+// * default: copy the checked-in bindings from `bindings/<libnx version>/` into OUT_DIR. No devkitPro
+//   or clang install is needed. The version is taken from $NX_SYS_LIBNX_VERSION, else the installed
+//   libnx if bindings exist for it, else the newest version checked in.
+// * `regenerate` feature: run bindgen against the installed libnx, as this crate always used to. The
+//   result is compared against the checked-in bindings for that libnx version, and written over them
+//   when $NX_SYS_UPDATE_BINDINGS is set.
+//
+// Drift between the installed libnx and the bindings used is reported as a cargo warning, or fails
+// the build when $NX_SYS_CHECK_BINDINGS is set. See bindings/README.md.
+
+fn warn(message: &str) {
+    println!("cargo:warning={}", message);
+}
+
+/// Finds the devkitPro root from $DEVKITPRO.
+pub fn get_devkitpro() -> Option<PathBuf> {
+    let var = env::var("DEVKITPRO").ok()?;
+    let dkp = PathBuf::from(&var);
+    if dkp.is_dir() {
+        return Some(dkp);
+    }
+    if cfg!(windows) {
+        // The Windows installer sets DEVKITPRO to an MSYS path like /opt/devkitpro, which native
+        // programs can't open, so look for devkitA64's bin directory in PATH instead.
+        let path = env::var("PATH").unwrap_or_default();
+        let dummy_path = format!("{}devkitA64{}bin", std::path::MAIN_SEPARATOR, std::path::MAIN_SEPARATOR);
+        for var in path.split(';') {
+            if var.ends_with(&dummy_path) {
+                return Some(PathBuf::from(&var[0..var.len() - dummy_path.len()]));
+            }
+        }
     }
+    warn(&format!("DEVKITPRO is set to {}, which is not a directory", var));
+    None
+}
+
+/// The parts of a devkitPro install needed to parse and compile against libnx's headers.
+pub struct DevkitPro {
+    pub gcc: PathBuf,
+    pub ar: PathBuf,
+    pub libnx_include: PathBuf,
+    pub portlibs_include: PathBuf,
+    pub newlib_include: PathBuf,
+    pub gcc_include: PathBuf,
+}
 
-    fn int_macro(&self, _name: &str, _value: i64) -> Option<IntKind> {
-        if _name.starts_with("POLL") && _value < i16::max_value() as i64 && _value > i16::min_value() as i64 {
-            Some(IntKind::I16)
+impl DevkitPro {
+    /// Locates everything under `dkp`, returning a description of each piece that is missing.
+    pub fn find(dkp: &Path) -> Result<DevkitPro, Vec<String>> {
+        let mut missing = Vec::new();
+        let libnx_include = find_libnx_include(dkp);
+        if libnx_include.is_none() {
+            missing.push(format!("libnx headers (no switch.h in {}); install the switch-dev package or set LIBNX", libnx_root(dkp).join("include").display()));
         }
-        else if _name.starts_with("DT_") && _value > 0 && _value < u8::max_value() as i64 {
-            Some(IntKind::U8)
+
+        let devkita64 = devkita64_root(dkp);
+        let newlib_include = devkita64.join("aarch64-none-elf").join("include");
+        if !newlib_include.is_dir() {
+            missing.push(format!("devkitA64's newlib headers ({} does not exist); install devkitA64 or set DEVKITA64", newlib_include.display()));
         }
-        else if _name.starts_with("S_IF") && _value > 0 && _value < u32::max_value() as i64 {
-            Some(IntKind::U32)
+        let bin = devkita64.join("bin");
+        let gcc = bin.join(format!("aarch64-none-elf-gcc{}", env::consts::EXE_SUFFIX));
+        if !gcc.is_file() {
+            missing.push(format!("devkitA64's compiler ({} does not exist); install devkitA64 or set DEVKITA64", gcc.display()));
         }
-        else if _value < i32::max_value() as i64 && _value > i32::min_value() as i64 {
-            Some(IntKind::I32)
+        let gcc_include = find_gcc_include(&devkita64);
+        if gcc_include.is_none() {
+            missing.push(format!("devkitA64's GCC headers (no version with an include directory in {})", devkita64.join("lib").join("gcc").join("aarch64-none-elf").display()));
         }
-        else {
-            None
+
+        match (libnx_include, gcc_include) {
+            (Some(libnx_include), Some(gcc_include)) if missing.is_empty() => Ok(DevkitPro {
+                gcc,
+                ar: bin.join(format!("aarch64-none-elf-ar{}", env::consts::EXE_SUFFIX)),
+                libnx_include,
+                // portlibs is optional, clang ignores include directories that don't exist
+                portlibs_include: dkp.join("portlibs").join("switch").join("include"),
+                newlib_include,
+                gcc_include,
+            }),
+            _ => Err(missing)
         }
     }
 
-    fn enum_variant_behavior(&self, _enum_name: Option<&str>, _original_variant_name: &str, _variant_value: EnumVariantValue,) -> Option<EnumVariantCustomBehavior> {
-        None
+    pub fn include_dirs(&self) -> Vec<&Path> {
+        vec![self.libnx_include.as_path(), self.portlibs_include.as_path(), self.newlib_include.as_path(), self.gcc_include.as_path()]
     }
 
-    fn enum_variant_name(&self, _enum_name: Option<&str>, _original_variant_name: &str, _variant_value: EnumVariantValue,) -> Option<String> {
-        None
+    /// A C build using devkitA64's GCC with the flags libnx itself is built with.
+    pub fn c_build(&self) -> cc::Build {
+        let mut build = cc::Build::new();
+        build.compiler(&self.gcc)
+            .archiver(&self.ar)
+            .include(&self.libnx_include)
+            .include(&self.portlibs_include)
+            .define("__SWITCH__", None)
+            .flag("-march=armv8-a+crc+crypto")
+            .flag("-mtune=cortex-a57")
+            .flag("-mtp=soft")
+            .flag("-fPIE");
+        build
     }
 }
 
-pub fn get_devkitpro() -> Option<String> {
-    match std::env::var("DEVKITPRO") {
-        Ok(_var) => {
-            match cfg!(windows) {
-                true => {
-                    let path = std::env::var("PATH").unwrap();
-                    let dummy_path = format!("{}devkitA64{}bin", std::path::MAIN_SEPARATOR, std::path::MAIN_SEPARATOR).to_owned();
-                    let pathvars : Vec<&str> = path.split(';').collect();
-                    for var in &pathvars {
-                        if var.ends_with(&dummy_path) {
-                            let dkp = &var[0..var.len() - dummy_path.len()];
-                            return Some(dkp.to_string());
-                        }
-                    }
-                    None
-                },
-                false => Some(String::from("/opt/devkitpro"))
+/// Finds devkitPro for `purpose`, failing the build with a warning for each missing piece if it
+/// can't.
+pub fn require_devkitpro(purpose: &str) -> DevkitPro {
+    let root = match get_devkitpro() {
+        Some(root) => root,
+        None => {
+            warn(&format!("DEVKITPRO must point to a devkitPro install to build {}", purpose));
+            panic!("No devkitPro install found; set DEVKITPRO");
+        }
+    };
+    match DevkitPro::find(&root) {
+        Ok(dkp) => dkp,
+        Err(missing) => {
+            for what in &missing {
+                warn(&format!("devkitPro at {} is missing {}", root.display(), what));
             }
+            panic!("The devkitPro install at {} is incomplete, see the warnings above", root.display());
+        }
+    }
+}
 
-        },
-        _ => None
+fn libnx_root(dkp: &Path) -> PathBuf {
+    match env::var_os("LIBNX") {
+        Some(libnx) => PathBuf::from(libnx),
+        None => dkp.join("libnx")
     }
 }
 
-pub fn regen_bindings(input: &str, output: &str, whitelist: Option<Vec<String>>) -> Result<bindgen::Bindings, std::io::Error> {
+fn devkita64_root(dkp: &Path) -> PathBuf {
+    match env::var_os("DEVKITA64") {
+        Some(devkita64) => PathBuf::from(devkita64),
+        None => dkp.join("devkitA64")
+    }
+}
 
-    let out_path = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
-    let out_p = out_path.join(output);
-    let gen_path = out_p.to_str().unwrap();
+pub fn find_libnx_include(dkp: &Path) -> Option<PathBuf> {
+    let include = libnx_root(dkp).join("include");
+    if include.join("switch.h").is_file() { Some(include) } else { None }
+}
 
-    let in_path = std::path::PathBuf::from("bindgen");
-    let in_p = in_path.join(input);
-    let header_wrapper = in_p.to_str().unwrap();
+/// Picks the newest GCC version installed for aarch64-none-elf.
+pub fn find_gcc_include(devkita64: &Path) -> Option<PathBuf> {
+    let gcc = devkita64.join("lib").join("gcc").join("aarch64-none-elf");
+    let mut versions: Vec<String> = fs::read_dir(&gcc).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("include").is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    versions.sort_by_key(|version| parse_version(version));
+    versions.pop().map(|version| gcc.join(version).join("include"))
+}
 
-    // we don't care if deletion succeeds, as long as the file is gone
-    let _ = std::fs::remove_file(gen_path);
-    assert!(!std::path::Path::new(gen_path).exists());
+/// Reads libnx's version from the `switch/libversion.h` its Makefile generates.
+pub fn installed_libnx_version(libnx_include: &Path) -> Option<String> {
+    let path = libnx_include.join("switch").join("libversion.h");
+    println!("cargo:rerun-if-changed={}", path.display());
+    let header = fs::read_to_string(path).ok()?;
+    let component = |name: &str| -> Option<u32> {
+        header.lines().filter_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("#define"), Some(define), Some(value)) if define == name => value.parse().ok(),
+                _ => None
+            }
+        }).next()
+    };
+    Some(format!("{}.{}.{}", component("LIBNX_MAJOR")?, component("LIBNX_MINOR")?, component("LIBNX_PATCH")?))
+}
+
+fn parse_version(version: &str) -> Vec<u32> {
+    version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
 
-    let dkp = match get_devkitpro() {
-        Some(path) => path,
-        _ => return Err(std::io::Error::new(std::io::ErrorKind::Other, "Oof"))
+fn bindings_dir() -> PathBuf {
+    PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("bindings")
+}
+
+fn out_dir() -> PathBuf {
+    PathBuf::from(env::var("OUT_DIR").unwrap())
+}
+
+/// The libnx versions there are checked-in bindings for, oldest first.
+pub fn pregenerated_versions() -> Vec<String> {
+    let mut versions: Vec<String> = match fs::read_dir(bindings_dir()) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join("libnx.rs").is_file())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new()
     };
+    versions.sort_by_key(|version| parse_version(version));
+    versions
+}
+
+fn check_bindings() -> bool {
+    env::var_os("NX_SYS_CHECK_BINDINGS").is_some()
+}
+
+/// Reports bindings that don't match the installed libnx: a warning normally, a build failure with
+/// $NX_SYS_CHECK_BINDINGS set.
+pub fn report_drift(message: &str) {
+    if check_bindings() {
+        panic!("{}", message);
+    }
+    warn(message);
+}
+
+/// Copies the checked-in bindings for `names` into OUT_DIR, returning the libnx version they're for.
+pub fn use_pregenerated(installed: Option<&str>, names: &[&str]) -> String {
+    let versions = pregenerated_versions();
+    let version = match env::var("NX_SYS_LIBNX_VERSION") {
+        Ok(version) => version,
+        Err(_) => match installed {
+            Some(installed) if versions.iter().any(|version| version == installed) => installed.to_string(),
+            _ => match versions.last() {
+                Some(version) => version.clone(),
+                None => panic!("nx-sys has no pre-generated bindings in {}; build with the `regenerate` feature and a devkitPro install to generate them (see bindings/README.md)", bindings_dir().display())
+            }
+        }
+    };
+
+    if let Some(installed) = installed {
+        if installed != version {
+            report_drift(&format!("nx-sys is using bindings generated from libnx {}, but libnx {} is installed; regenerate them with the `regenerate` feature (see bindings/README.md)", version, installed));
+        }
+    }
+
+    let dir = bindings_dir().join(&version);
+    for name in names {
+        let src = dir.join(format!("{}.rs", name));
+        if !src.is_file() {
+            panic!("nx-sys has no pre-generated {} bindings for libnx {} (expected {})", name, version, src.display());
+        }
+        fs::copy(&src, out_dir().join(format!("{}.rs", name))).expect(&format!("Error copying {}'s bindings!", name));
+    }
+    version
+}
+
+// The range of libnx_* flags declared to rustc's check-cfg, covering every libnx release so far
+// with room to spare.
+const MAX_LIBNX_MAJOR: u32 = 9;
+const MAX_LIBNX_MINOR: u32 = 9;
+
+/// Exposes the libnx version the bindings come from.
+///
+/// The crate gets `LIBNX_VERSION` and friends as constants, plus a cfg flag for every release up
+/// to this one: `libnx_vX` for every major version X, and `libnx_vX_Y` for every minor version Y
+/// of those, where earlier majors get every minor up to MAX_LIBNX_MINOR. So both mean "this
+/// release or newer": with libnx 3.1, `libnx_v1` to `libnx_v3`, `libnx_v1_0` to `libnx_v1_9`,
+/// `libnx_v2_0` to `libnx_v2_9`, `libnx_v3_0` and `libnx_v3_1` are set, and `libnx_v3_1` stays
+/// set on 4.x.
+///
+/// Through the `links` metadata dependents get the same as DEP_NX_VERSION and DEP_NX_CFGS (a
+/// comma-separated list), so their build scripts can set the flags too.
+pub fn emit_version(version: &str) {
+    let parts = parse_version(version);
+    let component = |index: usize| parts.get(index).cloned().unwrap_or(0);
+    let (major, minor, patch) = (component(0), component(1), component(2));
+
+    let mut cfgs: Vec<String> = (1..major + 1).map(|major| format!("libnx_v{}", major)).collect();
+    for earlier in 1..major {
+        cfgs.extend((0..MAX_LIBNX_MINOR + 1).map(|minor| format!("libnx_v{}_{}", earlier, minor)));
+    }
+    cfgs.extend((0..minor + 1).map(|minor| format!("libnx_v{}_{}", major, minor)));
+    for cfg in &cfgs {
+        println!("cargo:rustc-cfg={}", cfg);
+    }
+    // Declare every flag a libnx release could produce, so gating on a newer libnx than this one
+    // isn't reported as an unexpected cfg. nx-rs declares the same range.
+    let mut known: Vec<String> = (1..MAX_LIBNX_MAJOR + 1).map(|major| format!("libnx_v{}", major)).collect();
+    for major in 1..MAX_LIBNX_MAJOR + 1 {
+        known.extend((0..MAX_LIBNX_MINOR + 1).map(|minor| format!("libnx_v{}_{}", major, minor)));
+    }
+    known.extend(cfgs.iter().cloned());
+    println!("cargo:rustc-check-cfg=cfg({})", known.join(", "));
+    println!("cargo:version={}", version);
+    println!("cargo:cfgs={}", cfgs.join(","));
+
+    let consts = format!("/// The version of libnx these bindings were generated from.\npub const LIBNX_VERSION: &str = \"{}\";\npub const LIBNX_MAJOR: u32 = {};\npub const LIBNX_MINOR: u32 = {};\npub const LIBNX_PATCH: u32 = {};\n", version, major, minor, patch);
+    fs::write(out_dir().join("version.rs"), consts).expect("Error writing the libnx version!");
+}
+
+/// Generates, builds and links the C file exporting the `static inline` functions reachable from
+/// bindgen/libnx.h.
+pub fn compile_inline_shim(dkp: &DevkitPro) {
+    let wrapper = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("bindgen").join("libnx.h");
+    let mut headers = Vec::new();
+    collect_headers(&wrapper, &[&dkp.libnx_include, &dkp.portlibs_include], &mut headers);
+
+    let mut funcs: Vec<InlineFunction> = Vec::new();
+    for header in &headers {
+        let src = fs::read_to_string(header).expect(&format!("Error reading {}!", header.display()));
+        for func in find_inline_functions(&src) {
+            if !funcs.iter().any(|other| other.name == func.name) {
+                funcs.push(func);
+            }
+        }
+    }
 
-    let mut builder = bindgen::Builder::default().trust_clang_mangling(false).use_core().rust_target(bindgen::RustTarget::Nightly).ctypes_prefix("ctypes").generate_inline_functions(true).parse_callbacks(Box::new(CustomCallbacks{})).header(header_wrapper)
-    .clang_arg(format!("-I{}", std::path::Path::new(&dkp).join("libnx").join("include").to_str().unwrap()))
-    .clang_arg(format!("-I{}", std::path::Path::new(&dkp).join("portlibs").join("switch").join("include").to_str().unwrap()))
-    .clang_arg(format!("-I{}", std::path::Path::new(&dkp).join("devkitA64").join("aarch64-none-elf").join("include").to_str().unwrap()))
-    .clang_arg(format!("-I{}", std::path::Path::new(&dkp).join("devkitA64").join("lib").join("gcc").join("aarch64-none-elf").join("8.3.0").join("include").to_str().unwrap()))
-    .blacklist_type("u8").blacklist_type("u16").blacklist_type("u32").blacklist_type("u64");
-    
-    if let Some(whitelist) = whitelist {
-        for func in whitelist {
-            builder = builder.whitelist_function(func);
-        }
-    }
-
-    builder.generate().map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Could not create file!")).and_then(|bnd| {
-        let mut file = OpenOptions::new().write(true).create(true).open(gen_path)?;
-        file.write_all(br#"
-        mod ctypes {
-            pub type c_void = core::ffi::c_void;
-            pub type c_char = u8;
-            pub type c_int = i32;
-            pub type c_long = i64;
-            pub type c_longlong = i64;
-            pub type c_schar = i8;
-            pub type c_short = i16;
-            pub type c_uchar = u8;
-            pub type c_uint = u32;
-            pub type c_ulong = u64;
-            pub type c_ulonglong = u64;
-            pub type c_ushort = u16;
-            pub type size_t = u64;
-            pub type ssize_t = i64;
-            pub type c_float = f32;
-            pub type c_double = f64;
-        }
-        "#)?;
-        bnd.write(Box::new(file)).map(|_| bnd)
-    })
-}
-
-pub fn process_bindgen(input: &str, output: &str, name: &str, whitelist: Option<Vec<String>>) {
-    regen_bindings(input, output, whitelist).expect(&format!("Error generating {}'s bindings!", name));
+    let shim = out_dir().join("inline.c");
+    fs::write(&shim, inline_shim(wrapper.to_str().unwrap(), &funcs)).expect("Error writing the inline function shim!");
+    dkp.c_build().file(&shim).compile("nx_sys_inline");
 }
 
 cfg_if! {
     if #[cfg(feature = "twili")] {
-        pub fn process_twili() {
-            process_bindgen("twili.h", "twili.rs", "twili", Some(vec!["twiliWriteNamedPipe".to_string(), "twiliCreateNamedOutputPipe".to_string(), "twiliCreateNamedOutputPipe".to_string(), "twiliInitialize".to_string(), "twiliExit".to_string()]));
+        /// Builds twili.c with devkitA64's GCC into a static library and links it.
+        pub fn compile_twili(dkp: &DevkitPro) {
+            dkp.c_build()
+                .file("twili/src/twili.c")
+                .include("twili/include")
+                .compile("twili");
+
+            println!("cargo:rerun-if-changed=twili/src/twili.c");
+            println!("cargo:rerun-if-changed=twili/include/twili.h");
         }
     }
     else {
-        pub fn process_twili() {
+        pub fn compile_twili(_dkp: &DevkitPro) {
         }
     }
 }
 
+cfg_if! {
+    if #[cfg(feature = "regenerate")] {
+        extern crate bindgen;
+        use bindgen::callbacks::{ EnumVariantCustomBehavior, EnumVariantValue, IntKind, MacroParsingBehavior, ParseCallbacks };
+
+        /// The functions twili.c exports, i.e. the ones to generate bindings for.
+        pub const TWILI_FUNCTIONS: &[&str] = &["twiliInitialize", "twiliExit", "twiliCreateNamedOutputPipe", "twiliWriteNamedPipe"];
+
+        const BINDINGS_HEADER: &str = "// Generated by nx-sys/build.rs with the `regenerate` feature. Do not edit by hand,\n// see nx-sys/bindings/README.md for how to update these.\n";
+
+        #[derive(Debug)]
+        struct CustomCallbacks;
+
+        impl ParseCallbacks for CustomCallbacks {
+            fn will_parse_macro(&self, _name: &str) -> MacroParsingBehavior {
+                MacroParsingBehavior::Default
+            }
+
+            fn int_macro(&self, _name: &str, _value: i64) -> Option<IntKind> {
+                if _name.starts_with("POLL") && _value < i16::max_value() as i64 && _value > i16::min_value() as i64 {
+                    Some(IntKind::I16)
+                }
+                else if _name.starts_with("DT_") && _value > 0 && _value < u8::max_value() as i64 {
+                    Some(IntKind::U8)
+                }
+                else if _name.starts_with("S_IF") && _value > 0 && _value < u32::max_value() as i64 {
+                    Some(IntKind::U32)
+                }
+                else if _value < i32::max_value() as i64 && _value > i32::min_value() as i64 {
+                    Some(IntKind::I32)
+                }
+                else {
+                    None
+                }
+            }
+
+            fn enum_variant_behavior(&self, _enum_name: Option<&str>, _original_variant_name: &str, _variant_value: EnumVariantValue,) -> Option<EnumVariantCustomBehavior> {
+                None
+            }
+
+            fn enum_variant_name(&self, _enum_name: Option<&str>, _original_variant_name: &str, _variant_value: EnumVariantValue,) -> Option<String> {
+                None
+            }
+        }
+
+        pub fn regen_bindings(dkp: &DevkitPro, input: &str, whitelist: Option<Vec<String>>) -> Result<String, std::io::Error> {
+            let in_path = std::path::PathBuf::from("bindgen");
+            let in_p = in_path.join(input);
+            let header_wrapper = in_p.to_str().unwrap();
+
+            let mut builder = bindgen::Builder::default().trust_clang_mangling(false).use_core().rust_target(bindgen::RustTarget::Nightly).ctypes_prefix("ctypes").generate_inline_functions(true).parse_callbacks(Box::new(CustomCallbacks{})).header(header_wrapper)
+            .blacklist_type("u8").blacklist_type("u16").blacklist_type("u32").blacklist_type("u64");
+
+            for dir in dkp.include_dirs() {
+                builder = builder.clang_arg(format!("-I{}", dir.to_str().unwrap()));
+            }
+
+            if let Some(whitelist) = whitelist {
+                for func in whitelist {
+                    builder = builder.whitelist_function(func);
+                }
+            }
+
+            let bnd = builder.generate().map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Could not create file!"))?;
+            let mut contents = String::from(BINDINGS_HEADER);
+            contents.push_str(r#"
+mod ctypes {
+    pub type c_void = core::ffi::c_void;
+    pub type c_char = u8;
+    pub type c_int = i32;
+    pub type c_long = i64;
+    pub type c_longlong = i64;
+    pub type c_schar = i8;
+    pub type c_short = i16;
+    pub type c_uchar = u8;
+    pub type c_uint = u32;
+    pub type c_ulong = u64;
+    pub type c_ulonglong = u64;
+    pub type c_ushort = u16;
+    pub type size_t = u64;
+    pub type ssize_t = i64;
+    pub type c_float = f32;
+    pub type c_double = f64;
+}
+"#);
+            contents.push_str(&bnd.to_string());
+            Ok(contents)
+        }
+
+        /// Runs bindgen for `name` (libnx or twili) into OUT_DIR, returning the bindings.
+        pub fn generate_bindings(dkp: &DevkitPro, name: &str) -> String {
+            let whitelist = if name == "twili" { Some(TWILI_FUNCTIONS.iter().map(|func| func.to_string()).collect()) } else { None };
+            let contents = regen_bindings(dkp, &format!("{}.h", name), whitelist).expect(&format!("Error generating {}'s bindings!", name));
+            fs::write(out_dir().join(format!("{}.rs", name)), &contents).expect(&format!("Error writing {}'s bindings!", name));
+            contents
+        }
+
+        /// Runs bindgen for `name` into OUT_DIR, then checks it against (or, with $NX_SYS_UPDATE_BINDINGS
+        /// set, writes it over) the checked-in copy for `version`.
+        pub fn process_bindgen(dkp: &DevkitPro, version: &str, name: &str) {
+            let contents = generate_bindings(dkp, name);
+
+            let checked_in = bindings_dir().join(version).join(format!("{}.rs", name));
+            if env::var_os("NX_SYS_UPDATE_BINDINGS").is_some() {
+                fs::create_dir_all(checked_in.parent().unwrap()).expect("Error creating the bindings directory!");
+                fs::write(&checked_in, &contents).expect(&format!("Error updating {}'s checked-in bindings!", name));
+                warn(&format!("Updated {}", checked_in.display()));
+            }
+            else {
+                match fs::read_to_string(&checked_in) {
+                    Ok(ref existing) if *existing == contents => {},
+                    Ok(_) => report_drift(&format!("{} differs from the bindings generated from the installed libnx {}; set NX_SYS_UPDATE_BINDINGS=1 to update it", checked_in.display(), version)),
+                    Err(_) => report_drift(&format!("there are no checked-in {} bindings for libnx {}; set NX_SYS_UPDATE_BINDINGS=1 to add them", name, version))
+                }
+            }
+        }
+
+        /// The libnx version bindgen runs against: $NX_SYS_LIBNX_VERSION, else the installed one.
+        fn libnx_version(dkp: &DevkitPro) -> String {
+            match env::var("NX_SYS_LIBNX_VERSION").ok().or_else(|| installed_libnx_version(&dkp.libnx_include)) {
+                Some(version) => version,
+                None => panic!("Could not detect the installed libnx version; set NX_SYS_LIBNX_VERSION to the version to file the bindings under")
+            }
+        }
+
+        /// The `regenerate` build: bindgen always runs, and its output is compared with the checked-in
+        /// bindings.
+        fn regenerate() {
+            let dkp = require_devkitpro("with the `regenerate` feature (or build without it to use the pre-generated bindings)");
+            let version = libnx_version(&dkp);
+            for name in binding_names() {
+                process_bindgen(&dkp, &version, name);
+            }
+            emit_version(&version);
+            compile_inline_shim(&dkp);
+            if cfg!(feature = "twili") {
+                compile_twili(&dkp);
+            }
+        }
+    }
+    else {
+        fn regenerate() {
+            unreachable!("nx-sys was built without the `regenerate` feature");
+        }
+    }
+}
+
+fn binding_names() -> &'static [&'static str] {
+    if cfg!(feature = "twili") { &["libnx", "twili"] } else { &["libnx"] }
+}
+
+/// The default build: the checked-in bindings are used.
+fn use_checked_in() {
+    // devkitPro is optional here. Without it the checked-in bindings can't be checked
+    // against the installed libnx, and nothing written in C can be built.
+    let root = get_devkitpro();
+    let installed = root.as_ref().and_then(|root| match find_libnx_include(root) {
+        Some(include) => installed_libnx_version(&include),
+        None => {
+            warn(&format!("No libnx headers found in {}, skipping the bindings version check", libnx_root(root).join("include").display()));
+            None
+        }
+    });
+    let version = use_pregenerated(installed.as_ref().map(|version| version.as_str()), binding_names());
+    emit_version(&version);
+    if cfg!(feature = "twili") {
+        compile_twili(&require_devkitpro("the `twili` feature"));
+    }
+
+    // Only matters when linking for the Switch, e.g. not for a host `cargo doc`
+    let for_switch = env::var("TARGET").map(|target| target.starts_with("aarch64")).unwrap_or(false);
+    match root.as_ref().map(|root| (root, DevkitPro::find(root))) {
+        Some((_, Ok(dkp))) => compile_inline_shim(&dkp),
+        _ if !for_switch => {},
+        Some((root, Err(missing))) => {
+            for what in &missing {
+                warn(&format!("devkitPro at {} is missing {}", root.display(), what));
+            }
+            warn("libnx's static inline functions can't be built, so calls to them won't link");
+        },
+        None => warn("DEVKITPRO is not set, so libnx's static inline functions can't be built and calls to them won't link")
+    }
+
+    println!("cargo:rerun-if-changed={}", bindings_dir().display());
+    println!("cargo:rerun-if-env-changed=NX_SYS_LIBNX_VERSION");
+    println!("cargo:rerun-if-env-changed=NX_SYS_CHECK_BINDINGS");
+    println!("cargo:rerun-if-env-changed=DEVKITPRO");
+    println!("cargo:rerun-if-env-changed=DEVKITA64");
+    println!("cargo:rerun-if-env-changed=LIBNX");
+}
+
 pub fn main() {
-    process_bindgen("libnx.h", "libnx.rs", "libnx", None);
-    process_twili();
-}
\ No newline at end of file
+    if cfg!(feature = "regenerate") {
+        regenerate();
+    }
+    else {
+        use_checked_in();
+    }
+}
@@ -0,0 +1,400 @@
+//! Finding the `static inline` functions in libnx's headers, and generating the C file that exports
+//! them.
+//!
+//! Nothing here depends on the rest of build.rs, so the tests run on the host with
+//! `rustc --test build/inline.rs && ./inline`.
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+/// A `static inline` function defined in libnx's headers.
+///
+/// bindgen declares these like any other function, but libnx.a has no symbol for them, so calling
+/// one from Rust fails to link. `inline_shim` exports each of them from a C file instead.
+pub struct InlineFunction {
+    pub name: String,
+    pub ret: String,
+    pub params: String,
+    pub args: Vec<String>,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn has_word(text: &str, word: &str) -> bool {
+    text.split(|c: char| !is_ident_char(c)).any(|token| token == word)
+}
+
+/// Removes comments, and preprocessor lines along with any `#ifdef __cplusplus` blocks, leaving the
+/// declarations a C compiler sees.
+fn strip_preprocessor(src: &str) -> String {
+    let mut code = String::new();
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'/') => {
+                while let Some(&next) = chars.peek() {
+                    if next == '\n' { break; }
+                    chars.next();
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                while let Some(next) = chars.next() {
+                    if prev == '*' && next == '/' { break; }
+                    prev = next;
+                }
+                code.push(' ');
+            },
+            '\\' if chars.peek() == Some(&'\n') => {
+                chars.next();
+            },
+            _ => code.push(c)
+        }
+    }
+
+    let mut out = String::new();
+    // Nesting depth of conditionals inside a skipped `#ifdef __cplusplus` block, if in one
+    let mut skipping: Option<u32> = None;
+    for line in code.lines() {
+        let line = line.trim();
+        if !line.starts_with('#') {
+            if skipping.is_none() {
+                out.push_str(line);
+                out.push('\n');
+            }
+            continue;
+        }
+        let directive: String = line[1..].trim_start().chars().take_while(|&c| is_ident_char(c)).collect();
+        skipping = match (skipping, directive.as_str()) {
+            (None, "ifdef") | (None, "if") if has_word(line, "__cplusplus") => Some(0),
+            (Some(depth), "if") | (Some(depth), "ifdef") | (Some(depth), "ifndef") => Some(depth + 1),
+            (Some(0), "else") | (Some(0), "endif") => None,
+            (Some(depth), "endif") => Some(depth - 1),
+            (skipping, _) => skipping
+        };
+    }
+    out
+}
+
+/// Splits `params` on the commas that aren't nested in parentheses or brackets.
+fn split_params(params: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in params.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&params[start..i]);
+                start = i + 1;
+            },
+            _ => {}
+        }
+    }
+    parts.push(&params[start..]);
+    parts
+}
+
+/// The C keywords that can end a parameter's type.
+const TYPE_WORDS: &[&str] = &["void", "char", "short", "int", "long", "float", "double", "signed", "unsigned", "_Bool", "const", "volatile", "restrict"];
+
+fn param_name(param: &str) -> Option<String> {
+    // Function pointers, e.g. `void (*callback)(void*)`
+    let declarator = match param.find("(*") {
+        Some(pos) => &param[pos + 2..],
+        None => param.split('[').next().unwrap()
+    };
+    let name: String = if param.contains("(*") {
+        declarator.trim_start().chars().take_while(|&c| is_ident_char(c)).collect()
+    }
+    else {
+        let trimmed = declarator.trim();
+        let start = trimmed.rfind(|c: char| !is_ident_char(c)).map(|pos| pos + 1).unwrap_or(0);
+        // An unnamed parameter, e.g. `int` or `unsigned long`, is just its type
+        if start == 0 || TYPE_WORDS.contains(&&trimmed[start..]) {
+            return None;
+        }
+        trimmed[start..].to_string()
+    };
+    if name.is_empty() || name.chars().next().unwrap().is_ascii_digit() { None } else { Some(name) }
+}
+
+/// Parses the part of a function definition before its body, if it's a `static inline` one.
+pub fn parse_inline_function(prefix: &str) -> Option<InlineFunction> {
+    let mut prefix = prefix.trim().to_string();
+    if !(has_word(&prefix, "inline") || has_word(&prefix, "__inline__") || has_word(&prefix, "NX_INLINE") || has_word(&prefix, "NX_CONSTEXPR")) {
+        return None;
+    }
+    while let Some(start) = prefix.find("__attribute__") {
+        let open = start + prefix[start..].find('(')?;
+        let mut depth = 0;
+        let mut end = open;
+        for (i, c) in prefix[open..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = open + i + 1;
+                        break;
+                    }
+                },
+                _ => {}
+            }
+        }
+        prefix.replace_range(start..end, " ");
+    }
+    let prefix = prefix.trim();
+    if !prefix.ends_with(')') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut open = None;
+    for (i, c) in prefix.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => {
+                depth -= 1;
+                if depth == 0 {
+                    open = Some(i);
+                    break;
+                }
+            },
+            _ => {}
+        }
+    }
+    let open = open?;
+    let params = prefix[open + 1..prefix.len() - 1].trim();
+    let head = prefix[..open].trim_end();
+    let name_start = head.rfind(|c: char| !is_ident_char(c)).map(|pos| pos + 1).unwrap_or(0);
+    let name = &head[name_start..];
+    let ret: Vec<&str> = head[..name_start].split_whitespace()
+        .filter(|word| !["static", "inline", "__inline__", "__inline", "NX_INLINE", "NX_CONSTEXPR", "extern"].contains(word))
+        .collect();
+    if name.is_empty() || ret.is_empty() || has_word(head, "typedef") {
+        return None;
+    }
+
+    let args = if params.is_empty() || params == "void" {
+        Vec::new()
+    }
+    else {
+        let mut args = Vec::new();
+        for param in split_params(params) {
+            // Variadic functions can't forward their arguments, so they're left out
+            if param.trim() == "..." {
+                return None;
+            }
+            args.push(param_name(param)?);
+        }
+        args
+    };
+
+    Some(InlineFunction {
+        name: name.to_string(),
+        ret: ret.join(" "),
+        params: if params.is_empty() { String::from("void") } else { params.split_whitespace().collect::<Vec<_>>().join(" ") },
+        args,
+    })
+}
+
+/// Finds the `static inline` functions defined in a header.
+pub fn find_inline_functions(src: &str) -> Vec<InlineFunction> {
+    let code = strip_preprocessor(src);
+    let mut funcs = Vec::new();
+    let mut prefix = String::new();
+    let mut chars = code.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => prefix.clear(),
+            '{' => {
+                // Skip the body, minding braces in character and string literals
+                let mut depth = 1;
+                let mut quote = None;
+                let mut escaped = false;
+                while depth > 0 {
+                    let c = match chars.next() {
+                        Some(c) => c,
+                        None => break
+                    };
+                    match quote {
+                        Some(q) => {
+                            if escaped { escaped = false; }
+                            else if c == '\\' { escaped = true; }
+                            else if c == q { quote = None; }
+                        },
+                        None => match c {
+                            '\'' | '"' => quote = Some(c),
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                }
+                if let Some(func) = parse_inline_function(&prefix) {
+                    funcs.push(func);
+                }
+                prefix.clear();
+            },
+            _ => prefix.push(c)
+        }
+    }
+    funcs
+}
+
+/// Follows the `#include`s of `header` that resolve to files under `include_dirs`, i.e. skipping
+/// newlib's and GCC's headers.
+pub fn collect_headers(header: &Path, include_dirs: &[&Path], headers: &mut Vec<PathBuf>) {
+    if headers.iter().any(|seen| seen == header) {
+        return;
+    }
+    headers.push(header.to_path_buf());
+    let src = match fs::read_to_string(header) {
+        Ok(src) => src,
+        Err(_) => return
+    };
+    for line in src.lines() {
+        let line = line.trim();
+        if !line.starts_with('#') || !line[1..].trim_start().starts_with("include") {
+            continue;
+        }
+        let (name, local) = match (line.find('<'), line.find('>'), line.find('"'), line.rfind('"')) {
+            (Some(start), Some(end), _, _) if start < end => (&line[start + 1..end], false),
+            (_, _, Some(start), Some(end)) if start < end => (&line[start + 1..end], true),
+            _ => continue
+        };
+        let local_dir = header.parent().unwrap();
+        let candidates = if local { Some(local_dir) } else { None }.into_iter().chain(include_dirs.iter().cloned());
+        for dir in candidates {
+            let path = dir.join(name);
+            if path.is_file() {
+                collect_headers(&path, include_dirs, headers);
+                break;
+            }
+        }
+    }
+}
+
+const INLINE_PREFIX: &str = "__nx_sys_inline_";
+
+/// Generates a C file exporting `funcs` under their own names.
+///
+/// Each function is renamed with a macro while `header` is included, so the header's static copy
+/// doesn't clash with the exported wrapper that calls it.
+pub fn inline_shim(header: &str, funcs: &[InlineFunction]) -> String {
+    let mut shim = String::from("// Generated by nx-sys/build.rs, exports libnx's static inline functions so bindings to them link.\n\n");
+    for func in funcs {
+        shim.push_str(&format!("#define {0} {1}{0}\n", func.name, INLINE_PREFIX));
+    }
+    shim.push_str(&format!("\n#include \"{}\"\n\n", header));
+    for func in funcs {
+        shim.push_str(&format!("#undef {}\n", func.name));
+    }
+    for func in funcs {
+        let call = format!("{}{}({})", INLINE_PREFIX, func.name, func.args.join(", "));
+        let body = if func.ret == "void" { format!("{};", call) } else { format!("return {};", call) };
+        shim.push_str(&format!("\n{} {}({}) {{\n    {}\n}}\n", func.ret, func.name, func.params, body));
+    }
+    shim
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ find_inline_functions, inline_shim, InlineFunction };
+
+    fn find(src: &str) -> Vec<(String, String, String, Vec<String>)> {
+        find_inline_functions(src).into_iter().map(|func| (func.name, func.ret, func.params, func.args)).collect()
+    }
+
+    fn func(name: &str, ret: &str, params: &str, args: &[&str]) -> (String, String, String, Vec<String>) {
+        (name.to_string(), ret.to_string(), params.to_string(), args.iter().map(|arg| arg.to_string()).collect())
+    }
+
+    #[test]
+    fn finds_only_inline_definitions() {
+        let src = "Result smInitialize(void);\n\
+                   static inline u64 armGetSystemTick(void);\n\
+                   u32 notInline(u32 x) { return x; }\n\
+                   static inline u64 armGetSystemTickFreq(void) { return 19200000; }\n\
+                   static inline u32 noParams() { return 0; }\n";
+        assert_eq!(find(src), vec![
+            func("armGetSystemTickFreq", "u64", "void", &[]),
+            func("noParams", "u32", "void", &[]),
+        ]);
+    }
+
+    #[test]
+    fn joins_multi_line_signatures() {
+        let src = "static inline Result serviceDispatch(Service* s,\n        u32 request_id,\n        const void* in_data)\n{\n    return 0;\n}\n";
+        assert_eq!(find(src), vec![func("serviceDispatch", "Result", "Service* s, u32 request_id, const void* in_data", &["s", "request_id", "in_data"])]);
+    }
+
+    #[test]
+    fn strips_attributes_and_libnx_macros() {
+        let src = "__attribute__((always_inline)) static inline void* armGetTls(void) { return 0; }\n\
+                   static inline __attribute__((warn_unused_result, deprecated(\"use y()\"))) int x(int a) { return a; }\n\
+                   NX_CONSTEXPR u64 armNsToTicks(u64 ns) { return ns; }\n\
+                   NX_INLINE const char *name(void) { return \"}\"; }\n";
+        assert_eq!(find(src), vec![
+            func("armGetTls", "void*", "void", &[]),
+            func("x", "int", "int a", &["a"]),
+            func("armNsToTicks", "u64", "u64 ns", &["ns"]),
+            func("name", "const char *", "void", &[]),
+        ]);
+    }
+
+    #[test]
+    fn names_function_pointer_and_array_parameters() {
+        let src = "static inline void threadRun(void (*entry)(void*, int), void *arg, u8 key[0x10]) { entry(arg, key[0]); }\n";
+        assert_eq!(find(src), vec![func("threadRun", "void", "void (*entry)(void*, int), void *arg, u8 key[0x10]", &["entry", "arg", "key"])]);
+    }
+
+    #[test]
+    fn skips_unforwardable_functions() {
+        let src = "static inline int logf(const char* fmt, ...) { return 0; }\n\
+                   static inline int unnamed(int) { return 0; }\n\
+                   typedef struct { int x; } Point;\n\
+                   static inline int kept(Point p) { if (p.x) { return '{'; } return 0; }\n";
+        assert_eq!(find(src), vec![func("kept", "int", "Point p", &["p"])]);
+    }
+
+    #[test]
+    fn reads_through_conditionals_and_comments() {
+        let src = "#pragma once\n\
+                   #ifdef __cplusplus\nextern \"C\" {\n#endif\n\
+                   #if defined(__SWITCH__)\n\
+                   /* static inline int commented(void) { return 0; } */\n\
+                   static inline int onSwitch(void) { return 1; } // {\n\
+                   #else\n\
+                   static inline int elsewhere(void) { return 2; }\n\
+                   #endif\n\
+                   #ifdef __cplusplus\n\
+                   }\ntemplate <typename T> inline T cppOnly(T t) { return t; }\n\
+                   #endif\n\
+                   #define MACRO(x) \\\n    static inline int fromMacro(void) { return x; }\n";
+        assert_eq!(find(src), vec![
+            func("onSwitch", "int", "void", &[]),
+            func("elsewhere", "int", "void", &[]),
+        ]);
+    }
+
+    #[test]
+    fn exports_each_function_under_its_own_name() {
+        let funcs = vec![
+            InlineFunction { name: "get".to_string(), ret: "u64".to_string(), params: "u32 id".to_string(), args: vec!["id".to_string()] },
+            InlineFunction { name: "set".to_string(), ret: "void".to_string(), params: "void".to_string(), args: vec![] },
+        ];
+        assert_eq!(inline_shim("libnx.h", &funcs),
+            "// Generated by nx-sys/build.rs, exports libnx's static inline functions so bindings to them link.\n\n\
+             #define get __nx_sys_inline_get\n#define set __nx_sys_inline_set\n\n\
+             #include \"libnx.h\"\n\n\
+             #undef get\n#undef set\n\
+             \nu64 get(u32 id) {\n    return __nx_sys_inline_get(id);\n}\n\
+             \nvoid set(void) {\n    __nx_sys_inline_set();\n}\n");
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(warnings)]
 #![no_std]
 
+include!(concat!(env!("OUT_DIR"),"/version.rs"));
+
 include!(concat!(env!("OUT_DIR"),"/libnx.rs"));
 
 #[cfg(feature = "twili")]
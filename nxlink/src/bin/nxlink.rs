@@ -0,0 +1,86 @@
+//! nxlink [-a <address>] [-r <retries>] [-p <path>] [-s] <nro_file> [args...]
+//!
+//! Takes the same arguments as devkitPro's tool of the same name. Arguments
+//! after the NRO are passed to the app.
+
+extern crate nxlink;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::process;
+
+use nxlink::StdioServer;
+
+const USAGE: &str = "usage: nxlink [-a <address>] [-r <retries>] [-p <path>] [-s] <nro_file> [args...]";
+
+fn usage() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, USAGE)
+}
+
+fn run(args: &[String]) -> io::Result<()> {
+    let mut address = None;
+    let mut retries = 10;
+    let mut path = None;
+    let mut server = false;
+    let mut iter = args.iter();
+    let file = loop {
+        let arg = iter.next().ok_or_else(usage)?;
+        let (key, inline_value) = match arg.find('=') {
+            Some(eq) if arg.starts_with("--") => (&arg[..eq], Some(&arg[eq + 1..])),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = || inline_value.or_else(|| iter.next().map(|value| value.as_str())).ok_or_else(usage);
+        match key {
+            "-a" | "--address" => address = Some(value()?.parse::<IpAddr>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid address"))?),
+            "-r" | "--retries" => retries = value()?.parse()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid retry count"))?,
+            "-p" | "--path" => path = Some(value()?.to_owned()),
+            "-s" | "--server" => server = true,
+            _ if arg.starts_with('-') => return Err(usage()),
+            _ => break arg,
+        }
+    };
+    let mut app_args: Vec<String> = iter.cloned().collect();
+
+    let nro = fs::read(file)?;
+    let name = match path {
+        Some(path) => path,
+        None => Path::new(file).file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(usage)?
+            .to_owned(),
+    };
+    let address = match address {
+        Some(address) => address,
+        None => {
+            let address = nxlink::discover(retries)?;
+            println!("Found a device at {}", address);
+            address
+        }
+    };
+
+    // Listen before sending, so the app can't connect before we do.
+    let stdio = if server { Some(StdioServer::new()?) } else { None };
+    if server {
+        app_args.push(nxlink::STDIO_ARG.to_owned());
+    }
+    println!("Sending {}, {} bytes", name, nro.len());
+    nxlink::send_to(SocketAddr::new(address, nxlink::SERVER_PORT), &name, &nro, &app_args)?;
+    if let Some(stdio) = stdio {
+        println!("Waiting for the app's output");
+        stdio.serve(&mut io::stdout())?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(err) = run(&args) {
+        eprintln!("nxlink: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,200 @@
+//! The host side of nxlink, the protocol the homebrew menu's netloader
+//! speaks, replacing devkitPro's `nxlink` tool.
+//!
+//! 1. The host broadcasts `nxboot` over UDP to `SERVER_PORT`, and the
+//!    device answers `bootnx` to the host's `CLIENT_PORT`.
+//! 2. The host connects to the device's `SERVER_PORT` over TCP and sends the
+//!    NRO's name and size, then its contents as a single zlib stream split
+//!    into length-prefixed frames of at most `CHUNK_SIZE` bytes. The device
+//!    acknowledges the header and the contents with a status code each.
+//! 3. The host sends the app's arguments and the device launches it.
+//! 4. If the app calls `nxlinkStdio`, it connects back to the host's
+//!    `CLIENT_PORT` over TCP and sends its stdout and stderr there.
+//!
+//! All integers are little-endian `i32`s.
+
+extern crate flate2;
+
+use std::io::{self, Read, Write};
+use std::iter;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use flate2::{Compress, Compression, FlushCompress, Status};
+
+/// The device listens for discovery pings and uploads on this port.
+pub const SERVER_PORT: u16 = 28280;
+/// The host gets discovery replies and the app's stdio on this port.
+pub const CLIENT_PORT: u16 = 28771;
+/// The largest compressed frame the device accepts.
+pub const CHUNK_SIZE: usize = 0x4000;
+pub const PING: &[u8] = b"nxboot";
+pub const PONG: &[u8] = b"bootnx";
+/// The argument nxlink adds to tell the app a stdio server is waiting.
+pub const STDIO_ARG: &str = "_NXLINK_";
+
+fn other(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+pub fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+pub fn write_i32<W: Write>(w: &mut W, value: i32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+/// A length as sent on the wire.
+fn wire_len(len: usize, what: &str) -> io::Result<i32> {
+    if len > i32::max_value() as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is too large", what)));
+    }
+    Ok(len as i32)
+}
+
+/// Pings `target` from `socket` up to `retries` times, waiting `timeout` for
+/// an answer each time, and returns the address of the device that
+/// answered.
+pub fn discover_with(socket: &UdpSocket, target: SocketAddr, retries: u32, timeout: Duration) -> io::Result<IpAddr> {
+    socket.set_read_timeout(Some(timeout))?;
+    let mut buf = [0; 16];
+    for _ in 0..retries {
+        socket.send_to(PING, target)?;
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) if &buf[..len] == PONG => return Ok(from.ip()),
+            Ok(_) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "no device answered; is the homebrew menu's netloader running?"))
+}
+
+/// Finds a device on the local network by broadcasting to it.
+pub fn discover(retries: u32) -> io::Result<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::new(0, 0, 0, 0), CLIENT_PORT))?;
+    socket.set_broadcast(true)?;
+    discover_with(&socket, SocketAddr::new(Ipv4Addr::new(255, 255, 255, 255).into(), SERVER_PORT),
+                  retries, Duration::from_millis(250))
+}
+
+fn check_response<R: Read>(r: &mut R) -> io::Result<()> {
+    match read_i32(r)? {
+        0 => Ok(()),
+        -1 => Err(other("the device failed to create the file".to_owned())),
+        -2 => Err(other("the device has insufficient space for the file".to_owned())),
+        -3 => Err(other("the device has insufficient memory for the file".to_owned())),
+        code => Err(other(format!("the device refused the file ({})", code))),
+    }
+}
+
+/// Sends `data` as a zlib stream in frames of at most `CHUNK_SIZE` bytes.
+fn send_compressed<W: Write>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    let mut compress = Compress::new(Compression::default(), true);
+    let mut frame = vec![0; CHUNK_SIZE];
+    let mut input = data;
+    loop {
+        let before_in = compress.total_in();
+        let before_out = compress.total_out();
+        let status = compress.compress(input, &mut frame, FlushCompress::Finish)
+            .map_err(|err| other(format!("compressing failed: {}", err)))?;
+        input = &input[(compress.total_in() - before_in) as usize..];
+        let len = (compress.total_out() - before_out) as usize;
+        if len != 0 {
+            write_i32(w, len as i32)?;
+            w.write_all(&frame[..len])?;
+        }
+        if status == Status::StreamEnd {
+            return Ok(());
+        }
+    }
+}
+
+/// The arguments as the device expects them: each one NUL-terminated, with
+/// the NRO's name first.
+fn args_buffer(name: &str, args: &[String]) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    for arg in iter::once(name).chain(args.iter().map(|arg| arg.as_str())) {
+        if arg.as_bytes().contains(&0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "arguments can't contain NUL"));
+        }
+        buf.extend_from_slice(arg.as_bytes());
+        buf.push(0);
+    }
+    Ok(buf)
+}
+
+/// Uploads an NRO over an open connection to the device and has it launched
+/// with `args`. The device saves it as `name`, relative to `sdmc:/switch/`
+/// unless it starts with `/`.
+pub fn send<S: Read + Write>(stream: &mut S, name: &str, nro: &[u8], args: &[String]) -> io::Result<()> {
+    if name.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the file name is empty"));
+    }
+    let args = args_buffer(name, args)?;
+    write_i32(stream, wire_len(name.len(), "the file name")?)?;
+    stream.write_all(name.as_bytes())?;
+    write_i32(stream, wire_len(nro.len(), "the NRO")?)?;
+    check_response(stream)?;
+
+    send_compressed(stream, nro)?;
+    check_response(stream)?;
+
+    write_i32(stream, wire_len(args.len(), "the arguments")?)?;
+    stream.write_all(&args)?;
+    stream.flush()
+}
+
+/// Connects to the device at `addr` and uploads an NRO, see `send`.
+pub fn send_to(addr: SocketAddr, name: &str, nro: &[u8], args: &[String]) -> io::Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    send(&mut stream, name, nro, args)
+}
+
+/// Receives the stdout and stderr of an app started with `STDIO_ARG`.
+pub struct StdioServer {
+    listener: TcpListener,
+}
+
+impl StdioServer {
+    /// Listens on `CLIENT_PORT`. This should be done before the app is sent,
+    /// so it can't connect before the server is up.
+    pub fn new() -> io::Result<Self> {
+        StdioServer::bind((Ipv4Addr::new(0, 0, 0, 0), CLIENT_PORT))
+    }
+
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(StdioServer { listener: TcpListener::bind(addr)? })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for the app to connect, then copies its output to `out` until
+    /// it disconnects. Returns how many bytes it sent.
+    pub fn serve<W: Write>(&self, out: &mut W) -> io::Result<u64> {
+        let (mut stream, _) = self.listener.accept()?;
+        let mut buf = [0; 0x400];
+        let mut total = 0;
+        loop {
+            // Output is passed on as it arrives, so a live app's logs show
+            // up immediately.
+            let len = match stream.read(&mut buf) {
+                Ok(0) => return Ok(total),
+                Ok(len) => len,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                // The console doesn't always close the connection cleanly
+                // when the app exits.
+                Err(ref err) if err.kind() == io::ErrorKind::ConnectionReset => return Ok(total),
+                Err(err) => return Err(err),
+            };
+            out.write_all(&buf[..len])?;
+            out.flush()?;
+            total += len as u64;
+        }
+    }
+}
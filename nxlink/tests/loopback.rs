@@ -0,0 +1,171 @@
+//! Runs the host side against a fake device on the loopback interface.
+
+extern crate flate2;
+extern crate nxlink;
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use flate2::{Decompress, FlushDecompress, Status};
+
+use nxlink::{read_i32, write_i32, StdioServer, CHUNK_SIZE, PING, PONG};
+
+/// What the fake device received.
+#[derive(Debug)]
+struct Upload {
+    name: String,
+    data: Vec<u8>,
+    args: Vec<String>,
+}
+
+fn read_vec<R: Read>(r: &mut R, len: i32) -> Vec<u8> {
+    let mut buf = vec![0; len as usize];
+    r.read_exact(&mut buf).unwrap();
+    buf
+}
+
+/// Receives an upload like the netloader does, answering the header with
+/// `header_response`.
+fn receive(stream: &mut TcpStream, header_response: i32) -> Option<Upload> {
+    let len = read_i32(stream).unwrap();
+    let name = String::from_utf8(read_vec(stream, len)).unwrap();
+    let size = read_i32(stream).unwrap() as usize;
+    write_i32(stream, header_response).unwrap();
+    if header_response != 0 {
+        return None;
+    }
+
+    let mut inflate = Decompress::new(true);
+    let mut data = Vec::with_capacity(size + 1);
+    loop {
+        let len = read_i32(stream).unwrap();
+        assert!(len > 0 && len as usize <= CHUNK_SIZE, "bad frame length {}", len);
+        let frame = read_vec(stream, len);
+        let before = inflate.total_in();
+        let status = inflate.decompress_vec(&frame, &mut data, FlushDecompress::None).unwrap();
+        assert_eq!(inflate.total_in() - before, frame.len() as u64);
+        if status == Status::StreamEnd {
+            break;
+        }
+    }
+    assert_eq!(data.len(), size);
+    write_i32(stream, 0).unwrap();
+
+    let len = read_i32(stream).unwrap();
+    let args = read_vec(stream, len);
+    assert_eq!(args.last(), Some(&0));
+    let args = args[..args.len() - 1].split(|&b| b == 0)
+        .map(|arg| String::from_utf8(arg.to_vec()).unwrap())
+        .collect();
+    Some(Upload { name, data, args })
+}
+
+/// Answers one discovery ping, ignoring anything else sent first.
+fn fake_discovery() -> (SocketAddr, thread::JoinHandle<()>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let mut buf = [0; 16];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).unwrap();
+            if &buf[..len] == PING {
+                socket.send_to(b"garbage", from).unwrap();
+                socket.send_to(PONG, from).unwrap();
+                return;
+            }
+        }
+    });
+    (addr, handle)
+}
+
+/// Deterministic bytes that don't compress, so uploads span several frames.
+fn noise(len: usize) -> Vec<u8> {
+    let mut state = 0x1234_5678u32;
+    (0..len).map(|_| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (state >> 16) as u8
+    }).collect()
+}
+
+#[test]
+fn discovers_device() {
+    let (device, handle) = fake_discovery();
+    let host = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let found = nxlink::discover_with(&host, device, 10, Duration::from_millis(500)).unwrap();
+    assert_eq!(found, device.ip());
+    handle.join().unwrap();
+}
+
+#[test]
+fn discovery_times_out() {
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let host = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let err = nxlink::discover_with(&host, silent.local_addr().unwrap(), 2, Duration::from_millis(20)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn sends_nro_and_serves_stdio() {
+    let device = TcpListener::bind("127.0.0.1:0").unwrap();
+    let device_addr = device.local_addr().unwrap();
+    let stdio = StdioServer::bind("127.0.0.1:0").unwrap();
+    let stdio_addr = stdio.local_addr().unwrap();
+
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = device.accept().unwrap();
+        let upload = receive(&mut stream, 0).unwrap();
+        // The launched app redirects its output to the host.
+        let mut app = TcpStream::connect(stdio_addr).unwrap();
+        app.write_all(b"Hello from ").unwrap();
+        app.write_all(upload.name.as_bytes()).unwrap();
+        app.write_all(b"\n").unwrap();
+        upload
+    });
+
+    let nro = noise(3 * CHUNK_SIZE + 100);
+    let args = vec!["--verbose".to_owned(), "two words".to_owned(), nxlink::STDIO_ARG.to_owned()];
+    nxlink::send_to(device_addr, "hello.nro", &nro, &args).unwrap();
+    let mut output = Vec::new();
+    let len = stdio.serve(&mut output).unwrap();
+    assert_eq!(output, b"Hello from hello.nro\n");
+    assert_eq!(len, output.len() as u64);
+
+    let upload = handle.join().unwrap();
+    assert_eq!(upload.name, "hello.nro");
+    assert!(upload.data == nro);
+    assert_eq!(upload.args, ["hello.nro", "--verbose", "two words", "_NXLINK_"]);
+}
+
+#[test]
+fn sends_empty_and_compressible_files() {
+    for nro in &[Vec::new(), vec![0; 10 * CHUNK_SIZE]] {
+        let device = TcpListener::bind("127.0.0.1:0").unwrap();
+        let device_addr = device.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = device.accept().unwrap();
+            receive(&mut stream, 0).unwrap()
+        });
+        nxlink::send_to(device_addr, "/switch/app.nro", nro, &[]).unwrap();
+        let upload = handle.join().unwrap();
+        assert!(&upload.data == nro);
+        assert_eq!(upload.args, ["/switch/app.nro"]);
+    }
+}
+
+#[test]
+fn reports_device_errors() {
+    let device = TcpListener::bind("127.0.0.1:0").unwrap();
+    let device_addr = device.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = device.accept().unwrap();
+        receive(&mut stream, -2)
+    });
+    let err = nxlink::send_to(device_addr, "big.nro", &[1, 2, 3], &[]).unwrap_err();
+    assert!(err.to_string().contains("insufficient space"), "{}", err);
+    assert!(handle.join().unwrap().is_none());
+
+    let err = nxlink::send(&mut io::Cursor::new(Vec::new()), "", &[], &[]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}